@@ -0,0 +1,95 @@
+//! Terra Store v1.0 - Arena Storage
+//!
+//! Backing store for the package database's text arenas: an owned
+//! `String`, or (with the `mmap` feature) a view into a memory-mapped
+//! cache file that was validated as UTF-8 when it was mapped.
+
+use std::ops::Deref;
+
+#[cfg(feature = "mmap")]
+use std::{fs::File, path::Path, sync::Arc};
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+/// Text arena, readable as a `&str` either way
+#[derive(Debug)]
+pub enum Arena {
+    Owned(String),
+    /// Bytes `start..end` of a mapped file
+    #[cfg(feature = "mmap")]
+    Mapped {
+        map: Arc<Mmap>,
+        start: usize,
+        end: usize,
+    },
+}
+
+impl Arena {
+    /// Get owned, growable storage, copying a mapped arena first
+    pub fn to_mut(&mut self) -> &mut String {
+        #[cfg(feature = "mmap")]
+        if let Arena::Mapped { .. } = self {
+            *self = Arena::Owned(self.deref().to_string());
+        }
+
+        match self {
+            Arena::Owned(text) => text,
+            #[cfg(feature = "mmap")]
+            Arena::Mapped { .. } => unreachable!("mapped arena was just copied"),
+        }
+    }
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena::Owned(String::new())
+    }
+}
+
+impl Deref for Arena {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Arena::Owned(text) => text,
+            #[cfg(feature = "mmap")]
+            // SAFETY: `map_pair` checked this range is valid UTF-8
+            Arena::Mapped { map, start, end } => unsafe { std::str::from_utf8_unchecked(&map[*start..*end]) },
+        }
+    }
+}
+
+/// Map the file at `path` as two consecutive arenas of `first_len` and
+/// `second_len` bytes
+///
+/// Returns `None` if the file size doesn't match or either part isn't
+/// valid UTF-8.
+#[cfg(feature = "mmap")]
+pub fn map_pair(path: &Path, first_len: usize, second_len: usize) -> Option<(Arena, Arena)> {
+    let file = File::open(path).ok()?;
+    // SAFETY: the cache is only ever replaced by renaming a new file over
+    // it, never rewritten in place, so this mapping keeps seeing the
+    // bytes that are validated below
+    let map = unsafe { Mmap::map(&file) }.ok()?;
+
+    let total = first_len.checked_add(second_len)?;
+    if map.len() != total {
+        return None;
+    }
+    std::str::from_utf8(&map[..first_len]).ok()?;
+    std::str::from_utf8(&map[first_len..]).ok()?;
+
+    let map = Arc::new(map);
+    let first = Arena::Mapped {
+        map: Arc::clone(&map),
+        start: 0,
+        end: first_len,
+    };
+    let second = Arena::Mapped {
+        map,
+        start: first_len,
+        end: total,
+    };
+    Some((first, second))
+}
@@ -0,0 +1,161 @@
+//! Terra Store v1.0 - AUR RPC
+//!
+//! Looks up votes, popularity, maintainers and update times that only
+//! the AUR web API knows about. Requests need the `aur-rpc` feature;
+//! without it every lookup fails with `AurError::Disabled`.
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Whether this build can query the AUR RPC
+pub const ENABLED: bool = cfg!(feature = "aur-rpc");
+
+/// AUR RPC info endpoint
+#[cfg(feature = "aur-rpc")]
+const RPC_URL: &str = "https://aur.archlinux.org/rpc/?v=5&type=info";
+
+/// Raw PKGBUILD of a package's AUR git repository
+#[cfg(feature = "aur-rpc")]
+const PKGBUILD_URL: &str = "https://aur.archlinux.org/cgit/aur.git/plain/PKGBUILD?h=";
+
+/// How long a lookup may take before it's given up on
+#[cfg(feature = "aur-rpc")]
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum AurError {
+    #[cfg(not(feature = "aur-rpc"))]
+    #[error("Built without AUR RPC support")]
+    Disabled,
+
+    #[cfg(feature = "aur-rpc")]
+    #[error("AUR request failed: {0}")]
+    Request(String),
+
+    #[cfg(feature = "aur-rpc")]
+    #[error("Unexpected AUR response: {0}")]
+    Invalid(String),
+}
+
+/// What the AUR knows about a package
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AurInfo {
+    pub name: String,
+    pub num_votes: u32,
+    pub popularity: f64,
+    /// `None` for orphaned packages
+    pub maintainer: Option<String>,
+    /// Unix time of the last package update
+    pub last_modified: u64,
+    /// Unix time the package was flagged out of date, if it is
+    #[serde(default)]
+    pub out_of_date: Option<u64>,
+}
+
+/// Body of an RPC reply
+#[cfg(feature = "aur-rpc")]
+#[derive(Deserialize)]
+struct Reply {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    results: Vec<AurInfo>,
+}
+
+/// Parse an RPC info reply; names the AUR doesn't know are just absent
+#[cfg(feature = "aur-rpc")]
+fn parse_info(body: &str) -> Result<Vec<AurInfo>, AurError> {
+    let reply: Reply = serde_json::from_str(body).map_err(|e| AurError::Invalid(e.to_string()))?;
+    if reply.kind == "error" {
+        return Err(AurError::Invalid(reply.error.unwrap_or(reply.kind)));
+    }
+    Ok(reply.results)
+}
+
+/// Percent-encode a package name for a query string (`+` is common in
+/// names and would otherwise read as a space)
+#[cfg(feature = "aur-rpc")]
+fn encode(name: &str) -> String {
+    name.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => char::from(b).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Look up `names` in one request
+#[cfg(feature = "aur-rpc")]
+pub fn fetch_info(names: &[String]) -> Result<Vec<AurInfo>, AurError> {
+    let mut url = String::from(RPC_URL);
+    for name in names {
+        url.push_str("&arg[]=");
+        url.push_str(&encode(name));
+    }
+
+    parse_info(&get(&url)?)
+}
+
+/// Download a package's PKGBUILD from its AUR git repository
+#[cfg(feature = "aur-rpc")]
+pub fn fetch_pkgbuild(name: &str) -> Result<String, AurError> {
+    get(&format!("{}{}", PKGBUILD_URL, encode(name)))
+}
+
+/// Body of a GET request
+#[cfg(feature = "aur-rpc")]
+fn get(url: &str) -> Result<String, AurError> {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    agent
+        .get(url)
+        .call()
+        .map_err(|e| AurError::Request(e.to_string()))?
+        .into_string()
+        .map_err(|e| AurError::Request(e.to_string()))
+}
+
+/// Look up `names` in one request (needs the `aur-rpc` feature)
+#[cfg(not(feature = "aur-rpc"))]
+pub fn fetch_info(_names: &[String]) -> Result<Vec<AurInfo>, AurError> {
+    Err(AurError::Disabled)
+}
+
+/// Download a package's PKGBUILD (needs the `aur-rpc` feature)
+#[cfg(not(feature = "aur-rpc"))]
+pub fn fetch_pkgbuild(_name: &str) -> Result<String, AurError> {
+    Err(AurError::Disabled)
+}
+
+#[cfg(all(test, feature = "aur-rpc"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info() {
+        let body = r#"{"resultcount":2,"type":"multiinfo","version":5,"results":[
+            {"Name":"paru","NumVotes":812,"Popularity":21.5,"Maintainer":"Morganamilo","LastModified":1700000000,"Version":"2.0.3-1"},
+            {"Name":"old-tool","NumVotes":0,"Popularity":0,"Maintainer":null,"LastModified":1500000000,"OutOfDate":1600000000}
+        ]}"#;
+        let infos = parse_info(body).unwrap();
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[0].name, "paru");
+        assert_eq!(infos[0].num_votes, 812);
+        assert_eq!(infos[0].maintainer.as_deref(), Some("Morganamilo"));
+        assert_eq!(infos[1].maintainer, None);
+        assert_eq!(infos[0].out_of_date, None);
+        assert_eq!(infos[1].out_of_date, Some(1600000000));
+
+        let error = r#"{"resultcount":0,"type":"error","version":5,"results":[],"error":"Incorrect request type specified."}"#;
+        assert!(matches!(parse_info(error), Err(AurError::Invalid(message)) if message.starts_with("Incorrect")));
+        assert!(parse_info("<html>").is_err());
+    }
+
+    #[test]
+    fn test_encode_names() {
+        assert_eq!(encode("gtk+-git"), "gtk%2B-git");
+        assert_eq!(encode("python-foo_bar.1"), "python-foo_bar.1");
+    }
+}
@@ -1,17 +1,25 @@
 //! Terra Store v1.0 - Authentication Module
 //!
 //! The "Gatekeeper" - Handles sudo privilege management with a background
-//! keep-alive thread to prevent timeout during package browsing.
+//! keep-alive thread to prevent timeout during package browsing. Systems
+//! set up for polkit use pkexec instead, which authorizes each command
+//! through the polkit agent.
 
+use std::env;
+use std::ffi::OsString;
 use std::io::{self, Write};
 use std::process::{Command, Stdio};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{self, AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use serde::Deserialize;
 use thiserror::Error;
 
+/// Password prompts before giving up
+const MAX_PASSWORD_ATTEMPTS: u32 = 3;
+
 #[derive(Error, Debug)]
 pub enum AuthError {
     #[error("Authentication failed: incorrect password")]
@@ -24,12 +32,86 @@ pub enum AuthError {
     ProcessError(#[from] io::Error),
 
     #[error("Sudo not available on this system")]
-    #[allow(dead_code)]
     SudoNotFound,
+
+    #[error("sudo refused: {0}")]
+    Refused(String),
+}
+
+/// Program used to run package operations as root
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthBackend {
+    /// `sudo`, authenticated once up front and kept alive
+    #[default]
+    Sudo,
+    /// `pkexec`, which asks polkit on every command
+    Pkexec,
+}
+
+impl AuthBackend {
+    /// Use `forced` if set, otherwise sudo when installed and pkexec when
+    /// only that is
+    pub fn resolve(forced: Option<AuthBackend>) -> Self {
+        forced.unwrap_or_else(|| {
+            if !on_path("sudo") && on_path("pkexec") {
+                AuthBackend::Pkexec
+            } else {
+                AuthBackend::Sudo
+            }
+        })
+    }
+
+    /// Program name to prefix privileged commands with
+    pub fn program(self) -> &'static str {
+        match self {
+            AuthBackend::Sudo => "sudo",
+            AuthBackend::Pkexec => "pkexec",
+        }
+    }
+}
+
+/// Tell a wrong password (retryable) from sudo refusing outright, e.g.
+/// because the user is not in the sudoers file
+fn classify_failure(stderr: &str) -> AuthError {
+    if stderr.contains("try again") || stderr.contains("incorrect password") {
+        return AuthError::InvalidPassword;
+    }
+
+    let reason = stderr.lines().map(str::trim).rfind(|line| !line.is_empty());
+    AuthError::Refused(reason.unwrap_or("sudo -v failed").to_string())
+}
+
+/// Overwrite a password's bytes before freeing it, shortening the time it
+/// sits in memory
+fn wipe(password: String) {
+    let mut bytes = password.into_bytes();
+    for byte in bytes.iter_mut() {
+        // SAFETY: `byte` is a valid, exclusive reference into `bytes`; the
+        // volatile write stops the compiler dropping this dead store
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+/// Check whether `program` is an executable file somewhere on `PATH`
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// Command running `sudo`, the program followed by its leading arguments
+fn sudo_command(sudo: &[OsString]) -> Command {
+    let mut command = Command::new(&sudo[0]);
+    command.args(&sudo[1..]);
+    command
 }
 
 /// Authentication manager that handles sudo privileges
 pub struct AuthManager {
+    /// Backend privileged commands run through
+    backend: AuthBackend,
+    /// sudo program and any leading arguments (a stub script in tests)
+    sudo: Vec<OsString>,
     /// Flag to signal the keep-alive thread to stop
     running: Arc<AtomicBool>,
     /// Handle to the keep-alive thread
@@ -38,16 +120,18 @@ pub struct AuthManager {
 
 impl AuthManager {
     /// Create a new AuthManager (does not authenticate yet)
-    pub fn new() -> Self {
+    pub fn new(backend: AuthBackend) -> Self {
         Self {
+            backend,
+            sudo: vec![OsString::from("sudo")],
             running: Arc::new(AtomicBool::new(false)),
             keepalive_handle: None,
         }
     }
 
     /// Check if we currently have sudo privileges (without prompting)
-    pub fn has_privileges() -> bool {
-        Command::new("sudo")
+    pub fn has_privileges(&self) -> bool {
+        sudo_command(&self.sudo)
             .args(["-n", "true"])
             .stdout(Stdio::null())
             .stderr(Stdio::null())
@@ -56,47 +140,90 @@ impl AuthManager {
             .unwrap_or(false)
     }
 
+    /// Whether privileged commands can run without `authenticate` first
+    ///
+    /// For sudo that means the keep-alive is running; pkexec never needs
+    /// an up-front prompt.
+    pub fn is_authenticated(&self) -> bool {
+        self.backend == AuthBackend::Pkexec || self.running.load(Ordering::SeqCst)
+    }
+
     /// Authenticate with sudo, prompting for password if needed
     ///
     /// Returns Ok(()) if authentication succeeds, or an AuthError otherwise.
     pub fn authenticate(&mut self) -> Result<(), AuthError> {
+        // pkexec authorizes each command itself, so there is nothing to
+        // cache or keep alive
+        if self.backend == AuthBackend::Pkexec {
+            return Ok(());
+        }
+
         // Check if we already have privileges
-        if Self::has_privileges() {
+        if self.has_privileges() {
             self.spawn_keepalive();
             return Ok(());
         }
 
-        // Prompt for password securely
-        print!(":: Administrative privileges required.\n");
-        print!("   Password: ");
-        io::stdout().flush()?;
+        println!(":: Administrative privileges required.");
+
+        let mut attempt = 1;
+        loop {
+            // Prompt for password securely
+            print!("   Password: ");
+            io::stdout().flush()?;
+
+            let password = rpassword::read_password().map_err(|_| AuthError::Cancelled)?;
 
-        let password = rpassword::read_password().map_err(|_| AuthError::Cancelled)?;
+            if password.is_empty() {
+                return Err(AuthError::Cancelled);
+            }
+
+            let result = self.validate_password(&password);
+            wipe(password);
 
-        if password.is_empty() {
-            return Err(AuthError::Cancelled);
+            match result {
+                Ok(()) => {
+                    println!("   ✓ Authentication successful\n");
+                    self.spawn_keepalive();
+                    return Ok(());
+                }
+                Err(AuthError::InvalidPassword) if attempt < MAX_PASSWORD_ATTEMPTS => {
+                    println!("   ✗ Sorry, try again.");
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
         }
+    }
 
-        // Validate the password with sudo -S -v
-        let mut child = Command::new("sudo")
-            .args(["-S", "-v"])
+    /// Check a password with `sudo -S -v`, caching the credentials on
+    /// success
+    fn validate_password(&self, password: &str) -> Result<(), AuthError> {
+        // An empty prompt keeps sudo's stderr down to its verdict
+        let mut child = match sudo_command(&self.sudo)
+            .args(["-S", "-p", "", "-v"])
             .stdin(Stdio::piped())
             .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()?;
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(AuthError::SudoNotFound),
+            child => child?,
+        };
 
+        // Close stdin straight after the password, so sudo sees EOF rather
+        // than waiting for a retry. A failed write means sudo already
+        // exited, which its status reports below
         if let Some(mut stdin) = child.stdin.take() {
-            writeln!(stdin, "{}", password)?;
+            let _ = stdin.write_all(password.as_bytes()).and_then(|()| stdin.write_all(b"\n"));
+            drop(stdin);
         }
 
-        let status = child.wait()?;
-
-        if status.success() {
-            println!("   ✓ Authentication successful\n");
-            self.spawn_keepalive();
+        let output = child.wait_with_output()?;
+        if output.status.success() {
             Ok(())
         } else {
-            Err(AuthError::InvalidPassword)
+            Err(classify_failure(&String::from_utf8_lossy(&output.stderr)))
         }
     }
 
@@ -111,12 +238,16 @@ impl AuthManager {
 
         self.running.store(true, Ordering::SeqCst);
         let running = Arc::clone(&self.running);
+        let sudo = self.sudo.clone();
 
         let handle = thread::spawn(move || {
             while running.load(Ordering::SeqCst) {
-                // Refresh sudo timestamp
-                let _ = Command::new("sudo")
+                // Refresh sudo timestamp. Never prompts (-n) and never
+                // touches the terminal, so it can't fight an interactive
+                // pacman or sudo prompt for input
+                let _ = sudo_command(&sudo)
                     .args(["-n", "-v"])
+                    .stdin(Stdio::null())
                     .stdout(Stdio::null())
                     .stderr(Stdio::null())
                     .status();
@@ -147,7 +278,7 @@ impl AuthManager {
 
 impl Default for AuthManager {
     fn default() -> Self {
-        Self::new()
+        Self::new(AuthBackend::default())
     }
 }
 
@@ -163,7 +294,47 @@ mod tests {
 
     #[test]
     fn test_auth_manager_creation() {
-        let manager = AuthManager::new();
+        let manager = AuthManager::new(AuthBackend::Sudo);
         assert!(!manager.running.load(Ordering::SeqCst));
+        assert!(!manager.is_authenticated());
+    }
+
+    #[test]
+    fn test_pkexec_needs_no_keepalive() {
+        assert_eq!(AuthBackend::resolve(Some(AuthBackend::Pkexec)), AuthBackend::Pkexec);
+
+        let mut manager = AuthManager::new(AuthBackend::Pkexec);
+        assert!(manager.is_authenticated());
+        assert!(manager.authenticate().is_ok());
+        assert!(manager.keepalive_handle.is_none());
+    }
+
+    #[test]
+    fn test_validate_password_with_stub_sudo() {
+        // Accepts "hunter2", refuses "locked" outright, rejects the rest
+        let stub = env::temp_dir().join(format!("terra-sudo-{}", std::process::id()));
+        std::fs::write(
+            &stub,
+            "#!/bin/sh\nread -r password\n\
+             [ \"$password\" = hunter2 ] && exit 0\n\
+             [ \"$password\" = locked ] && { echo 'user is not in the sudoers file.' >&2; exit 1; }\n\
+             echo 'Sorry, try again.' >&2\nexit 1\n",
+        )
+        .unwrap();
+
+        // Run through sh, since exec'ing a just-written script can fail
+        // with ETXTBSY while another test thread forks
+        let mut manager = AuthManager::new(AuthBackend::Sudo);
+        manager.sudo = vec![OsString::from("sh"), stub.clone().into_os_string()];
+        assert!(manager.validate_password("hunter2").is_ok());
+        assert!(matches!(manager.validate_password("wrong"), Err(AuthError::InvalidPassword)));
+        assert!(matches!(
+            manager.validate_password("locked"),
+            Err(AuthError::Refused(reason)) if reason == "user is not in the sudoers file."
+        ));
+
+        manager.sudo = vec![env::temp_dir().join("terra-no-such-sudo").into_os_string()];
+        assert!(matches!(manager.validate_password("hunter2"), Err(AuthError::SudoNotFound)));
+        std::fs::remove_file(&stub).unwrap();
     }
 }
@@ -0,0 +1,440 @@
+//! Terra Store v1.0 - Command Line
+//!
+//! Non-interactive subcommands that run without the TUI, so the index
+//! can be used from shell pipelines and dmenu-style launchers.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use serde::Serialize;
+
+use crate::config::{describe_error, Config};
+use crate::database::{write_atomically, PackageDatabase};
+use crate::history::{History, HistoryFormat};
+use crate::package::PackageSource;
+use crate::repos::init_aur_helper;
+
+/// Printed by `--help`
+pub const USAGE: &str = "\
+Usage:
+  terra-store                    Launch the TUI
+  terra-store search <query>...  Print matching packages and exit
+  terra-store history export     Print the install history
+  terra-store history import <file>
+                                 Merge an exported history into this one
+  terra-store audit              Compare installed packages with the TerraFlow
+                                 lists; exits 1 if any are missing
+
+Search options:
+      --source <official|aur>    Only match one source
+      --json                     Print a JSON array instead of one name per line
+  -d, --descriptions             Also print descriptions
+      --limit <n>                Print at most n results
+
+Audit options:
+      --json                     Print the result as JSON
+
+History options:
+      --format <json|csv>        File format (import guesses it from the extension)
+  -o, --output <file>            Write the export to a file instead of stdout
+
+Options:
+  -h, --help                     Print this help
+  -V, --version                  Print the version";
+
+/// What to run, as chosen by the command line
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Launch the interactive TUI
+    Tui,
+    /// `--help`: print `USAGE`
+    Help,
+    /// `--version`: print the version
+    Version,
+    /// `terra-store search <query>`
+    Search(SearchArgs),
+    /// `terra-store history export`
+    HistoryExport {
+        format: HistoryFormat,
+        output: Option<PathBuf>,
+    },
+    /// `terra-store history import <file>`
+    HistoryImport {
+        path: PathBuf,
+        format: Option<HistoryFormat>,
+    },
+    /// `terra-store audit`
+    Audit {
+        /// Print the result as JSON
+        json: bool,
+    },
+}
+
+/// Options for the `search` subcommand
+#[derive(Debug, PartialEq, Eq)]
+pub struct SearchArgs {
+    /// Query terms, joined with spaces
+    pub query: String,
+    /// Only match this source (`--source official|aur`)
+    pub source: Option<PackageSource>,
+    /// Print a JSON array instead of one name per line
+    pub json: bool,
+    /// Also print descriptions
+    pub descriptions: bool,
+    /// Most results to print
+    pub limit: usize,
+}
+
+/// One search result as printed by `--json`
+#[derive(Serialize)]
+struct SearchHit<'a> {
+    name: &'a str,
+    source: PackageSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+}
+
+/// Parse the arguments after the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        None => Ok(Command::Tui),
+        Some("-h" | "--help") => Ok(Command::Help),
+        Some("-V" | "--version") => Ok(Command::Version),
+        Some("search") => parse_search(args),
+        Some("history") => parse_history(args),
+        Some("audit") => parse_audit(args),
+        Some(flag) if flag.starts_with('-') => Err(format!("unknown option '{}'", flag)),
+        Some(other) => Err(format!("unknown command '{}'", other)),
+    }
+}
+
+fn parse_search(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut terms = Vec::new();
+    let mut search = SearchArgs {
+        query: String::new(),
+        source: None,
+        json: false,
+        descriptions: false,
+        limit: usize::MAX,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--json" => search.json = true,
+            "-d" | "--descriptions" => search.descriptions = true,
+            "--source" => {
+                let value = args.next().ok_or("--source needs a value")?;
+                search.source = Some(parse_source(&value)?);
+            }
+            "--limit" => {
+                let value = args.next().ok_or("--limit needs a value")?;
+                search.limit = value.parse().map_err(|_| format!("invalid limit '{}'", value))?;
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown option '{}'", flag)),
+            term => terms.push(term.to_string()),
+        }
+    }
+
+    if terms.is_empty() {
+        return Err("search needs a query".to_string());
+    }
+    search.query = terms.join(" ");
+    Ok(Command::Search(search))
+}
+
+fn parse_history(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let action = args.next().ok_or("history needs 'export' or 'import'")?;
+    let mut format = None;
+    let mut output = None;
+    let mut paths = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--format" => {
+                let value = args.next().ok_or("--format needs a value")?;
+                let parsed = HistoryFormat::from_name(&value)
+                    .ok_or_else(|| format!("unknown format '{}' (expected json or csv)", value))?;
+                format = Some(parsed);
+            }
+            "-o" | "--output" => output = Some(PathBuf::from(args.next().ok_or("--output needs a value")?)),
+            flag if flag.starts_with('-') => return Err(format!("unknown option '{}'", flag)),
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+
+    match action.as_str() {
+        "export" if paths.is_empty() => Ok(Command::HistoryExport {
+            format: format.unwrap_or(HistoryFormat::Json),
+            output,
+        }),
+        "export" => Err("history export takes no file (use --output)".to_string()),
+        "import" if output.is_some() => Err("--output only applies to history export".to_string()),
+        "import" => match <[PathBuf; 1]>::try_from(paths) {
+            Ok([path]) => Ok(Command::HistoryImport { path, format }),
+            Err(_) => Err("history import needs one file".to_string()),
+        },
+        other => Err(format!("unknown history command '{}'", other)),
+    }
+}
+
+fn parse_audit(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--json" => json = true,
+            other => return Err(format!("unknown audit option '{}'", other)),
+        }
+    }
+    Ok(Command::Audit { json })
+}
+
+/// Parse a `--source` value
+fn parse_source(value: &str) -> Result<PackageSource, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "official" | "repo" => Ok(PackageSource::Official),
+        "aur" => Ok(PackageSource::Aur),
+        _ => Err(format!("unknown source '{}' (expected official or aur)", value)),
+    }
+}
+
+/// Load config.toml, warning (and using the defaults) if it doesn't parse
+fn load_config() -> Config {
+    Config::load().unwrap_or_else(|e| {
+        eprintln!("terra-store: config.toml: {}", describe_error(&e));
+        Config::default()
+    })
+}
+
+/// Run `search` against the cached database and print the matches
+///
+/// Exits with 0 when something matched and 1 when nothing did.
+pub fn run_search(args: &SearchArgs) -> ExitCode {
+    // A rebuild lists AUR packages through the configured helper
+    if let Some(warning) = init_aur_helper(&load_config()) {
+        eprintln!("terra-store: {}", warning);
+    }
+    let database = PackageDatabase::load_or_build();
+    let results = database.search_counted(&args.query, args.source, args.limit);
+
+    let hits: Vec<SearchHit> = results
+        .indices
+        .iter()
+        .filter_map(|&idx| {
+            Some(SearchHit {
+                name: database.get_name(idx)?,
+                source: database.get_source(idx)?,
+                description: if args.descriptions { database.get_description(idx) } else { None },
+            })
+        })
+        .collect();
+
+    let mut out = io::stdout().lock();
+    let written = if args.json {
+        serde_json::to_writer(&mut out, &hits)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(out))
+    } else {
+        hits.iter().try_for_each(|hit| match hit.description {
+            Some(description) => writeln!(out, "{}\t{}", hit.name, description),
+            None => writeln!(out, "{}", hit.name),
+        })
+    };
+
+    // A closed pipe (e.g. `| head`) is not an error worth reporting
+    if let Err(e) = written {
+        if e.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("terra-store: {}", e);
+            return ExitCode::from(2);
+        }
+    }
+
+    if hits.is_empty() {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Write the install history to `output` (stdout if `None`)
+pub fn run_history_export(format: HistoryFormat, output: Option<&Path>) -> ExitCode {
+    let history = History::load();
+    if let Some(error) = &history.load_error {
+        eprintln!("terra-store: {}", error);
+    }
+    // A file is only replaced once the whole export is written, and
+    // both are flushed here so a failed write is reported
+    let written = match output {
+        Some(path) => write_atomically(path, |writer| history.export(format, writer)),
+        None => {
+            let mut stdout = io::stdout().lock();
+            history.export(format, &mut stdout).and_then(|()| stdout.flush())
+        }
+    };
+
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("terra-store: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Merge the history exported to `path` into the install history
+pub fn run_history_import(path: &Path, format: Option<HistoryFormat>) -> ExitCode {
+    let format = format.unwrap_or_else(|| HistoryFormat::from_path(path));
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("terra-store: {}: {}", path.display(), e);
+            return ExitCode::from(2);
+        }
+    };
+    let Some((records, skipped)) = History::parse_export(&contents, format) else {
+        eprintln!("terra-store: {} is not an exported history", path.display());
+        return ExitCode::from(2);
+    };
+
+    let mut history = History::load().with_limit(load_config().history_limit);
+    if let Some(error) = &history.load_error {
+        eprintln!("terra-store: {}", error);
+    }
+    let total = records.len();
+    let added = history.merge(records);
+    if let Err(e) = history.save() {
+        eprintln!("terra-store: {}", e);
+        return ExitCode::from(2);
+    }
+
+    println!("Imported {} of {} records ({} already present)", added, total, total - added);
+    if skipped > 0 {
+        eprintln!("terra-store: skipped {} unreadable records", skipped);
+    }
+    ExitCode::SUCCESS
+}
+
+/// Audit installed packages against the TerraFlow lists
+///
+/// Exits 1 if any listed package is missing, 2 if TerraFlow isn't set up.
+#[cfg(feature = "terraflow")]
+pub fn run_audit(json: bool) -> ExitCode {
+    let Some(terraflow) = crate::terraflow::TerraFlow::from_config(&load_config()) else {
+        eprintln!("terra-store: no TerraFlow packages directory found");
+        return ExitCode::from(2);
+    };
+    let result = terraflow.audit();
+
+    let mut out = io::stdout().lock();
+    let written = if json {
+        serde_json::to_writer(&mut out, &result)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(out))
+    } else {
+        result
+            .missing
+            .iter()
+            .try_for_each(|entry| writeln!(out, "{} [{}] ({})", entry.name, entry.source, entry.file))
+            .and_then(|()| {
+                writeln!(
+                    out,
+                    "{} missing, {} extra ({} listed, {} installed)",
+                    result.missing.len(),
+                    result.extra.len(),
+                    result.config_count,
+                    result.installed_count
+                )
+            })
+    };
+
+    if let Err(e) = written {
+        if e.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("terra-store: {}", e);
+            return ExitCode::from(2);
+        }
+    }
+
+    if result.missing.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    }
+}
+
+/// TerraFlow support is compiled out
+#[cfg(not(feature = "terraflow"))]
+pub fn run_audit(_json: bool) -> ExitCode {
+    eprintln!("terra-store: built without TerraFlow support");
+    ExitCode::from(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_search() {
+        assert_eq!(parse(args(&[])), Ok(Command::Tui));
+
+        let Ok(Command::Search(search)) = parse(args(&["search", "neo", "vim", "--source", "aur", "--json"])) else {
+            panic!("expected a search command");
+        };
+        assert_eq!(search.query, "neo vim");
+        assert_eq!(search.source, Some(PackageSource::Aur));
+        assert!(search.json);
+        assert!(!search.descriptions);
+
+        assert!(parse(args(&["search"])).is_err());
+        assert!(parse(args(&["search", "htop", "--source", "flatpak"])).is_err());
+        assert!(parse(args(&["frobnicate"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        assert_eq!(parse(args(&["--version"])), Ok(Command::Version));
+        assert_eq!(parse(args(&["-h"])), Ok(Command::Help));
+        assert_eq!(parse(args(&["search", "--help"])), Ok(Command::Help));
+        assert_eq!(parse(args(&["--verbose"])), Err("unknown option '--verbose'".to_string()));
+    }
+
+    #[test]
+    fn test_parse_history() {
+        assert_eq!(
+            parse(args(&["history", "export", "--format", "csv"])),
+            Ok(Command::HistoryExport {
+                format: HistoryFormat::Csv,
+                output: None,
+            })
+        );
+        assert_eq!(
+            parse(args(&["history", "import", "backup.json"])),
+            Ok(Command::HistoryImport {
+                path: PathBuf::from("backup.json"),
+                format: None,
+            })
+        );
+
+        assert!(parse(args(&["history"])).is_err());
+        assert!(parse(args(&["history", "import"])).is_err());
+        assert!(parse(args(&["history", "export", "--format", "xml"])).is_err());
+        assert!(parse(args(&["history", "import", "a.csv", "-o", "b.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_audit() {
+        assert_eq!(parse(args(&["audit"])), Ok(Command::Audit { json: false }));
+        assert_eq!(parse(args(&["audit", "--json"])), Ok(Command::Audit { json: true }));
+        assert_eq!(parse(args(&["audit", "-h"])), Ok(Command::Help));
+        assert!(parse(args(&["audit", "--all"])).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+//! Terra Store v1.0 - Clipboard
+//!
+//! Copies text to the system clipboard through whichever clipboard tool
+//! the session has: `wl-copy` under Wayland, `xclip` or `xsel` under X11.
+
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ClipboardError {
+    #[error("No clipboard available (install wl-clipboard, xclip or xsel)")]
+    Unavailable,
+
+    #[error("{0} failed")]
+    Failed(&'static str),
+}
+
+/// Clipboard tools to try, in order, with the arguments that make them
+/// read the clipboard contents from stdin
+fn candidates(wayland: bool, x11: bool) -> Vec<(&'static str, &'static [&'static str])> {
+    let mut tools = Vec::new();
+    if wayland {
+        tools.push(("wl-copy", &[][..]));
+    }
+    if x11 {
+        tools.push(("xclip", &["-selection", "clipboard"][..]));
+        tools.push(("xsel", &["--clipboard", "--input"][..]));
+    }
+    tools
+}
+
+/// Copy `text` to the clipboard, returning the tool that did it
+pub fn copy(text: &str) -> Result<&'static str, ClipboardError> {
+    let set = |var| env::var_os(var).is_some_and(|value| !value.is_empty());
+    for (tool, args) in candidates(set("WAYLAND_DISPLAY"), set("DISPLAY")) {
+        match run(tool, args, text) {
+            Ok(true) => return Ok(tool),
+            Ok(false) => return Err(ClipboardError::Failed(tool)),
+            // Not installed, try the next one
+            Err(_) => continue,
+        }
+    }
+    Err(ClipboardError::Unavailable)
+}
+
+/// Pipe `text` into `tool`, returning whether it exited successfully
+///
+/// Output goes nowhere: xclip and wl-copy stay in the background to serve
+/// the selection, and would keep a captured stdout open.
+fn run(tool: &str, args: &[&str], text: &str) -> io::Result<bool> {
+    let mut child = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    Ok(child.wait()?.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates_follow_session() {
+        let names = |wayland, x11| -> Vec<&str> {
+            candidates(wayland, x11).into_iter().map(|(tool, _)| tool).collect()
+        };
+        assert_eq!(names(true, true), vec!["wl-copy", "xclip", "xsel"]);
+        assert_eq!(names(false, true), vec!["xclip", "xsel"]);
+        assert!(names(false, false).is_empty());
+    }
+}
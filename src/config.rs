@@ -0,0 +1,211 @@
+//! Terra Store v1.0 - User Configuration
+//!
+//! Loads user settings from ~/.config/terra-store/config.toml.
+//! Every field has a default, so a missing or partial file is fine.
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::auth::AuthBackend;
+use crate::theme::ThemeOverrides;
+use crate::ui::SourceFilter;
+
+/// What Enter does on a package that is already installed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum InstalledAction {
+    /// Open the action menu (Reinstall / Details)
+    #[default]
+    Menu,
+    /// Reinstall straight away, like any other package
+    Install,
+}
+
+/// When Search mode runs a search
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    /// Search on every keystroke
+    #[default]
+    Instant,
+    /// Search only when Enter is pressed
+    OnEnter,
+}
+
+/// User configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Behavior of Enter on an already-installed package
+    pub enter_on_installed: InstalledAction,
+    /// Whether Flatpak search also matches app summaries
+    pub flatpak_search_summary: bool,
+    /// Fold `-git`/`-svn`/... variants into their release package in results
+    pub collapse_vcs: bool,
+    /// Search as you type, or only on Enter
+    pub search_mode: SearchMode,
+    /// Up on the first entry goes to the last, and Down on the last to the first
+    pub wrap_navigation: bool,
+    /// Recently installed packages listed while the query is empty
+    pub recent_installs: usize,
+    /// Highlight the part of each result name the query matched
+    pub highlight_matches: bool,
+    /// Most search results to display
+    pub max_results: usize,
+    /// Most history records to keep
+    pub history_limit: usize,
+    /// Source filter on the first start (later starts restore the last one)
+    pub default_source: SourceFilter,
+    /// Also match package descriptions, ranked after name matches
+    pub search_descriptions: bool,
+    /// Most packages a single batch may queue
+    pub max_batch: usize,
+    /// Batches of at least this many packages ask for confirmation first
+    pub batch_confirm_threshold: usize,
+    /// Ask before installing a single package
+    pub confirm_install: bool,
+    /// Also upgrade AUR packages during a system upgrade
+    pub upgrade_aur: bool,
+    /// AUR helper to use instead of detecting paru or yay
+    pub aur_helper: Option<String>,
+    /// Flatpak remote for apps whose remote isn't known
+    pub flatpak_remote: String,
+    /// TerraFlow packages directory, checked before `TERRAFLOW_DIR` and
+    /// the usual dotfiles locations
+    pub terraflow_dir: Option<PathBuf>,
+    /// Extensions of TerraFlow package list files (`""` for files without
+    /// one); `.toml` lists are always read
+    pub terraflow_extensions: Vec<String>,
+    /// Force `sudo` or `pkexec` for privileged commands (auto-detected
+    /// when unset)
+    pub auth_backend: Option<AuthBackend>,
+    /// Custom colors (`[theme]` table)
+    pub theme: ThemeOverrides,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enter_on_installed: InstalledAction::default(),
+            flatpak_search_summary: false,
+            collapse_vcs: false,
+            search_mode: SearchMode::default(),
+            wrap_navigation: false,
+            highlight_matches: true,
+            recent_installs: 10,
+            max_results: 500,
+            history_limit: 500,
+            default_source: SourceFilter::All,
+            search_descriptions: false,
+            max_batch: 50,
+            batch_confirm_threshold: 2,
+            confirm_install: true,
+            upgrade_aur: true,
+            aur_helper: None,
+            flatpak_remote: "flathub".to_string(),
+            terraflow_dir: None,
+            terraflow_extensions: vec!["txt".to_string()],
+            auth_backend: None,
+            theme: ThemeOverrides::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Get the config file path
+    fn path() -> Option<PathBuf> {
+        let config_dir = dirs::config_dir()?;
+        Some(config_dir.join("terra-store").join("config.toml"))
+    }
+
+    /// Load config from disk; a missing file gives the defaults
+    ///
+    /// A file that doesn't parse is an error, which callers report
+    /// before falling back to the defaults.
+    pub fn load() -> Result<Self, toml::de::Error> {
+        match Self::path().and_then(|path| fs::read_to_string(path).ok()) {
+            Some(contents) => Self::parse(&contents),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Parse config from TOML text (unknown keys are ignored)
+    pub fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+}
+
+/// One-line summary of a config.toml error, for the status line
+pub fn describe_error(error: &toml::de::Error) -> String {
+    let text = error.to_string();
+    match text.lines().next().and_then(|line| line.strip_prefix("TOML parse error at ")) {
+        Some(location) => format!("{}: {}", location, error.message()),
+        None => error.message().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sample_config() {
+        let config = Config::parse(
+            r##"
+            max_results = 200
+            history_limit = 50
+            default_source = "aur"
+            confirm_install = false
+            aur_helper = "yay"
+            some_future_option = true
+
+            [theme]
+            bg = "#1d2021"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(config.max_results, 200);
+        assert_eq!(config.history_limit, 50);
+        assert_eq!(config.default_source, SourceFilter::Aur);
+        assert!(!config.confirm_install);
+        assert_eq!(config.aur_helper.as_deref(), Some("yay"));
+        assert_eq!(config.theme.bg.as_deref(), Some("#1d2021"));
+        assert!(!config.theme.override_pywal);
+        // Unset keys keep their defaults
+        assert_eq!(config.max_batch, 50);
+    }
+
+    #[test]
+    fn test_parse_enter_on_installed() {
+        let config = Config::parse("enter_on_installed = \"install\"").unwrap();
+        assert_eq!(config.enter_on_installed, InstalledAction::Install);
+
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.enter_on_installed, InstalledAction::Menu);
+        assert!(!config.flatpak_search_summary);
+        assert_eq!(config.search_mode, SearchMode::Instant);
+        assert!(!config.wrap_navigation);
+        assert!(config.highlight_matches);
+
+        let config = Config::parse("search_mode = \"on_enter\"").unwrap();
+        assert_eq!(config.search_mode, SearchMode::OnEnter);
+        assert_eq!(config.max_batch, 50);
+        assert_eq!(config.auth_backend, None);
+
+        let config = Config::parse("auth_backend = \"pkexec\"").unwrap();
+        assert_eq!(config.auth_backend, Some(AuthBackend::Pkexec));
+    }
+
+    #[test]
+    fn test_invalid_value_is_reported() {
+        let error = Config::parse("confirm_install = false\nmax_results = \"500\"").unwrap_err();
+        let message = describe_error(&error);
+        assert!(message.starts_with("line 2"), "{}", message);
+        assert!(message.contains("expected"), "{}", message);
+        assert!(!message.contains('\n'));
+
+        assert!(Config::parse("search_mode = \"sometimes\"").is_err());
+    }
+}
@@ -3,18 +3,40 @@
 //! Arena-based memory architecture for instant package search.
 //! Uses monolithic storage + lightweight index pointers for zero-CPU search.
 
+use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter, Write};
-use std::path::PathBuf;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use serde::{Deserialize, Serialize};
 
+use crate::arena::Arena;
 use crate::package::PackageSource;
+use crate::paths;
+use crate::repos::{aur_helper, parse_pacman_search_output};
 
 /// Cache file version - increment when format changes
-const CACHE_VERSION: u32 = 1;
+const CACHE_VERSION: u32 = 5;
+
+/// pacman's sync databases; a cache older than these is rebuilt
+const PACMAN_SYNC_DIR: &str = "/var/lib/pacman/sync";
+
+/// How many packages a search scans between cancellation checks
+const CANCEL_CHECK_INTERVAL: usize = 4096;
+
+/// Prefix that makes a query a regular expression (`/^lib.*-dev$`)
+pub const REGEX_PREFIX: char = '/';
+
+/// Suffixes marking AUR packages built from version control
+const VCS_SUFFIXES: [&str; 6] = ["-git", "-svn", "-hg", "-bzr", "-cvs", "-darcs"];
 
 /// Lightweight view into the arena - just byte offsets
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,15 +45,54 @@ pub struct PackageView {
     pub name_start: usize,
     /// End byte offset in arena for package name
     pub name_end: usize,
+    /// Start byte offset in the description arena
+    pub desc_start: usize,
+    /// End byte offset in the description arena
+    pub desc_end: usize,
     /// Package source (Official or AUR)
     pub source: PackageSource,
 }
 
 impl PackageView {
-    /// Get the package name as a string slice from the arena
+    /// Get the package name, or `None` if the offsets don't fall on valid
+    /// character boundaries inside the arena (e.g. a corrupted cache)
+    #[inline]
+    pub fn try_name<'a>(&self, arena: &'a str) -> Option<&'a str> {
+        arena.get(self.name_start..self.name_end)
+    }
+
+    /// Get the package description, or `None` if the offsets are invalid
     #[inline]
-    pub fn name<'a>(&self, arena: &'a str) -> &'a str {
-        &arena[self.name_start..self.name_end]
+    pub fn try_description<'a>(&self, desc_arena: &'a str) -> Option<&'a str> {
+        desc_arena.get(self.desc_start..self.desc_end)
+    }
+}
+
+/// Parameters of a package search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Only return packages from this source
+    pub source_filter: Option<PackageSource>,
+    /// Maximum number of results
+    pub limit: usize,
+    /// Also match descriptions, ranked after name matches
+    pub descriptions: bool,
+}
+
+/// Ranked search results plus how many matched before the limit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchResults {
+    /// Matching indices, best first, at most `limit` of them
+    pub indices: Vec<usize>,
+    /// Total number of matches, including those cut by the limit
+    pub total: usize,
+}
+
+impl SearchResults {
+    /// Check whether the limit cut off some matches
+    #[cfg(test)]
+    pub fn is_truncated(&self) -> bool {
+        self.total > self.indices.len()
     }
 }
 
@@ -42,7 +103,12 @@ struct CacheHeader {
     official_count: usize,
     aur_count: usize,
     arena_len: usize,
+    desc_arena_len: usize,
     timestamp: u64,
+    /// Newest mtime in the pacman sync dir when the cache was built
+    sync_mtime: u64,
+    /// Arenas live raw in `arenas.bin` (for mapping) instead of in here
+    mapped_arenas: bool,
 }
 
 /// The "Zero-Stress" Package Database
@@ -54,9 +120,13 @@ struct CacheHeader {
 #[derive(Debug)]
 pub struct PackageDatabase {
     /// The Monolith - all package names concatenated with newlines
-    arena: String,
+    arena: Arena,
+    /// Package descriptions, laid out the same way as the names
+    desc_arena: Arena,
     /// The Index - lightweight views into the arena
     packages: Vec<PackageView>,
+    /// Sync database mtime the packages were listed from (0 if unknown)
+    sync_mtime: u64,
     /// Statistics
     pub stats: DatabaseStats,
 }
@@ -68,28 +138,94 @@ pub struct DatabaseStats {
     pub arena_bytes: usize,
     pub load_time_ms: u64,
     pub was_cached: bool,
+    /// Time spent listing official packages (fresh builds only)
+    pub official_ms: u64,
+    /// Time spent listing AUR packages (fresh builds only)
+    pub aur_ms: u64,
 }
 
 impl PackageDatabase {
     /// Create an empty database
     pub fn new() -> Self {
         Self {
-            arena: String::new(),
+            arena: Arena::default(),
+            desc_arena: Arena::default(),
             packages: Vec::new(),
+            sync_mtime: 0,
             stats: DatabaseStats::default(),
         }
     }
 
+    /// Build a database from a list of names (all from one source)
+    #[cfg(test)]
+    pub fn from_names<S: AsRef<str>>(names: &[S], source: PackageSource) -> Self {
+        let mut db = Self::new();
+        db.append_names(names, source);
+        db
+    }
+
+    /// Append description-less packages (all from one source)
+    #[cfg(test)]
+    pub fn append_names<S: AsRef<str>>(&mut self, names: &[S], source: PackageSource) {
+        for name in names {
+            self.push(name.as_ref(), "", source);
+        }
+    }
+
+    /// Build a database from `(name, description)` pairs (all from one source)
+    #[cfg(test)]
+    pub fn from_entries(entries: &[(&str, &str)], source: PackageSource) -> Self {
+        let mut db = Self::new();
+        for (name, description) in entries {
+            db.push(name, description, source);
+        }
+        db
+    }
+
+    /// Append a package to both arenas
+    fn push(&mut self, name: &str, description: &str, source: PackageSource) {
+        let arena = self.arena.to_mut();
+        let name_start = arena.len();
+        arena.push_str(name);
+        let name_end = arena.len();
+        arena.push('\n');
+
+        let desc_arena = self.desc_arena.to_mut();
+        let desc_start = desc_arena.len();
+        desc_arena.push_str(description);
+        let desc_end = desc_arena.len();
+        desc_arena.push('\n');
+
+        self.packages.push(PackageView {
+            name_start,
+            name_end,
+            desc_start,
+            desc_end,
+            source,
+        });
+    }
+
     /// Get the cache file path
     fn cache_path() -> Option<PathBuf> {
-        let cache_dir = dirs::cache_dir()?;
-        let terra_cache = cache_dir.join("terra-store");
-        fs::create_dir_all(&terra_cache).ok()?;
-        Some(terra_cache.join("index.bin"))
+        paths::cache_file("index.bin")
+    }
+
+    /// Get the path of the raw arenas written for memory mapping
+    #[cfg(feature = "mmap")]
+    fn arenas_path() -> Option<PathBuf> {
+        Some(Self::cache_path()?.with_file_name("arenas.bin"))
     }
 
     /// Load from binary cache if valid, otherwise rebuild
     pub fn load_or_build() -> Self {
+        Self::load_or_build_cancellable(&AtomicBool::new(false))
+    }
+
+    /// Like `load_or_build`, but gives up saving the cache once `cancel` is set
+    ///
+    /// Used when loading on a worker thread, so quitting during startup
+    /// never leaves a half-built cache behind.
+    pub fn load_or_build_cancellable(cancel: &AtomicBool) -> Self {
         let start = Instant::now();
 
         // Try loading from cache first
@@ -102,8 +238,10 @@ impl PackageDatabase {
         db.stats.load_time_ms = start.elapsed().as_millis() as u64;
         db.stats.was_cached = false;
 
-        // Save to cache for next time
-        let _ = db.save_to_cache();
+        // Save to cache for next time (unless the load was abandoned)
+        if !cancel.load(Ordering::SeqCst) {
+            let _ = db.save_to_cache();
+        }
 
         db
     }
@@ -117,9 +255,23 @@ impl PackageDatabase {
             return None;
         }
 
+        #[cfg(feature = "mmap")]
+        let arenas_path = Self::arenas_path();
+        #[cfg(not(feature = "mmap"))]
+        let arenas_path: Option<PathBuf> = None;
+
         let file = File::open(&cache_path).ok()?;
-        let mut reader = BufReader::new(file);
+        let mut db = Self::read_cache(BufReader::new(file), pacman_sync_mtime(), arenas_path.as_deref())?;
+        db.stats.load_time_ms = start.elapsed().as_millis() as u64;
+        Some(db)
+    }
 
+    /// Decode a cache written by `write_cache`
+    ///
+    /// Returns `None` for an unreadable cache, or one built before the
+    /// sync databases last changed (`current_sync_mtime`). A cache whose
+    /// arenas were written for mapping also needs `arenas_path`.
+    fn read_cache(mut reader: impl Read, current_sync_mtime: Option<u64>, arenas_path: Option<&Path>) -> Option<Self> {
         // Read header
         let header: CacheHeader = bincode::deserialize_from(&mut reader).ok()?;
 
@@ -128,10 +280,31 @@ impl PackageDatabase {
             return None;
         }
 
-        // Read arena
-        let mut arena = String::with_capacity(header.arena_len);
+        // A `pacman -Sy` since the build may have added packages
+        if is_stale(header.sync_mtime, current_sync_mtime) {
+            return None;
+        }
+
+        // Everything after the header is gzip-compressed
+        let mut reader = BufReader::new(GzDecoder::new(reader));
+
+        // Read arenas. They were written from valid strings, so invalid
+        // UTF-8 means corruption; a lossy repair would shift every offset
         let arena_bytes: Vec<u8> = bincode::deserialize_from(&mut reader).ok()?;
-        arena.push_str(&String::from_utf8_lossy(&arena_bytes));
+        let arena = String::from_utf8(arena_bytes).ok()?;
+
+        let desc_bytes: Vec<u8> = bincode::deserialize_from(&mut reader).ok()?;
+        let desc_arena = String::from_utf8(desc_bytes).ok()?;
+
+        let (arena, desc_arena) = if header.mapped_arenas {
+            Self::map_arenas(arenas_path?, &header)?
+        } else {
+            (Arena::Owned(arena), Arena::Owned(desc_arena))
+        };
+
+        if arena.len() != header.arena_len || desc_arena.len() != header.desc_arena_len {
+            return None;
+        }
 
         // Read packages
         let packages: Vec<PackageView> = bincode::deserialize_from(&mut reader).ok()?;
@@ -139,18 +312,33 @@ impl PackageDatabase {
         let stats = DatabaseStats {
             official_count: header.official_count,
             aur_count: header.aur_count,
-            arena_bytes: arena.len(),
-            load_time_ms: start.elapsed().as_millis() as u64,
+            arena_bytes: arena.len() + desc_arena.len(),
+            load_time_ms: 0,
             was_cached: true,
+            ..DatabaseStats::default()
         };
 
         Some(Self {
             arena,
+            desc_arena,
             packages,
+            sync_mtime: header.sync_mtime,
             stats,
         })
     }
 
+    /// Map the arenas a cache header points at
+    #[cfg(feature = "mmap")]
+    fn map_arenas(path: &Path, header: &CacheHeader) -> Option<(Arena, Arena)> {
+        crate::arena::map_pair(path, header.arena_len, header.desc_arena_len)
+    }
+
+    /// Without the `mmap` feature a mapped cache is just a miss
+    #[cfg(not(feature = "mmap"))]
+    fn map_arenas(_path: &Path, _header: &CacheHeader) -> Option<(Arena, Arena)> {
+        None
+    }
+
     /// Save database to binary cache
     fn save_to_cache(&self) -> std::io::Result<()> {
         let cache_path = match Self::cache_path() {
@@ -158,122 +346,133 @@ impl PackageDatabase {
             None => return Ok(()),
         };
 
-        let file = File::create(&cache_path)?;
-        let mut writer = BufWriter::new(file);
+        // With mmap, the arenas go raw into their own file first, so they
+        // can be mapped straight back in; the index then only records them
+        #[cfg(feature = "mmap")]
+        let mapped = match Self::arenas_path() {
+            Some(path) => {
+                write_atomically(&path, |writer| self.write_arenas(writer))?;
+                true
+            }
+            None => false,
+        };
+        #[cfg(not(feature = "mmap"))]
+        let mapped = false;
 
+        write_atomically(&cache_path, |writer| self.write_cache(writer, mapped))
+    }
+
+    /// Write both arenas back to back, uncompressed, for `map_arenas`
+    #[cfg(feature = "mmap")]
+    fn write_arenas(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writer.write_all(self.arena.as_bytes())?;
+        writer.write_all(self.desc_arena.as_bytes())
+    }
+
+    /// Encode the database: header, then the gzip-compressed name arena,
+    /// description arena and index
+    ///
+    /// The header stays uncompressed so a version mismatch or stale cache
+    /// is detected without inflating anything. With `mapped_arenas` the
+    /// arenas are left out, as they were saved to `arenas.bin`.
+    fn write_cache(&self, mut writer: impl Write, mapped_arenas: bool) -> std::io::Result<()> {
         // Write header
         let header = CacheHeader {
             version: CACHE_VERSION,
             official_count: self.stats.official_count,
             aur_count: self.stats.aur_count,
             arena_len: self.arena.len(),
+            desc_arena_len: self.desc_arena.len(),
             timestamp: std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs(),
+            sync_mtime: self.sync_mtime,
+            mapped_arenas,
         };
         bincode::serialize_into(&mut writer, &header)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .map_err(std::io::Error::other)?;
+
+        // Fast compression: the arenas are plain text and shrink well, and
+        // the cache is rewritten on every rebuild
+        let mut writer = BufWriter::new(GzEncoder::new(writer, Compression::fast()));
 
-        // Write arena as bytes
-        bincode::serialize_into(&mut writer, self.arena.as_bytes())
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        // Write arenas as bytes
+        let (arena, desc_arena) = if mapped_arenas {
+            ("", "")
+        } else {
+            (&*self.arena, &*self.desc_arena)
+        };
+        bincode::serialize_into(&mut writer, arena.as_bytes())
+            .map_err(std::io::Error::other)?;
+        bincode::serialize_into(&mut writer, desc_arena.as_bytes())
+            .map_err(std::io::Error::other)?;
 
         // Write packages
         bincode::serialize_into(&mut writer, &self.packages)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-
-        writer.flush()?;
+            .map_err(std::io::Error::other)?;
+        writer.into_inner().map_err(|e| e.into_error())?.finish()?;
         Ok(())
     }
 
     /// Build database fresh from pacman/paru
+    ///
+    /// The AUR listing can take seconds, so both sources are fetched on
+    /// their own threads and the AUR portion is appended afterwards.
     fn build_fresh() -> Self {
-        let mut arena = String::with_capacity(5 * 1024 * 1024); // Pre-allocate 5MB
-        let mut packages = Vec::with_capacity(100_000);
-        let mut official_count = 0;
-        let mut aur_count = 0;
-
-        // Fetch official packages
-        if let Ok(output) = Command::new("pacman").args(["-Slq"]).output() {
-            if output.status.success() {
-                let text = String::from_utf8_lossy(&output.stdout);
-                for line in text.lines() {
-                    if !line.is_empty() {
-                        let start = arena.len();
-                        arena.push_str(line);
-                        let end = arena.len();
-                        arena.push('\n');
-
-                        packages.push(PackageView {
-                            name_start: start,
-                            name_end: end,
-                            source: PackageSource::Official,
-                        });
-                        official_count += 1;
-                    }
-                }
-            }
-        }
+        // Taken before listing, so a sync racing the build triggers
+        // another rebuild next time rather than being missed
+        let sync_mtime = pacman_sync_mtime().unwrap_or(0);
 
-        // Fetch AUR packages (if paru/yay available)
-        let aur_helper = if Command::new("paru")
-            .arg("--version")
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false)
-        {
-            Some("paru")
-        } else if Command::new("yay")
-            .arg("--version")
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false)
-        {
-            Some("yay")
-        } else {
-            None
-        };
-
-        if let Some(helper) = aur_helper {
+        let official = thread::spawn(|| {
+            fetch_source("pacman", &["-Slq"], &["-Ss"], PackageSource::Official)
+        });
+        let aur = thread::spawn(|| match aur_helper() {
             // Only get AUR packages (exclude official repos from the list)
-            if let Ok(output) = Command::new(helper).args(["-Slq", "--aur"]).output() {
-                if output.status.success() {
-                    let text = String::from_utf8_lossy(&output.stdout);
-                    for line in text.lines() {
-                        if !line.is_empty() {
-                            let start = arena.len();
-                            arena.push_str(line);
-                            let end = arena.len();
-                            arena.push('\n');
-
-                            packages.push(PackageView {
-                                name_start: start,
-                                name_end: end,
-                                source: PackageSource::Aur,
-                            });
-                            aur_count += 1;
-                        }
-                    }
-                }
-            }
-        }
+            Some(helper) => fetch_source(&helper, &["-Slq", "--aur"], &["-Ss", "--aur"], PackageSource::Aur),
+            None => (Self::new(), Duration::ZERO),
+        });
+
+        let (mut db, official_time) = official.join().unwrap_or_else(|_| (Self::new(), Duration::ZERO));
+        let (aur_db, aur_time) = aur.join().unwrap_or_else(|_| (Self::new(), Duration::ZERO));
+        let official_count = db.len();
+        let aur_count = aur_db.len();
+        db.append(aur_db);
+        db.sync_mtime = sync_mtime;
 
         // Shrink to fit
-        arena.shrink_to_fit();
-        packages.shrink_to_fit();
+        db.arena.to_mut().shrink_to_fit();
+        db.desc_arena.to_mut().shrink_to_fit();
+        db.packages.shrink_to_fit();
 
-        Self {
-            arena,
-            packages,
-            stats: DatabaseStats {
-                official_count,
-                aur_count,
-                arena_bytes: 0, // Will be set after
-                load_time_ms: 0,
-                was_cached: false,
-            },
-        }
+        db.stats = DatabaseStats {
+            official_count,
+            aur_count,
+            arena_bytes: db.arena.len() + db.desc_arena.len(),
+            load_time_ms: 0,
+            was_cached: false,
+            official_ms: official_time.as_millis() as u64,
+            aur_ms: aur_time.as_millis() as u64,
+        };
+
+        db
+    }
+
+    /// Append every package of `other`, shifting its arena offsets past
+    /// the packages already here
+    fn append(&mut self, other: PackageDatabase) {
+        let name_offset = self.arena.len();
+        let desc_offset = self.desc_arena.len();
+
+        self.arena.to_mut().push_str(&other.arena);
+        self.desc_arena.to_mut().push_str(&other.desc_arena);
+        self.packages.extend(other.packages.into_iter().map(|view| PackageView {
+            name_start: view.name_start + name_offset,
+            name_end: view.name_end + name_offset,
+            desc_start: view.desc_start + desc_offset,
+            desc_end: view.desc_end + desc_offset,
+            source: view.source,
+        }));
     }
 
     /// Get total package count
@@ -281,48 +480,252 @@ impl PackageDatabase {
         self.packages.len()
     }
 
-    /// Check if empty
-    #[allow(dead_code)]
-    pub fn is_empty(&self) -> bool {
-        self.packages.is_empty()
-    }
-
     /// Zero-CPU search - just pointer math, no string allocation
-    /// Returns indices into the packages vector
+    /// Returns indices into the packages vector, best match first
+    ///
+    /// The query is split on whitespace into terms that must all match.
+    /// Each term is fuzzy-matched as a subsequence of the name (so "nvim"
+    /// finds "neovim"). Results are grouped by `MatchTier` - exact, prefix,
+    /// substring, then fuzzy - sorted alphabetically within the first three
+    /// and by `fuzzy_score` within the last. Terms are matched literally, so
+    /// characters like `+`, `*` or `(` carry no special meaning.
+    #[cfg(test)]
     #[inline]
     pub fn search(&self, query: &str, source_filter: Option<PackageSource>, limit: usize) -> Vec<usize> {
-        if query.is_empty() {
-            return Vec::new();
+        self.search_counted(query, source_filter, limit).indices
+    }
+
+    /// Like `search`, but also reports the total number of matches so
+    /// callers can tell when `limit` truncated the results
+    pub fn search_counted(&self, query: &str, source_filter: Option<PackageSource>, limit: usize) -> SearchResults {
+        let options = SearchOptions {
+            source_filter,
+            limit,
+            descriptions: false,
+        };
+        self.search_cancellable(query, &options, None, |_| true, || false)
+            .unwrap_or_default()
+    }
+
+    /// Like `search`, but packages whose description contains every term
+    /// also match, ranked after all name matches
+    #[cfg(test)]
+    pub fn search_with_descriptions(
+        &self,
+        query: &str,
+        source_filter: Option<PackageSource>,
+        limit: usize,
+    ) -> Vec<usize> {
+        let options = SearchOptions {
+            source_filter,
+            limit,
+            descriptions: true,
+        };
+        self.search_cancellable(query, &options, None, |_| true, || false)
+            .unwrap_or_default()
+            .indices
+    }
+
+    /// Search that polls `cancelled` during the scan and gives up when it
+    /// returns true, yielding `None`
+    ///
+    /// With `candidates`, only those indices are scanned instead of the
+    /// whole database. Matches whose name `keep` rejects are dropped
+    /// (e.g. to show only installed packages).
+    pub fn search_cancellable(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        candidates: Option<&[usize]>,
+        keep: impl Fn(&str) -> bool,
+        cancelled: impl Fn() -> bool,
+    ) -> Option<SearchResults> {
+        #[cfg(feature = "regex")]
+        if let Some(pattern) = query.strip_prefix(REGEX_PREFIX) {
+            return match compile_regex(pattern) {
+                Ok(regex) => self.search_regex(&regex, options, candidates, keep, cancelled),
+                // An invalid pattern is searched literally instead
+                Err(_) => self.search_cancellable(pattern, options, candidates, keep, cancelled),
+            };
         }
 
-        let query_lower = query.to_lowercase();
-        let mut results = Vec::with_capacity(limit);
+        let terms = query_terms(query);
+        if terms.is_empty() {
+            return Some(SearchResults::default());
+        }
+
+        // (tier, fuzzy score, name, index) for every match
+        let mut matches: Vec<(MatchTier, u32, &str, usize)> = Vec::new();
+
+        let scan: Box<dyn Iterator<Item = usize>> = match candidates {
+            Some(candidates) => Box::new(candidates.iter().copied()),
+            None => Box::new(0..self.packages.len()),
+        };
+
+        for (scanned, idx) in scan.enumerate() {
+            if scanned % CANCEL_CHECK_INTERVAL == 0 && cancelled() {
+                return None;
+            }
+            let Some(pkg) = self.packages.get(idx) else {
+                continue;
+            };
 
-        for (idx, pkg) in self.packages.iter().enumerate() {
             // Source filter
-            if let Some(filter) = source_filter {
+            if let Some(filter) = options.source_filter {
                 if pkg.source != filter {
                     continue;
                 }
             }
 
-            // Name match (case-insensitive)
-            let name = pkg.name(&self.arena);
-            if name.to_lowercase().contains(&query_lower) {
-                results.push(idx);
-                if results.len() >= limit {
-                    break;
-                }
+            // Name match (case-insensitive, every term must match)
+            let Some(name) = pkg.try_name(&self.arena).filter(|name| keep(name)) else {
+                continue;
+            };
+            // The weakest term decides the tier
+            let matched = terms.iter().try_fold((MatchTier::Exact, 0), |(tier, score), term| {
+                let (term_tier, term_score) = match_term(name, term)?;
+                Some((tier.max(term_tier), score + term_score))
+            });
+            if let Some((tier, score)) = matched {
+                matches.push((tier, score, name, idx));
+            } else if options.descriptions && self.description_matches(pkg, &terms) {
+                matches.push((MatchTier::Description, 0, name, idx));
             }
         }
 
-        results
+        // Best tier first; within the fuzzy tier highest score first;
+        // then alphabetically (arena order breaks exact ties)
+        matches.sort_unstable_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then(b.1.cmp(&a.1))
+                .then(a.2.cmp(b.2))
+                .then(a.3.cmp(&b.3))
+        });
+        let total = matches.len();
+        matches.truncate(options.limit);
+
+        Some(SearchResults {
+            indices: matches.into_iter().map(|(_, _, _, idx)| idx).collect(),
+            total,
+        })
+    }
+
+    /// Search for names matching `regex`, sorted alphabetically
+    #[cfg(feature = "regex")]
+    fn search_regex(
+        &self,
+        regex: &regex::Regex,
+        options: &SearchOptions,
+        candidates: Option<&[usize]>,
+        keep: impl Fn(&str) -> bool,
+        cancelled: impl Fn() -> bool,
+    ) -> Option<SearchResults> {
+        let scan: Box<dyn Iterator<Item = usize>> = match candidates {
+            Some(candidates) => Box::new(candidates.iter().copied()),
+            None => Box::new(0..self.packages.len()),
+        };
+
+        let mut matches: Vec<(&str, usize)> = Vec::new();
+        for (scanned, idx) in scan.enumerate() {
+            if scanned % CANCEL_CHECK_INTERVAL == 0 && cancelled() {
+                return None;
+            }
+            let Some(pkg) = self.packages.get(idx) else {
+                continue;
+            };
+            if options.source_filter.is_some_and(|filter| pkg.source != filter) {
+                continue;
+            }
+            if let Some(name) = pkg.try_name(&self.arena).filter(|name| keep(name) && regex.is_match(name)) {
+                matches.push((name, idx));
+            }
+        }
+
+        matches.sort_unstable();
+        let total = matches.len();
+        matches.truncate(options.limit);
+
+        Some(SearchResults {
+            indices: matches.into_iter().map(|(_, idx)| idx).collect(),
+            total,
+        })
+    }
+
+    /// Every package from `source_filter` whose name `keep` accepts,
+    /// alphabetically (for browsing e.g. installed packages without a query)
+    pub fn list_filtered(
+        &self,
+        source_filter: Option<PackageSource>,
+        limit: usize,
+        keep: impl Fn(&str) -> bool,
+    ) -> SearchResults {
+        let mut matches: Vec<(&str, usize)> = self
+            .packages
+            .iter()
+            .enumerate()
+            .filter(|(_, pkg)| source_filter.is_none_or(|filter| pkg.source == filter))
+            .filter_map(|(idx, pkg)| Some((pkg.try_name(&self.arena)?, idx)))
+            .filter(|(name, _)| keep(name))
+            .collect();
+
+        matches.sort_unstable();
+        let total = matches.len();
+        matches.truncate(limit);
+
+        SearchResults {
+            indices: matches.into_iter().map(|(_, idx)| idx).collect(),
+            total,
+        }
+    }
+
+    /// Check whether a package description contains every term
+    fn description_matches(&self, pkg: &PackageView, terms: &[String]) -> bool {
+        let Some(description) = pkg.try_description(&self.desc_arena) else {
+            return false;
+        };
+        let bytes = description.as_bytes();
+        terms.iter().all(|term| contains_ignore_case(bytes, term.as_bytes()))
+    }
+
+    /// Fold VCS variants (`foo-git`, ...) into their release package when
+    /// both appear in `results`
+    ///
+    /// Returns the folded list, in the original order, plus the hidden
+    /// variant indices keyed by the release index that absorbed them.
+    pub fn collapse_vcs(&self, results: &[usize]) -> (Vec<usize>, HashMap<usize, Vec<usize>>) {
+        let by_name: HashMap<&str, usize> = results
+            .iter()
+            .filter_map(|&idx| Some((self.get_name(idx)?, idx)))
+            .collect();
+
+        let mut kept = Vec::with_capacity(results.len());
+        let mut variants: HashMap<usize, Vec<usize>> = HashMap::new();
+
+        for &idx in results {
+            let release = self
+                .get_name(idx)
+                .and_then(vcs_base)
+                .and_then(|base| by_name.get(base));
+
+            match release {
+                Some(&release) => variants.entry(release).or_default().push(idx),
+                None => kept.push(idx),
+            }
+        }
+
+        (kept, variants)
     }
 
     /// Get package name by index
     #[inline]
     pub fn get_name(&self, idx: usize) -> Option<&str> {
-        self.packages.get(idx).map(|p| p.name(&self.arena))
+        self.packages.get(idx).and_then(|p| p.try_name(&self.arena))
+    }
+
+    /// Get package description by index (empty if none was indexed)
+    #[inline]
+    pub fn get_description(&self, idx: usize) -> Option<&str> {
+        self.packages.get(idx).and_then(|p| p.try_description(&self.desc_arena))
     }
 
     /// Get package source by index
@@ -331,6 +734,27 @@ impl PackageDatabase {
         self.packages.get(idx).map(|p| p.source)
     }
 
+    /// Indices of the packages called `name` from `source`, in the order
+    /// asked for, looked up in a single pass; packages that aren't
+    /// indexed are left out
+    pub fn find_all(&self, wanted: &[(&str, PackageSource)]) -> Vec<usize> {
+        let mut positions = HashMap::with_capacity(wanted.len());
+        for (position, &key) in wanted.iter().enumerate() {
+            positions.entry(key).or_insert(position);
+        }
+
+        let mut found = vec![None; wanted.len()];
+        for (idx, pkg) in self.packages.iter().enumerate() {
+            let Some(name) = pkg.try_name(&self.arena) else {
+                continue;
+            };
+            if let Some(&position) = positions.get(&(name, pkg.source)) {
+                found[position].get_or_insert(idx);
+            }
+        }
+        found.into_iter().flatten().collect()
+    }
+
     /// Invalidate cache (force rebuild on next load)
     pub fn invalidate_cache() -> std::io::Result<()> {
         if let Some(path) = Self::cache_path() {
@@ -354,6 +778,330 @@ impl PackageDatabase {
     }
 }
 
+/// Check whether `query` is a regex query (never without the `regex`
+/// feature)
+pub fn is_regex_query(query: &str) -> bool {
+    cfg!(feature = "regex") && query.starts_with(REGEX_PREFIX)
+}
+
+/// Why a regex query's pattern doesn't compile, if it is one and doesn't
+#[cfg(feature = "regex")]
+pub fn regex_error(query: &str) -> Option<String> {
+    let pattern = query.strip_prefix(REGEX_PREFIX)?;
+    // Syntax errors span several lines; the last one says what's wrong
+    compile_regex(pattern)
+        .err()
+        .map(|e| e.to_string().lines().last().unwrap_or_default().trim_start_matches("error: ").to_string())
+}
+
+/// Why a regex query's pattern doesn't compile (regex queries need the
+/// `regex` feature)
+#[cfg(not(feature = "regex"))]
+pub fn regex_error(_query: &str) -> Option<String> {
+    None
+}
+
+/// Compile a regex query's pattern, case-insensitively like plain search
+#[cfg(feature = "regex")]
+fn compile_regex(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+/// A query prepared for highlighting the names it matched, so a regex
+/// query's pattern is compiled once rather than for every name
+#[derive(Debug, Default)]
+pub struct Highlighter {
+    /// The query this was prepared from
+    pub query: String,
+    #[cfg(feature = "regex")]
+    regex: Option<regex::Regex>,
+    terms: Vec<String>,
+}
+
+impl Highlighter {
+    pub fn new(query: &str) -> Self {
+        let prepared = query.to_string();
+        #[cfg(feature = "regex")]
+        if let Some(Ok(regex)) = query.strip_prefix(REGEX_PREFIX).map(compile_regex) {
+            return Self {
+                query: prepared,
+                regex: Some(regex),
+                terms: Vec::new(),
+            };
+        }
+        #[cfg(feature = "regex")]
+        let query = query.strip_prefix(REGEX_PREFIX).unwrap_or(query);
+
+        Self {
+            query: prepared,
+            #[cfg(feature = "regex")]
+            regex: None,
+            terms: query_terms(query),
+        }
+    }
+
+    /// Byte ranges of `name` the query matched, in order and merged
+    ///
+    /// Each term is located the way `match_term` matched it: its first
+    /// occurrence, else the greedy fuzzy positions. Terms the name
+    /// doesn't match (description matches) mark nothing. Regex queries
+    /// mark the pattern's matches.
+    pub fn ranges(&self, name: &str) -> Vec<Range<usize>> {
+        let bytes = name.as_bytes();
+        let mut matched = vec![false; bytes.len()];
+
+        #[cfg(feature = "regex")]
+        if let Some(regex) = &self.regex {
+            for found in regex.find_iter(name) {
+                matched[found.range()].fill(true);
+            }
+            return merge_matched(name, &matched);
+        }
+
+        for term in &self.terms {
+            let term = term.as_bytes();
+            if let Some(start) = bytes.windows(term.len()).position(|window| window.eq_ignore_ascii_case(term)) {
+                matched[start..start + term.len()].fill(true);
+                continue;
+            }
+            // Greedy from the left, like `fuzzy_score`
+            let mut positions = Vec::with_capacity(term.len());
+            let mut rest = term.iter();
+            let mut next = rest.next();
+            for (pos, &b) in bytes.iter().enumerate() {
+                let Some(&want) = next else {
+                    break;
+                };
+                if b.to_ascii_lowercase() == want {
+                    positions.push(pos);
+                    next = rest.next();
+                }
+            }
+            if next.is_none() {
+                for pos in positions {
+                    matched[pos] = true;
+                }
+            }
+        }
+        merge_matched(name, &matched)
+    }
+}
+
+/// Merge matched bytes into ranges, widened to whole chars
+fn merge_matched(name: &str, matched: &[bool]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (start, c) in name.char_indices() {
+        let end = start + c.len_utf8();
+        if !matched[start..end].contains(&true) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+    ranges
+}
+
+/// Split a query into lowercase, whitespace-separated literal terms
+fn query_terms(query: &str) -> Vec<String> {
+    query.split_whitespace().map(str::to_lowercase).collect()
+}
+
+/// Write a file through `write` into a temp file and rename it over
+/// `path`, so an interrupted save never leaves a truncated file in place
+pub(crate) fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&tmp_path, path)
+}
+
+/// Newest modification time (Unix seconds) of the pacman sync dir and
+/// the databases in it, or `None` if it can't be read
+fn pacman_sync_mtime() -> Option<u64> {
+    let dir = Path::new(PACMAN_SYNC_DIR);
+    let mtime = |meta: fs::Metadata| {
+        meta.modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs())
+    };
+
+    let dir_mtime = mtime(fs::metadata(dir).ok()?)?;
+    let newest_db = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| mtime(entry.ok()?.metadata().ok()?))
+        .max()
+        .unwrap_or(0);
+    Some(dir_mtime.max(newest_db))
+}
+
+/// Check whether a cache built at sync mtime `cached` is older than the
+/// sync databases' `current` mtime
+///
+/// An unknown current mtime (no pacman, unreadable dir) never invalidates
+/// the cache, since F5 can still force a rebuild.
+fn is_stale(cached: u64, current: Option<u64>) -> bool {
+    current.is_some_and(|current| current > cached)
+}
+
+/// List one source's packages, with descriptions, into a standalone
+/// database, returning it with the time the listing took
+fn fetch_source(
+    cmd: &str,
+    list_args: &[&str],
+    desc_args: &[&str],
+    source: PackageSource,
+) -> (PackageDatabase, Duration) {
+    let start = Instant::now();
+    let mut db = PackageDatabase::new();
+
+    if let Ok(output) = Command::new(cmd).args(list_args).output() {
+        if output.status.success() {
+            let descriptions = fetch_descriptions(cmd, desc_args, source);
+            let text = String::from_utf8_lossy(&output.stdout);
+            for line in text.lines().filter(|line| !line.is_empty()) {
+                let description = descriptions.get(line).map_or("", String::as_str);
+                db.push(line, description, source);
+            }
+        }
+    }
+
+    (db, start.elapsed())
+}
+
+/// Collect descriptions from `<cmd> -Ss` output, keyed by package name
+///
+/// Descriptions are best-effort: if the listing fails, packages are simply
+/// indexed without one.
+fn fetch_descriptions(cmd: &str, args: &[&str], source: PackageSource) -> HashMap<String, String> {
+    let Ok(output) = Command::new(cmd).args(args).output() else {
+        return HashMap::new();
+    };
+    if !output.status.success() {
+        return HashMap::new();
+    }
+
+    parse_pacman_search_output(&String::from_utf8_lossy(&output.stdout), source)
+        .into_iter()
+        .map(|pkg| (pkg.name, pkg.description))
+        .collect()
+}
+
+/// Strip a VCS suffix from a package name (`foo-git` -> `foo`)
+pub fn vcs_base(name: &str) -> Option<&str> {
+    VCS_SUFFIXES
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .filter(|base| !base.is_empty())
+}
+
+/// How a query term matched a package name, best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum MatchTier {
+    /// The term is the whole name
+    Exact,
+    /// The name starts with the term
+    Prefix,
+    /// The term appears contiguously inside the name
+    Substring,
+    /// The term's characters appear in order, with gaps
+    Fuzzy,
+    /// The name doesn't match, but the description contains the term
+    Description,
+}
+
+/// Bonus for a match at the start of the name
+const PREFIX_BONUS: u32 = 50;
+/// Bonus for a match right after a separator like `-` or `_`
+const BOUNDARY_BONUS: u32 = 20;
+/// Bonus for each fuzzy-matched char directly following the previous one
+const RUN_BONUS: u32 = 5;
+
+/// Check whether a byte separates words in a package name
+#[inline]
+fn is_separator(b: u8) -> bool {
+    matches!(b, b'-' | b'_' | b'.' | b'+' | b'@')
+}
+
+/// Classify how `name` matches a lowercase `term`, or `None` if it doesn't
+///
+/// Only fuzzy matches carry a meaningful score. Comparison works on the
+/// raw bytes and is ASCII case-insensitive, which covers pacman names.
+pub(crate) fn match_term(name: &str, term: &str) -> Option<(MatchTier, u32)> {
+    let bytes = name.as_bytes();
+    let term_bytes = term.as_bytes();
+
+    if bytes.eq_ignore_ascii_case(term_bytes) {
+        Some((MatchTier::Exact, 0))
+    } else if bytes.len() >= term_bytes.len() && bytes[..term_bytes.len()].eq_ignore_ascii_case(term_bytes) {
+        Some((MatchTier::Prefix, 0))
+    } else if contains_ignore_case(bytes, term_bytes) {
+        Some((MatchTier::Substring, 0))
+    } else {
+        fuzzy_score(name, term).map(|score| (MatchTier::Fuzzy, score))
+    }
+}
+
+/// ASCII case-insensitive substring check on raw bytes
+#[inline]
+fn contains_ignore_case(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len().max(1))
+        .any(|window| window.eq_ignore_ascii_case(needle))
+}
+
+/// Score a scattered subsequence match of a lowercase `term`, or `None`
+/// if the term's characters don't all appear in order
+///
+/// Matches earn bonuses for starting the name or a word, and for running
+/// on directly from the previous matched character.
+fn fuzzy_score(name: &str, term: &str) -> Option<u32> {
+    let name = name.as_bytes();
+    let term = term.as_bytes();
+
+    let bonus_at = |pos: usize| {
+        if pos == 0 {
+            PREFIX_BONUS
+        } else if is_separator(name[pos - 1]) {
+            BOUNDARY_BONUS
+        } else {
+            0
+        }
+    };
+
+    // Greedy from the left
+    let mut score = 0;
+    let mut matched = 0;
+    let mut last: Option<usize> = None;
+
+    for (pos, &b) in name.iter().enumerate() {
+        if matched == term.len() {
+            break;
+        }
+        if b.to_ascii_lowercase() == term[matched] {
+            score += 1 + bonus_at(pos);
+            if pos > 0 && last == Some(pos - 1) {
+                score += RUN_BONUS;
+            }
+            last = Some(pos);
+            matched += 1;
+        }
+    }
+
+    (matched == term.len()).then_some(score)
+}
+
 impl Default for PackageDatabase {
     fn default() -> Self {
         Self::new()
@@ -364,41 +1112,290 @@ impl Default for PackageDatabase {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_find_all() {
+        let mut db = PackageDatabase::from_names(&["neovim", "htop"], PackageSource::Official);
+        db.append_names(&["neovim-git", "neovim"], PackageSource::Aur);
+
+        let wanted = [
+            ("neovim", PackageSource::Aur),
+            ("gone", PackageSource::Official),
+            ("htop", PackageSource::Official),
+            ("htop", PackageSource::Aur),
+        ];
+        assert_eq!(db.find_all(&wanted), vec![3, 1]);
+        assert!(db.find_all(&[]).is_empty());
+    }
+
     #[test]
     fn test_package_view() {
         let arena = "neofetch\nhtop\nfirefox\n";
         let view = PackageView {
             name_start: 0,
             name_end: 8,
+            desc_start: 0,
+            desc_end: 0,
             source: PackageSource::Official,
         };
-        assert_eq!(view.name(arena), "neofetch");
+        assert_eq!(view.try_name(arena), Some("neofetch"));
     }
 
     #[test]
-    fn test_search() {
-        let mut arena = String::new();
-        let mut packages = Vec::new();
-
-        for name in ["neofetch", "htop", "firefox", "neomutt", "neovim"] {
-            let start = arena.len();
-            arena.push_str(name);
-            let end = arena.len();
-            arena.push('\n');
-            packages.push(PackageView {
-                name_start: start,
-                name_end: end,
-                source: PackageSource::Official,
-            });
-        }
-
-        let db = PackageDatabase {
-            arena,
-            packages,
-            stats: DatabaseStats::default(),
+    fn test_try_name_rejects_bad_offsets() {
+        let arena = "neofetch\nhtöp\n";
+        let view = |start, end| PackageView {
+            name_start: start,
+            name_end: end,
+            desc_start: 0,
+            desc_end: 0,
+            source: PackageSource::Official,
         };
 
+        assert_eq!(view(0, 8).try_name(arena), Some("neofetch"));
+        assert_eq!(view(9, 100).try_name(arena), None); // Out of range
+        assert_eq!(view(12, 14).try_name(arena), None); // Inside 'ö'
+        assert_eq!(view(8, 2).try_name(arena), None); // Reversed
+    }
+
+    /// Build an in-memory database of official packages
+    fn test_db(names: &[&str]) -> PackageDatabase {
+        PackageDatabase::from_names(names, PackageSource::Official)
+    }
+
+    #[test]
+    fn test_search() {
+        let db = test_db(&["neofetch", "htop", "firefox", "neomutt", "neovim"]);
+
         let results = db.search("neo", None, 10);
         assert_eq!(results.len(), 3); // neofetch, neomutt, neovim
+        assert_eq!(results, vec![0, 3, 4]);
+    }
+
+    #[test]
+    fn test_search_tiers() {
+        let db = test_db(&["xhtop", "htop-git", "bashtop", "htop", "btop", "h-t-o-p"]);
+        let names: Vec<&str> = db
+            .search("htop", None, 10)
+            .into_iter()
+            .filter_map(|idx| db.get_name(idx))
+            .collect();
+
+        // Exact, prefix, substring (alphabetical), then fuzzy
+        assert_eq!(names, vec!["htop", "htop-git", "bashtop", "xhtop", "h-t-o-p"]);
+    }
+
+    #[test]
+    fn test_search_special_chars_are_literal() {
+        let db = test_db(&["gcc", "gcc-c++", "libc++", "python-regex"]);
+
+        let names = |query: &str| -> Vec<&str> {
+            db.search(query, None, 10)
+                .into_iter()
+                .filter_map(|idx| db.get_name(idx))
+                .collect()
+        };
+
+        assert_eq!(names("c++"), vec!["gcc-c++", "libc++"]);
+        assert_eq!(names("gcc c++"), vec!["gcc-c++"]);
+        assert!(names("(").is_empty());
+        assert!(names("*").is_empty());
+        assert!(names("re.ex").is_empty());
+        assert!(names("   ").is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let db = test_db(&["neomutt", "neovim", "vim-airline", "vim", "gvim"]);
+
+        let results = db.search("nvim", None, 10);
+        assert_eq!(db.get_name(results[0]), Some("neovim"));
+
+        // Exact prefix matches rank above mid-string matches
+        let names: Vec<&str> = db
+            .search("vim", None, 10)
+            .into_iter()
+            .filter_map(|idx| db.get_name(idx))
+            .collect();
+        assert_eq!(names, vec!["vim", "vim-airline", "gvim", "neovim"]);
+    }
+
+    #[test]
+    fn test_match_ranges() {
+        let match_ranges = |name, query| Highlighter::new(query).ranges(name);
+        // Substring, then fuzzy, every term marked and merged
+        assert_eq!(match_ranges("neovim", "VIM"), vec![3..6]);
+        assert_eq!(match_ranges("neovim", "nvim"), vec![0..1, 3..6]);
+        assert_eq!(match_ranges("python-neovim", "py vim"), vec![0..2, 10..13]);
+        assert_eq!(match_ranges("python-neovim", "neo vim"), vec![7..13]);
+        // A term only the description matched marks nothing
+        assert_eq!(match_ranges("htop", "monitor"), Vec::<Range<usize>>::new());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_match_ranges_regex() {
+        let match_ranges = |name, query| Highlighter::new(query).ranges(name);
+        assert_eq!(match_ranges("libfoo-dev", "/-dev$"), vec![6..10]);
+        // An invalid pattern is matched literally
+        assert_eq!(match_ranges("pkg(1)", "/pkg("), vec![0..4]);
+    }
+
+    #[test]
+    fn test_collapse_vcs() {
+        let db = test_db(&["foo", "foo-git", "bar-git", "baz", "baz-hg", "baz-svn"]);
+        let (kept, variants) = db.collapse_vcs(&[0, 1, 2, 3, 4, 5]);
+
+        // A VCS package without its release stays visible
+        assert_eq!(kept, vec![0, 2, 3]);
+        assert_eq!(variants.get(&0), Some(&vec![1]));
+        assert_eq!(variants.get(&3), Some(&vec![4, 5]));
+        assert_eq!(vcs_base("foo-git"), Some("foo"));
+        assert_eq!(vcs_base("-git"), None);
+    }
+
+    #[test]
+    fn test_append_shifts_offsets() {
+        let mut db = PackageDatabase::from_entries(&[("neofetch", "System info")], PackageSource::Official);
+        db.append(PackageDatabase::from_entries(
+            &[("yay", "AUR helper"), ("paru-bin", "Another AUR helper")],
+            PackageSource::Aur,
+        ));
+
+        assert_eq!(db.len(), 3);
+        assert_eq!(db.get_name(0), Some("neofetch"));
+        assert_eq!(db.get_name(2), Some("paru-bin"));
+        assert_eq!(db.get_description(1), Some("AUR helper"));
+        assert_eq!(db.get_source(2), Some(PackageSource::Aur));
+    }
+
+    #[test]
+    fn test_cache_staleness() {
+        assert!(!is_stale(100, Some(100)));
+        assert!(!is_stale(100, Some(50)));
+        assert!(is_stale(100, Some(101)));
+        assert!(!is_stale(100, None));
+
+        // A cache written before the last sync is rejected on read
+        let mut db = test_db(&["neofetch"]);
+        db.sync_mtime = 1_000;
+        let mut bytes = Vec::new();
+        db.write_cache(&mut bytes, false).unwrap();
+        assert!(PackageDatabase::read_cache(bytes.as_slice(), Some(1_000), None).is_some());
+        assert!(PackageDatabase::read_cache(bytes.as_slice(), Some(2_000), None).is_none());
+    }
+
+    #[test]
+    fn test_cache_round_trips_multibyte_names() {
+        let db = PackageDatabase::from_entries(
+            &[("ttf-noto-émoji", "Polices à emoji"), ("日本語-fonts", "フォント"), ("htop", "")],
+            PackageSource::Official,
+        );
+        let mut bytes = Vec::new();
+        db.write_cache(&mut bytes, false).unwrap();
+
+        let cached = PackageDatabase::read_cache(bytes.as_slice(), None, None).unwrap();
+        assert_eq!(cached.get_name(0), Some("ttf-noto-émoji"));
+        assert_eq!(cached.get_name(1), Some("日本語-fonts"));
+        assert_eq!(cached.get_name(2), Some("htop"));
+        assert_eq!(cached.get_description(1), Some("フォント"));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_cache_round_trip() {
+        let db = PackageDatabase::from_entries(&[("neofetch", "System info"), ("日本語-fonts", "")], PackageSource::Official);
+        let path = std::env::temp_dir().join(format!("terra-arenas-{}.bin", std::process::id()));
+        write_atomically(&path, |writer| db.write_arenas(writer)).unwrap();
+        let mut bytes = Vec::new();
+        db.write_cache(&mut bytes, true).unwrap();
+
+        let cached = PackageDatabase::read_cache(bytes.as_slice(), None, Some(&path)).unwrap();
+        assert!(matches!(cached.arena, Arena::Mapped { .. }));
+        assert_eq!(cached.get_name(1), Some("日本語-fonts"));
+        assert_eq!(cached.get_description(0), Some("System info"));
+        assert_eq!(cached.search("neo", None, 10), vec![0]);
+
+        // The index alone is a miss
+        assert!(PackageDatabase::read_cache(bytes.as_slice(), None, None).is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_search_cancelled() {
+        let db = test_db(&["neofetch", "neovim"]);
+        let options = SearchOptions {
+            source_filter: None,
+            limit: 10,
+            descriptions: false,
+        };
+        assert!(db.search_cancellable("neo", &options, None, |_| true, || true).is_none());
+        assert_eq!(db.search_cancellable("neo", &options, None, |_| true, || false).map(|r| r.total), Some(2));
+
+        // Only the given candidates are scanned
+        assert_eq!(
+            db.search_cancellable("neo", &options, Some(&[1]), |_| true, || false).map(|r| r.indices),
+            Some(vec![1])
+        );
+
+        // Names `keep` rejects are skipped
+        assert_eq!(
+            db.search_cancellable("neo", &options, None, |name| name == "neovim", || false).map(|r| r.indices),
+            Some(vec![1])
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_search() {
+        let db = test_db(&["libfoo-dev", "libbar", "glib-dev", "LibBaz-dev", "pkg(1)"]);
+        let names = |query: &str| -> Vec<&str> {
+            db.search(query, None, 10).into_iter().filter_map(|idx| db.get_name(idx)).collect()
+        };
+
+        // Anchored and case-insensitive, sorted by name
+        assert_eq!(names("/^lib.*-dev$"), vec!["LibBaz-dev", "libfoo-dev"]);
+        assert_eq!(regex_error("/^lib.*-dev$"), None);
+
+        // An invalid pattern degrades to a literal search
+        assert!(regex_error("/pkg(").is_some());
+        assert_eq!(names("/pkg("), vec!["pkg(1)"]);
+        assert_eq!(regex_error("libfoo"), None);
+    }
+
+    #[test]
+    fn test_search_counted_reports_total() {
+        let db = test_db(&["neofetch", "neovim", "neomutt", "htop"]);
+        let results = db.search_counted("neo", None, 2);
+        assert_eq!(results.indices.len(), 2);
+        assert_eq!(results.total, 3);
+        assert!(results.is_truncated());
+        assert!(!db.search_counted("htop", None, 2).is_truncated());
+    }
+
+    #[test]
+    fn test_search_descriptions() {
+        let db = PackageDatabase::from_entries(
+            &[
+                ("htop", "Interactive process viewer"),
+                ("ripgrep", "A search tool that recursively searches directories"),
+                ("grep-viewer", "Browse search results"),
+            ],
+            PackageSource::Official,
+        );
+
+        // Descriptions are only consulted when asked for
+        assert!(db.search("recursively", None, 10).is_empty());
+        assert_eq!(db.search_with_descriptions("recursively", None, 10), vec![1]);
+        assert_eq!(db.search_with_descriptions("PROCESS viewer", None, 10), vec![0]);
+
+        // Name matches rank ahead of description-only matches
+        assert_eq!(db.search_with_descriptions("viewer", None, 10), vec![2, 0]);
+
+        // The description arena survives a cache round-trip
+        let mut bytes = Vec::new();
+        db.write_cache(&mut bytes, false).unwrap();
+        let cached = PackageDatabase::read_cache(bytes.as_slice(), None, None).unwrap();
+        assert_eq!(cached.get_description(1), db.get_description(1));
+        assert_eq!(cached.search_with_descriptions("recursively", None, 10), vec![1]);
     }
 }
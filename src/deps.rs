@@ -0,0 +1,258 @@
+//! Terra Store v1.0 - Dependency Trees
+//!
+//! Builds the tree of packages a package pulls in, from `pactree` when
+//! pacman-contrib is installed, otherwise from recursive `-Si` lookups.
+//! Trees are capped in depth and size, so cyclic or huge dependency
+//! graphs stay readable.
+
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+
+use crate::package::PackageSource;
+use crate::repos::RepoManager;
+
+/// Deepest dependency level expanded
+pub const MAX_DEPTH: usize = 4;
+
+/// Most packages listed in one tree
+const MAX_NODES: usize = 300;
+
+/// A package and the dependencies it pulls in
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DepNode {
+    pub name: String,
+    pub children: Vec<DepNode>,
+    /// Has dependencies hidden by the depth or size cap
+    pub truncated: bool,
+    /// Already expanded elsewhere in the tree (shared deps and cycles)
+    pub repeated: bool,
+}
+
+impl DepNode {
+    fn leaf(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Number of distinct packages this one pulls in (repeated nodes
+    /// are counted once, and a cycle back to this one not at all)
+    pub fn dependency_count(&self) -> usize {
+        fn collect<'a>(node: &'a DepNode, names: &mut HashSet<&'a str>) {
+            for child in &node.children {
+                names.insert(&child.name);
+                collect(child, names);
+            }
+        }
+
+        let mut names = HashSet::new();
+        collect(self, &mut names);
+        names.remove(self.name.as_str());
+        names.len()
+    }
+
+    /// Render as indented lines with box-drawing branches
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.render_into(&mut out, "", "");
+        out
+    }
+
+    fn render_into(&self, out: &mut String, branch: &str, indent: &str) {
+        out.push_str(branch);
+        out.push_str(&self.name);
+        if self.repeated {
+            out.push_str(" (see above)");
+        } else if self.truncated {
+            out.push_str(" …");
+        }
+        out.push('\n');
+
+        for (i, child) in self.children.iter().enumerate() {
+            let last = i + 1 == self.children.len();
+            let (branch, next) = if last { ("└─ ", "   ") } else { ("├─ ", "│  ") };
+            child.render_into(out, &format!("{}{}", indent, branch), &format!("{}{}", indent, next));
+        }
+    }
+}
+
+/// Build the dependency tree of `name`
+///
+/// Official packages use `pactree` when it is installed; AUR packages and
+/// systems without pacman-contrib fall back to `-Si` lookups, trying the
+/// official repos before the AUR helper for each dependency.
+pub fn dependency_tree(name: &str, source: PackageSource) -> DepNode {
+    if source == PackageSource::Official {
+        if let Some(deps) = pactree_deps(name) {
+            return build_tree(name, |pkg| deps.get(pkg).cloned());
+        }
+    }
+
+    let repos = RepoManager::new();
+    build_tree(name, |pkg| {
+        let info = match source {
+            PackageSource::Aur if pkg == name => repos.get_info(pkg, PackageSource::Aur),
+            _ => repos
+                .get_info(pkg, PackageSource::Official)
+                .or_else(|_| repos.get_info(pkg, PackageSource::Aur)),
+        };
+        info.ok().map(|info| info.depends)
+    })
+}
+
+/// Expand `root` depth-first with `lookup` giving each package's direct
+/// dependencies (`None` when unknown)
+fn build_tree(root: &str, mut lookup: impl FnMut(&str) -> Option<Vec<String>>) -> DepNode {
+    let mut seen = HashSet::new();
+    let mut count = 0;
+    expand(root, 0, &mut lookup, &mut seen, &mut count)
+}
+
+fn expand(
+    name: &str,
+    depth: usize,
+    lookup: &mut impl FnMut(&str) -> Option<Vec<String>>,
+    seen: &mut HashSet<String>,
+    count: &mut usize,
+) -> DepNode {
+    let mut node = DepNode::leaf(name);
+    *count += 1;
+
+    if !seen.insert(name.to_string()) {
+        node.repeated = true;
+        return node;
+    }
+
+    let Some(entries) = lookup(name) else {
+        return node;
+    };
+    let deps: Vec<&str> = entries.iter().filter_map(|dep| package_name(dep)).collect();
+
+    if deps.is_empty() {
+        return node;
+    }
+    if depth >= MAX_DEPTH {
+        node.truncated = true;
+        return node;
+    }
+
+    for dep in deps {
+        if *count >= MAX_NODES {
+            node.truncated = true;
+            break;
+        }
+        node.children.push(expand(dep, depth + 1, lookup, seen, count));
+    }
+    node
+}
+
+/// Package name of a `Depends On` entry, without its version constraint
+///
+/// Soname entries (`libfoo.so=1-64`) are skipped: pacman lists the
+/// package providing them as a dependency of its own.
+fn package_name(dep: &str) -> Option<&str> {
+    let name = dep.split(['<', '>', '=']).next()?.trim();
+    (!name.is_empty() && !name.ends_with(".so")).then_some(name)
+}
+
+/// Direct dependencies of every package in `pactree -s` output for `name`
+///
+/// Returns `None` if pactree isn't installed or doesn't know the package.
+fn pactree_deps(name: &str) -> Option<HashMap<String, Vec<String>>> {
+    // One level deeper than shown, so the last level knows whether it
+    // has dependencies of its own
+    let depth = (MAX_DEPTH + 1).to_string();
+    let output = Command::new("pactree")
+        .args(["-s", "-a", "-d", &depth, name])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(parse_pactree(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse `pactree -a` output into each package's direct dependencies
+///
+/// Lines look like `| |-glibc`: two prefix characters per level, then the
+/// name (or `bash provides sh`, of which only the package counts).
+fn parse_pactree(output: &str) -> HashMap<String, Vec<String>> {
+    let mut deps: HashMap<String, Vec<String>> = HashMap::new();
+    // Package at each depth along the current path
+    let mut path: Vec<String> = Vec::new();
+
+    for line in output.lines() {
+        let start = line.find(|c: char| !matches!(c, ' ' | '|' | '`' | '-')).unwrap_or(line.len());
+        let Some(name) = line[start..].split_whitespace().next() else {
+            continue;
+        };
+        let depth = start / 2;
+
+        path.truncate(depth);
+        if let Some(parent) = path.last() {
+            let children = deps.entry(parent.clone()).or_default();
+            if !children.iter().any(|child| child == name) {
+                children.push(name.to_string());
+            }
+        }
+        path.push(name.to_string());
+    }
+    deps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pactree() {
+        let output = "\
+htop
+|-glibc
+| |-linux-api-headers
+| `-tzdata
+|-libnl
+| `-glibc
+`-ncurses
+  `-bash provides sh
+";
+        let deps = parse_pactree(output);
+        assert_eq!(deps["htop"], vec!["glibc", "libnl", "ncurses"]);
+        assert_eq!(deps["glibc"], vec!["linux-api-headers", "tzdata"]);
+        assert_eq!(deps["ncurses"], vec!["bash"]);
+
+        let tree = build_tree("htop", |pkg| deps.get(pkg).cloned());
+        assert_eq!(tree.dependency_count(), 6);
+        // glibc is only expanded the first time
+        assert!(tree.children[1].children[0].repeated);
+        assert!(tree.render().starts_with("htop\n├─ glibc\n│  ├─ linux-api-headers\n"));
+    }
+
+    #[test]
+    fn test_cycles_and_depth_are_capped() {
+        // a -> b -> a, and an endless chain below c
+        let tree = build_tree("a", |pkg| match pkg {
+            "a" => Some(vec!["b>=1.0".to_string(), "libc.so=6-64".to_string(), "c".to_string()]),
+            "b" => Some(vec!["a".to_string()]),
+            other => Some(vec![format!("{}x", other)]),
+        });
+
+        let names: Vec<&str> = tree.children.iter().map(|child| child.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "c"]);
+        assert!(tree.children[0].children[0].repeated);
+
+        // The way back to a isn't a dependency of a
+        let cycle = build_tree("a", |pkg| Some(vec![if pkg == "a" { "b" } else { "a" }.to_string()]));
+        assert_eq!(cycle.dependency_count(), 1);
+
+        let mut deepest = &tree.children[1];
+        let mut depth = 1;
+        while let Some(child) = deepest.children.first() {
+            deepest = child;
+            depth += 1;
+        }
+        assert_eq!(depth, MAX_DEPTH);
+        assert!(deepest.truncated);
+    }
+}
@@ -0,0 +1,149 @@
+//! Terra Store v1.0 - Favorites
+//!
+//! Packages starred with Ctrl+F. They sort above other search results
+//! and have a mode of their own, and are kept next to the history.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::database::write_atomically;
+use crate::paths;
+
+/// Starred package names
+#[derive(Debug, Default)]
+pub struct Favorites {
+    names: HashSet<String>,
+    /// Why the favorites file couldn't be read, if it couldn't
+    pub load_error: Option<String>,
+    /// Keep a copy of the file on disk before the next save overwrites it
+    backup_pending: bool,
+    /// File `save` writes to (`None` keeps the favorites in memory only)
+    path: Option<PathBuf>,
+}
+
+impl Favorites {
+    /// Get the favorites file path
+    fn path() -> Option<PathBuf> {
+        paths::data_file("favorites.json")
+    }
+
+    /// Load favorites from disk
+    pub fn load() -> Self {
+        match Self::path() {
+            Some(path) => Self::load_from(path),
+            None => Self::default(),
+        }
+    }
+
+    /// Load favorites from `path`, which `save` then writes back to
+    pub fn load_from(path: PathBuf) -> Self {
+        let favorites = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str::<Vec<String>>(&contents) {
+                Ok(names) => Self {
+                    names: names.into_iter().collect(),
+                    ..Self::default()
+                },
+                // Start empty, but keep the old file
+                Err(e) => Self {
+                    backup_pending: true,
+                    load_error: Some(format!(
+                        "{} is not a favorites list ({}), starting empty (the old file is kept as {})",
+                        path.display(),
+                        e,
+                        path.with_extension("json.bak").display()
+                    )),
+                    ..Self::default()
+                },
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => Self {
+                load_error: Some(format!("Couldn't read {}: {}", path.display(), e)),
+                ..Self::default()
+            },
+        };
+
+        Self { path: Some(path), ..favorites }
+    }
+
+    /// Save favorites to disk, sorted by name
+    ///
+    /// If the file couldn't be parsed on load, it is first copied to
+    /// `favorites.json.bak` rather than overwritten for good.
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if self.backup_pending {
+            if path.exists() {
+                fs::copy(path, path.with_extension("json.bak"))?;
+            }
+            self.backup_pending = false;
+        }
+        let mut names: Vec<&str> = self.names.iter().map(String::as_str).collect();
+        names.sort_unstable();
+        write_atomically(path, |writer| Ok(serde_json::to_writer_pretty(writer, &names)?))
+    }
+
+    /// Star `name`, or unstar it if it already is; returns whether it's
+    /// starred now
+    pub fn toggle(&mut self, name: &str) -> bool {
+        if self.names.remove(name) {
+            false
+        } else {
+            self.names.insert(name.to_string());
+            true
+        }
+    }
+
+    /// Check whether `name` is starred
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_favorites_round_trip() {
+        let dir = std::env::temp_dir().join(format!("terra-store-favorites-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("favorites.json");
+
+        let mut favorites = Favorites::load_from(path.clone());
+        assert!(favorites.is_empty());
+        assert!(favorites.load_error.is_none());
+        assert!(favorites.toggle("neovim"));
+        assert!(favorites.toggle("htop"));
+        assert!(!favorites.toggle("neovim"));
+        favorites.save().unwrap();
+
+        let loaded = Favorites::load_from(path.clone());
+        assert!(loaded.contains("htop"));
+        assert!(!loaded.contains("neovim"));
+        assert_eq!(loaded.len(), 1);
+
+        // A broken file is kept aside before the first save replaces it
+        fs::write(&path, "{ not json").unwrap();
+        let mut broken = Favorites::load_from(path.clone());
+        assert!(broken.load_error.as_ref().unwrap().contains("not a favorites list"));
+        broken.toggle("neovim");
+        broken.save().unwrap();
+        assert_eq!(fs::read_to_string(path.with_extension("json.bak")).unwrap(), "{ not json");
+        assert!(Favorites::load_from(path).contains("neovim"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,18 +3,22 @@
 //! Lazy-loaded Flatpak support via AppStream XML parsing.
 //! Only loads when user explicitly requests Universal mode.
 
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::Instant;
 
 use flate2::read::GzDecoder;
-use quick_xml::events::Event;
+use quick_xml::events::{BytesStart, Event};
 use quick_xml::Reader;
 
+use crate::database::{match_term, MatchTier};
+
 /// A Flatpak application entry
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct FlatpakApp {
     /// Application ID (e.g., org.mozilla.firefox)
     pub id: String,
@@ -22,6 +26,78 @@ pub struct FlatpakApp {
     pub name: String,
     /// Short description
     pub summary: String,
+    /// Newest release version (empty if unknown)
+    pub version: String,
+    /// SPDX license expression (empty if unknown)
+    pub license: String,
+    /// AppStream categories (e.g. Graphics, Office)
+    pub categories: Vec<String>,
+    /// Remote the app is listed by (empty if unknown)
+    pub remote: String,
+}
+
+/// Flatpak installation an app lives in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallScope {
+    /// Per-user installation (`--user`)
+    User,
+    /// System-wide installation (`--system`)
+    System,
+}
+
+impl InstallScope {
+    /// Flag selecting this installation on the flatpak command line
+    fn flag(self) -> &'static str {
+        match self {
+            InstallScope::User => "--user",
+            InstallScope::System => "--system",
+        }
+    }
+}
+
+/// How an app matched a query, best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchRank {
+    /// The query is the whole name or app ID
+    Exact,
+    /// The name starts with the query
+    NamePrefix,
+    /// The name contains the query
+    NameSubstring,
+    /// The app ID contains the query
+    Id,
+    /// The query's characters appear in order in the name
+    NameFuzzy,
+    /// Only the summary contains the query
+    Summary,
+}
+
+impl MatchRank {
+    /// Rank how `app` matches the (lowercase) query, with a fuzzy score
+    /// for `NameFuzzy` matches
+    fn of(app: &FlatpakApp, query_lower: &str, include_summary: bool) -> Option<(Self, u32)> {
+        if app.id.eq_ignore_ascii_case(query_lower) {
+            return Some((MatchRank::Exact, 0));
+        }
+
+        let name = match_term(&app.name, query_lower);
+        match name {
+            Some((MatchTier::Exact, _)) => return Some((MatchRank::Exact, 0)),
+            Some((MatchTier::Prefix, _)) => return Some((MatchRank::NamePrefix, 0)),
+            Some((MatchTier::Substring, _)) => return Some((MatchRank::NameSubstring, 0)),
+            _ => {}
+        }
+
+        if app.id.to_lowercase().contains(query_lower) {
+            Some((MatchRank::Id, 0))
+        } else if let Some((_, score)) = name {
+            Some((MatchRank::NameFuzzy, score))
+        } else if include_summary && app.summary.to_lowercase().contains(query_lower) {
+            Some((MatchRank::Summary, 0))
+        } else {
+            None
+        }
+    }
 }
 
 /// Flatpak database statistics
@@ -37,6 +113,8 @@ pub struct FlatpakStats {
 pub struct FlatpakDatabase {
     /// Applications (None = not loaded yet)
     apps: Option<Vec<FlatpakApp>>,
+    /// Locally installed app IDs and where they are installed
+    installed: HashMap<String, InstallScope>,
     /// Load statistics
     pub stats: FlatpakStats,
 }
@@ -47,6 +125,21 @@ impl FlatpakDatabase {
         Self::default()
     }
 
+    /// Create a database preloaded with `apps`
+    #[cfg(test)]
+    pub fn from_apps(apps: Vec<FlatpakApp>) -> Self {
+        Self {
+            apps: Some(apps),
+            ..Self::default()
+        }
+    }
+
+    /// Record `app_id` as installed without asking flatpak
+    #[cfg(test)]
+    pub fn mark_installed(&mut self, app_id: &str, scope: InstallScope) {
+        self.installed.insert(app_id.to_string(), scope);
+    }
+
     /// Check if Flatpak is installed
     pub fn is_available() -> bool {
         Command::new("flatpak")
@@ -68,9 +161,10 @@ impl FlatpakDatabase {
         }
 
         let start = Instant::now();
+        self.refresh_installed();
 
         // Try AppStream XML first (fastest)
-        if let Some(apps) = Self::parse_appstream() {
+        if let Some(apps) = Self::parse_appstream().map(dedupe) {
             self.stats = FlatpakStats {
                 app_count: apps.len(),
                 load_time_ms: start.elapsed().as_millis() as u64,
@@ -81,7 +175,7 @@ impl FlatpakDatabase {
         }
 
         // Fallback to flatpak CLI
-        if let Some(apps) = Self::parse_flatpak_cli() {
+        if let Some(apps) = Self::parse_flatpak_cli().map(dedupe) {
             self.stats = FlatpakStats {
                 app_count: apps.len(),
                 load_time_ms: start.elapsed().as_millis() as u64,
@@ -94,32 +188,87 @@ impl FlatpakDatabase {
         Err("Failed to load Flatpak database".to_string())
     }
 
-    /// Parse AppStream XML from Flathub
+    /// Reload the set of installed apps from `flatpak list`
+    pub fn refresh_installed(&mut self) {
+        let output = Command::new("flatpak")
+            .args(["list", "--app", "--columns=application,installation"])
+            .output();
+
+        self.installed = match output {
+            Ok(output) if output.status.success() => parse_installed(&String::from_utf8_lossy(&output.stdout)),
+            _ => HashMap::new(),
+        };
+    }
+
+    /// Installed version of `app_id`, from `flatpak list`
+    ///
+    /// `None` if it isn't installed or its metadata has no version.
+    pub fn installed_version(app_id: &str) -> Option<String> {
+        let output = Command::new("flatpak")
+            .args(["list", "--app", "--columns=application,version"])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        find_version(&String::from_utf8_lossy(&output.stdout), app_id)
+    }
+
+    /// Installation holding `app_id`, if it is installed
+    pub fn installed_scope(&self, app_id: &str) -> Option<InstallScope> {
+        self.installed.get(app_id).copied()
+    }
+
+    /// Check whether `app_id` is installed in any installation
+    pub fn is_installed(&self, app_id: &str) -> bool {
+        self.installed.contains_key(app_id)
+    }
+
+    /// Parse the AppStream XML of every configured remote
+    ///
+    /// Remotes live under `<installation>/appstream/<remote>/<arch>/active`
+    /// for both the system and the per-user installation.
     fn parse_appstream() -> Option<Vec<FlatpakApp>> {
-        // Common AppStream locations
-        let paths = [
-            PathBuf::from("/var/lib/flatpak/appstream/flathub/x86_64/active/appstream.xml.gz"),
-            PathBuf::from("/var/lib/flatpak/appstream/flathub/x86_64/active/appstream.xml"),
-        ];
-
-        for path in &paths {
-            if !path.exists() {
-                continue;
-            }
+        let mut roots = vec![PathBuf::from("/var/lib/flatpak/appstream")];
+        if let Some(data_dir) = dirs::data_dir() {
+            roots.push(data_dir.join("flatpak").join("appstream"));
+        }
 
-            let apps = if path.extension().map(|e| e == "gz").unwrap_or(false) {
-                Self::parse_gzipped_xml(path)
-            } else {
-                Self::parse_plain_xml(path)
+        let mut apps = Vec::new();
+        for root in roots {
+            let Ok(entries) = std::fs::read_dir(&root) else {
+                continue;
             };
-
-            if let Some(apps) = apps {
-                if !apps.is_empty() {
-                    return Some(apps);
+            for entry in entries.flatten() {
+                let remote = entry.file_name().to_string_lossy().to_string();
+                let active = entry.path().join(std::env::consts::ARCH).join("active");
+                let Some(mut remote_apps) = Self::parse_appstream_dir(&active) else {
+                    continue;
+                };
+                for app in &mut remote_apps {
+                    app.remote = remote.clone();
                 }
+                apps.append(&mut remote_apps);
             }
         }
 
+        if apps.is_empty() {
+            None
+        } else {
+            Some(apps)
+        }
+    }
+
+    /// Parse one remote's `active` AppStream directory
+    fn parse_appstream_dir(active: &Path) -> Option<Vec<FlatpakApp>> {
+        let gzipped = active.join("appstream.xml.gz");
+        if gzipped.exists() {
+            return Self::parse_gzipped_xml(&gzipped);
+        }
+        let plain = active.join("appstream.xml");
+        if plain.exists() {
+            return Self::parse_plain_xml(&plain);
+        }
         None
     }
 
@@ -147,9 +296,7 @@ impl FlatpakDatabase {
         let mut buf = Vec::with_capacity(1024);
 
         let mut in_component = false;
-        let mut current_id = String::new();
-        let mut current_name = String::new();
-        let mut current_summary = String::new();
+        let mut current = FlatpakApp::default();
         let mut current_tag = String::new();
 
         loop {
@@ -160,43 +307,36 @@ impl FlatpakDatabase {
 
                     if tag == "component" {
                         in_component = true;
-                        current_id.clear();
-                        current_name.clear();
-                        current_summary.clear();
+                        current = FlatpakApp::default();
                     }
 
                     if in_component {
+                        if tag == "release" {
+                            read_release_version(&e, &mut current);
+                        }
                         current_tag = tag;
                     }
                 }
-                Ok(Event::Text(e)) => {
-                    if in_component {
-                        let text = e.unescape().unwrap_or_default().to_string();
-                        match current_tag.as_str() {
-                            "id" => current_id = text,
-                            "name" => {
-                                if current_name.is_empty() {
-                                    current_name = text;
-                                }
-                            }
-                            "summary" => {
-                                if current_summary.is_empty() {
-                                    current_summary = text;
-                                }
-                            }
-                            _ => {}
-                        }
+                // Releases are usually self-closing: <release version=".."/>
+                Ok(Event::Empty(e)) if in_component && e.name().as_ref() == b"release" => {
+                    read_release_version(&e, &mut current);
+                }
+                Ok(Event::Text(e)) if in_component => {
+                    let text = e.unescape().unwrap_or_default().to_string();
+                    match current_tag.as_str() {
+                        "id" => current.id = text,
+                        "name" if current.name.is_empty() => current.name = text,
+                        "summary" if current.summary.is_empty() => current.summary = text,
+                        "project_license" => current.license = text,
+                        "category" => current.categories.push(text),
+                        _ => {}
                     }
                 }
                 Ok(Event::End(e)) => {
                     let name = e.name();
                     if name.as_ref() == b"component" && in_component {
-                        if !current_id.is_empty() && !current_name.is_empty() {
-                            apps.push(FlatpakApp {
-                                id: current_id.clone(),
-                                name: current_name.clone(),
-                                summary: current_summary.clone(),
-                            });
+                        if !current.id.is_empty() && !current.name.is_empty() {
+                            apps.push(std::mem::take(&mut current));
                         }
                         in_component = false;
                     }
@@ -216,7 +356,7 @@ impl FlatpakDatabase {
     /// Fallback: Parse from flatpak CLI
     fn parse_flatpak_cli() -> Option<Vec<FlatpakApp>> {
         let output = Command::new("flatpak")
-            .args(["remote-ls", "--app", "--columns=application,name,description"])
+            .args(["remote-ls", "--app", "--columns=application,origin,name,description"])
             .output()
             .ok()?;
 
@@ -224,21 +364,7 @@ impl FlatpakDatabase {
             return None;
         }
 
-        let text = String::from_utf8_lossy(&output.stdout);
-        let mut apps = Vec::new();
-
-        for line in text.lines() {
-            let parts: Vec<&str> = line.splitn(3, '\t').collect();
-            if parts.len() >= 2 {
-                apps.push(FlatpakApp {
-                    id: parts[0].to_string(),
-                    name: parts[1].to_string(),
-                    summary: parts.get(2).unwrap_or(&"").to_string(),
-                });
-            }
-        }
-
-        Some(apps)
+        Some(parse_remote_ls(&String::from_utf8_lossy(&output.stdout)))
     }
 
     /// Get app count (0 if not loaded)
@@ -247,27 +373,53 @@ impl FlatpakDatabase {
     }
 
     /// Search Flatpaks (only if loaded)
-    pub fn search(&self, query: &str, limit: usize) -> Vec<&FlatpakApp> {
+    ///
+    /// Results are ranked exact name or ID, name prefix, name substring,
+    /// ID substring, fuzzy name match (best score first), then summary
+    /// match, keeping load order within each rank. Summaries are only
+    /// searched when `include_summary` is set, and with `installed_only`
+    /// only installed apps match. An empty query matches every app.
+    ///
+    /// Returns indices for `get`.
+    pub fn search(&self, query: &str, limit: usize, include_summary: bool, installed_only: bool) -> Vec<usize> {
         let Some(apps) = &self.apps else {
             return Vec::new();
         };
 
         let query_lower = query.to_lowercase();
 
-        apps.iter()
-            .filter(|app| {
-                app.id.to_lowercase().contains(&query_lower)
-                    || app.name.to_lowercase().contains(&query_lower)
-            })
-            .take(limit)
-            .collect()
+        let mut ranked: Vec<((MatchRank, u32), usize)> = apps
+            .iter()
+            .enumerate()
+            .filter(|(_, app)| !installed_only || self.is_installed(&app.id))
+            .filter_map(|(idx, app)| MatchRank::of(app, &query_lower, include_summary).map(|rank| (rank, idx)))
+            .collect();
+
+        // Stable sort keeps load order within a rank
+        ranked.sort_by_key(|&((rank, score), _)| (rank, Reverse(score)));
+        ranked.into_iter().take(limit).map(|(_, idx)| idx).collect()
     }
 
-    /// Install a Flatpak
-    #[allow(dead_code)]
-    pub fn install(&self, app_id: &str) -> Result<(), String> {
+    /// Get an app by index
+    pub fn get(&self, idx: usize) -> Option<&FlatpakApp> {
+        self.apps.as_ref()?.get(idx)
+    }
+
+    /// Remote listing `app_id`, if known
+    pub fn remote_of(&self, app_id: &str) -> Option<&str> {
+        self.apps
+            .as_ref()?
+            .iter()
+            .find(|app| app.id == app_id && !app.remote.is_empty())
+            .map(|app| app.remote.as_str())
+    }
+
+    /// Install a Flatpak from `remote`
+    ///
+    /// An app that is already installed is updated instead of failing.
+    pub fn install(&self, app_id: &str, remote: &str) -> Result<(), String> {
         let status = Command::new("flatpak")
-            .args(["install", "-y", "flathub", app_id])
+            .args(["install", "-y", "--or-update", remote, app_id])
             .status()
             .map_err(|e| e.to_string())?;
 
@@ -278,6 +430,26 @@ impl FlatpakDatabase {
         }
     }
 
+    /// Uninstall a Flatpak
+    ///
+    /// Targets the installation it was found in, so an app installed
+    /// both per-user and system-wide only loses the copy that was listed.
+    pub fn uninstall(&self, app_id: &str) -> Result<(), String> {
+        let mut command = Command::new("flatpak");
+        command.args(["uninstall", "-y"]);
+        if let Some(scope) = self.installed_scope(app_id) {
+            command.arg(scope.flag());
+        }
+
+        let status = command.arg(app_id).status().map_err(|e| e.to_string())?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("Flatpak uninstall failed with code: {:?}", status.code()))
+        }
+    }
+
     /// Unload to free memory
     #[allow(dead_code)]
     pub fn unload(&mut self) {
@@ -286,6 +458,102 @@ impl FlatpakDatabase {
     }
 }
 
+/// Parse `flatpak remote-ls --columns=application,origin,name,description`
+/// output (the description comes last since it may contain tabs)
+fn parse_remote_ls(output: &str) -> Vec<FlatpakApp> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.splitn(4, '\t').collect();
+            if parts.len() < 3 {
+                return None;
+            }
+            Some(FlatpakApp {
+                id: parts[0].to_string(),
+                remote: parts[1].to_string(),
+                name: parts[2].to_string(),
+                summary: parts.get(3).unwrap_or(&"").to_string(),
+                ..FlatpakApp::default()
+            })
+        })
+        .collect()
+}
+
+/// Drop apps listed more than once (e.g. by several remotes), keeping
+/// the position of the first and the metadata of the richest
+fn dedupe(apps: Vec<FlatpakApp>) -> Vec<FlatpakApp> {
+    let mut positions: HashMap<String, usize> = HashMap::with_capacity(apps.len());
+    let mut unique: Vec<FlatpakApp> = Vec::with_capacity(apps.len());
+
+    for app in apps {
+        match positions.get(&app.id) {
+            Some(&pos) => {
+                if richness(&app) > richness(&unique[pos]) {
+                    unique[pos] = app;
+                }
+            }
+            None => {
+                positions.insert(app.id.clone(), unique.len());
+                unique.push(app);
+            }
+        }
+    }
+    unique
+}
+
+/// How much metadata an app has, summary first
+fn richness(app: &FlatpakApp) -> (bool, usize) {
+    let extras = [!app.version.is_empty(), !app.license.is_empty(), !app.categories.is_empty()];
+    (!app.summary.is_empty(), extras.iter().filter(|&&set| set).count())
+}
+
+/// Take the version of a component's first `<release>`
+///
+/// AppStream lists releases newest first, so later ones are ignored.
+fn read_release_version(release: &BytesStart, app: &mut FlatpakApp) {
+    if !app.version.is_empty() {
+        return;
+    }
+    if let Ok(Some(version)) = release.try_get_attribute("version") {
+        app.version = version.unescape_value().unwrap_or_default().to_string();
+    }
+}
+
+/// Version column of `app_id`'s row in `flatpak list
+/// --columns=application,version` output
+fn find_version(output: &str, app_id: &str) -> Option<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .find(|(id, _)| id.trim() == app_id)
+        .map(|(_, version)| version.trim())
+        .filter(|version| !version.is_empty())
+        .map(String::from)
+}
+
+/// Parse `flatpak list --columns=application,installation` output
+///
+/// Apps in both installations are recorded as per-user, matching the
+/// copy flatpak itself runs.
+fn parse_installed(output: &str) -> HashMap<String, InstallScope> {
+    let mut installed = HashMap::new();
+    for line in output.lines() {
+        let mut columns = line.split('\t').map(str::trim);
+        let Some(id) = columns.next().filter(|id| !id.is_empty()) else {
+            continue;
+        };
+        let scope = match columns.next() {
+            Some("user") => InstallScope::User,
+            _ => InstallScope::System,
+        };
+        let entry = installed.entry(id.to_string()).or_insert(scope);
+        if scope == InstallScope::User {
+            *entry = scope;
+        }
+    }
+    installed
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -302,4 +570,146 @@ mod tests {
         assert!(!db.is_loaded());
         assert_eq!(db.len(), 0);
     }
+
+    fn app(id: &str, name: &str, summary: &str) -> FlatpakApp {
+        FlatpakApp {
+            id: id.to_string(),
+            name: name.to_string(),
+            summary: summary.to_string(),
+            ..FlatpakApp::default()
+        }
+    }
+
+    #[test]
+    fn test_search_summary_and_ranking() {
+        let db = FlatpakDatabase {
+            apps: Some(vec![
+                app("com.github.maoschanz.drawing", "Drawing", "A simple paint program"),
+                app("org.kde.paintapp", "Krita Lite", "Digital art"),
+                app("com.example.Paint", "Paint", "Image editor"),
+            ]),
+            ..FlatpakDatabase::default()
+        };
+
+        let names = |include_summary| -> Vec<&str> {
+            db.search("paint", 10, include_summary, false)
+                .into_iter()
+                .filter_map(|idx| db.get(idx))
+                .map(|app| app.name.as_str())
+                .collect()
+        };
+
+        assert_eq!(names(false), vec!["Paint", "Krita Lite"]);
+        assert_eq!(names(true), vec!["Paint", "Krita Lite", "Drawing"]);
+    }
+
+    #[test]
+    fn test_search_prefers_name_prefix() {
+        let db = FlatpakDatabase::from_apps(vec![
+            app("org.example.fireworks", "Sparkler", "Fireworks simulator"),
+            app("net.example.Campfire", "Campfire", "Chat"),
+            app("org.mozilla.firefox", "Firefox", "Web browser"),
+            app("io.example.Recovery", "FileRescue", "Undelete files"),
+        ]);
+
+        let names: Vec<&str> = db
+            .search("fire", 10, false, false)
+            .into_iter()
+            .filter_map(|idx| db.get(idx))
+            .map(|app| app.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Firefox", "Campfire", "Sparkler", "FileRescue"]);
+
+        // A full app ID is an exact match
+        assert_eq!(db.search("org.mozilla.firefox", 10, false, false), vec![2]);
+    }
+
+    #[test]
+    fn test_parse_installed() {
+        let output = "org.mozilla.firefox\tsystem\norg.gimp.GIMP\tuser\norg.gimp.GIMP\tsystem\ncom.example.Bare\n";
+        let installed = parse_installed(output);
+
+        assert_eq!(installed.len(), 3);
+        assert_eq!(installed["org.mozilla.firefox"], InstallScope::System);
+        assert_eq!(installed["org.gimp.GIMP"], InstallScope::User);
+        assert_eq!(installed["com.example.Bare"], InstallScope::System);
+
+        let versions = "org.mozilla.firefox\t131.0\norg.example.NoVersion\t\n";
+        assert_eq!(find_version(versions, "org.mozilla.firefox").as_deref(), Some("131.0"));
+        assert_eq!(find_version(versions, "org.example.NoVersion"), None);
+        assert_eq!(find_version(versions, "org.gimp.GIMP"), None);
+    }
+
+    #[test]
+    fn test_parse_appstream_component() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<components version="0.8" origin="flathub">
+  <component type="desktop-application">
+    <id>org.gimp.GIMP</id>
+    <name>GNU Image Manipulation Program</name>
+    <name xml:lang="de">GNU-Bildbearbeitungsprogramm</name>
+    <summary>Create images and edit photographs</summary>
+    <project_license>GPL-3.0+ AND LGPL-3.0+</project_license>
+    <description><p>GIMP is an image editor.</p></description>
+    <categories>
+      <category>Graphics</category>
+      <category>2DGraphics</category>
+    </categories>
+    <releases>
+      <release version="2.10.38" timestamp="1714953600">
+        <description><p>Bug fixes</p></description>
+      </release>
+      <release version="2.10.36" timestamp="1699488000"/>
+    </releases>
+  </component>
+  <component type="desktop-application">
+    <id>com.example.Bare</id>
+    <name>Bare</name>
+    <releases><release version="0.1" timestamp="1"/></releases>
+  </component>
+</components>"#;
+
+        let apps = FlatpakDatabase::parse_xml_reader(xml.as_bytes()).unwrap();
+        assert_eq!(apps.len(), 2);
+
+        let gimp = &apps[0];
+        assert_eq!(gimp.id, "org.gimp.GIMP");
+        assert_eq!(gimp.name, "GNU Image Manipulation Program");
+        assert_eq!(gimp.summary, "Create images and edit photographs");
+        assert_eq!(gimp.license, "GPL-3.0+ AND LGPL-3.0+");
+        assert_eq!(gimp.categories, vec!["Graphics", "2DGraphics"]);
+        assert_eq!(gimp.version, "2.10.38");
+
+        assert_eq!(apps[1].version, "0.1");
+        assert!(apps[1].license.is_empty());
+        assert!(apps[1].categories.is_empty());
+    }
+
+    #[test]
+    fn test_dedupe_prefers_richest() {
+        let xml = r#"<components>
+  <component><id>org.gimp.GIMP</id><name>GIMP</name></component>
+  <component><id>org.kde.krita</id><name>Krita</name><summary>Digital painting</summary></component>
+  <component><id>org.gimp.GIMP</id><name>GIMP</name><summary>Image editor</summary></component>
+</components>"#;
+
+        let apps = dedupe(FlatpakDatabase::parse_xml_reader(xml.as_bytes()).unwrap());
+        let summaries: Vec<(&str, &str)> = apps.iter().map(|app| (app.id.as_str(), app.summary.as_str())).collect();
+        assert_eq!(summaries, vec![("org.gimp.GIMP", "Image editor"), ("org.kde.krita", "Digital painting")]);
+    }
+
+    #[test]
+    fn test_parse_remote_ls() {
+        let output = "org.gimp.GIMP\tflathub\tGIMP\tImage editor\twith a tab\norg.kde.krita\tkdeapps\tKrita\nbroken\n";
+        let apps = parse_remote_ls(output);
+
+        assert_eq!(apps.len(), 2);
+        assert_eq!(apps[0].remote, "flathub");
+        assert_eq!(apps[0].summary, "Image editor\twith a tab");
+        assert_eq!(apps[1].remote, "kdeapps");
+
+        let db = FlatpakDatabase::from_apps(apps);
+        assert_eq!(db.remote_of("org.kde.krita"), Some("kdeapps"));
+        assert_eq!(db.remote_of("com.example.Missing"), None);
+    }
 }
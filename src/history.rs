@@ -1,24 +1,94 @@
 //! Terra Store v1.0 - Installation History
 //!
 //! Tracks package installations for rollback and audit purposes.
+//! History can be exported as JSON or CSV and merged back in.
 
-use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
 
+use crate::database::write_atomically;
 use crate::package::PackageSource;
+use crate::paths;
 
-/// Maximum history entries to keep
+/// Maximum history entries to keep, unless configured otherwise
 const MAX_HISTORY_ENTRIES: usize = 500;
 
+/// History file schema version - increment when the layout changes
+///
+/// v0 files are a bare array of records (or an unversioned `records` object).
+const HISTORY_VERSION: u64 = 1;
+
+/// What a history record did to the package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Action {
+    #[default]
+    Install,
+    Remove,
+    /// Full system upgrade (recorded under the name `system`)
+    Upgrade,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Install => write!(f, "Install"),
+            Action::Remove => write!(f, "Remove"),
+            Action::Upgrade => write!(f, "Upgrade"),
+        }
+    }
+}
+
+/// Which outcomes the History view lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatusFilter {
+    #[default]
+    All,
+    Success,
+    Failed,
+}
+
+impl StatusFilter {
+    /// The filter after this one, wrapping around
+    pub fn next(self) -> Self {
+        match self {
+            StatusFilter::All => StatusFilter::Success,
+            StatusFilter::Success => StatusFilter::Failed,
+            StatusFilter::Failed => StatusFilter::All,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StatusFilter::All => "ALL",
+            StatusFilter::Success => "SUCCESS",
+            StatusFilter::Failed => "FAILED",
+        }
+    }
+
+    /// Check whether `record` passes this filter
+    pub fn matches(self, record: &InstallRecord) -> bool {
+        match self {
+            StatusFilter::All => true,
+            StatusFilter::Success => record.success,
+            StatusFilter::Failed => !record.success,
+        }
+    }
+}
+
 /// A single installation record
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InstallRecord {
     /// Package name
     pub name: String,
+    /// Whether the package was installed or removed
+    #[serde(default)]
+    pub action: Action,
     /// Package source
     pub source: PackageSource,
     /// Unix timestamp of installation
@@ -26,7 +96,11 @@ pub struct InstallRecord {
     /// Whether installation succeeded
     pub success: bool,
     /// Optional error message if failed
+    #[serde(default)]
     pub error: Option<String>,
+    /// Version installed, or removed (unknown for failed installs)
+    #[serde(default)]
+    pub version: Option<String>,
 }
 
 impl InstallRecord {
@@ -34,10 +108,12 @@ impl InstallRecord {
     pub fn success(name: impl Into<String>, source: PackageSource) -> Self {
         Self {
             name: name.into(),
+            action: Action::Install,
             source,
             timestamp: current_timestamp(),
             success: true,
             error: None,
+            version: None,
         }
     }
 
@@ -45,106 +121,398 @@ impl InstallRecord {
     pub fn failure(name: impl Into<String>, source: PackageSource, error: impl Into<String>) -> Self {
         Self {
             name: name.into(),
+            action: Action::Install,
             source,
             timestamp: current_timestamp(),
             success: false,
             error: Some(error.into()),
+            version: None,
         }
     }
 
+    /// Set the action this record describes
+    pub fn with_action(mut self, action: Action) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Set the package version this record describes
+    pub fn with_version(mut self, version: Option<String>) -> Self {
+        self.version = version;
+        self
+    }
+
     /// Format timestamp for display
     pub fn formatted_time(&self) -> String {
-        // Simple formatting - just show relative time
-        let now = current_timestamp();
-        let diff = now.saturating_sub(self.timestamp);
-
-        if diff < 60 {
-            "just now".to_string()
-        } else if diff < 3600 {
-            format!("{} min ago", diff / 60)
-        } else if diff < 86400 {
-            format!("{} hours ago", diff / 3600)
-        } else {
-            format!("{} days ago", diff / 86400)
+        relative_time(self.timestamp)
+    }
+
+    /// Absolute UTC time in ISO 8601 (`2024-03-09T14:05:00Z`)
+    pub fn iso_time(&self) -> String {
+        let (year, month, day) = civil_date(self.timestamp);
+        let secs = self.timestamp % 86400;
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )
+    }
+
+    /// UTC day of the record in ISO 8601 (`2024-03-09`)
+    pub fn iso_date(&self) -> String {
+        iso_date(self.timestamp)
+    }
+}
+
+/// UTC day of a unix timestamp in ISO 8601 (`2024-03-09`)
+pub fn iso_date(timestamp: u64) -> String {
+    let (year, month, day) = civil_date(timestamp);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// How long ago a unix timestamp was, coarsely ("3 min ago")
+pub fn relative_time(timestamp: u64) -> String {
+    let diff = current_timestamp().saturating_sub(timestamp);
+
+    if diff < 60 {
+        "just now".to_string()
+    } else if diff < 3600 {
+        format!("{} min ago", diff / 60)
+    } else if diff < 86400 {
+        format!("{} hours ago", diff / 3600)
+    } else {
+        format!("{} days ago", diff / 86400)
+    }
+}
+
+/// Proleptic Gregorian (year, month, day) of a unix timestamp, in UTC
+///
+/// Howard Hinnant's `civil_from_days`, which counts in 400-year eras
+/// starting on March 1st so leap days fall at the end of each year.
+fn civil_date(timestamp: u64) -> (u64, u64, u64) {
+    let days = timestamp / 86400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// File format for exporting and importing history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFormat {
+    /// The history file's own layout
+    Json,
+    /// One row per record: name, source, action, timestamp, success, error
+    Csv,
+}
+
+impl HistoryFormat {
+    /// Parse a `--format` value
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(HistoryFormat::Json),
+            "csv" => Some(HistoryFormat::Csv),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from a file extension, defaulting to JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => HistoryFormat::Csv,
+            _ => HistoryFormat::Json,
         }
     }
 }
 
+/// Header row of exported CSV
+const CSV_HEADER: &str = "name,source,action,timestamp,success,error,version";
+
+/// On-disk layout of the history file
+#[derive(Serialize)]
+struct HistoryFile<'a> {
+    version: u64,
+    records: &'a [InstallRecord],
+}
+
 /// Installation history manager
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug)]
 pub struct History {
     /// List of installation records (newest first)
     pub records: Vec<InstallRecord>,
+    /// Records dropped on load because they couldn't be parsed
+    pub skipped: usize,
+    /// Why the history file couldn't be loaded at all, if it couldn't
+    pub load_error: Option<String>,
+    /// Keep a copy of the file on disk before the next save overwrites it
+    backup_pending: bool,
+    /// Most records to keep
+    limit: usize,
+    /// File `save` writes to (`None` keeps the history in memory only)
+    path: Option<PathBuf>,
+    /// Records changed since the last save
+    unsaved: bool,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            records: Vec::new(),
+            skipped: 0,
+            load_error: None,
+            backup_pending: false,
+            limit: MAX_HISTORY_ENTRIES,
+            path: None,
+            unsaved: false,
+        }
+    }
 }
 
 impl History {
     /// Get the history file path
     fn path() -> Option<PathBuf> {
-        let data_dir = dirs::data_dir()?;
-        let terra_dir = data_dir.join("terra-store");
-        fs::create_dir_all(&terra_dir).ok()?;
-        Some(terra_dir.join("history.json"))
+        paths::data_file("history.json")
     }
 
     /// Load history from disk
     pub fn load() -> Self {
-        let path = match Self::path() {
-            Some(p) => p,
-            None => return Self::default(),
+        match Self::path() {
+            Some(path) => Self::load_from(path),
+            None => Self::default(),
+        }
+    }
+
+    /// Load history from `path`, which `save` then writes back to
+    pub fn load_from(path: PathBuf) -> Self {
+        let history = match fs::read_to_string(&path) {
+            Ok(contents) => match Self::parse(&contents) {
+                Some(history) => history,
+                // Unreadable as a whole - start fresh, but keep the old file
+                None => Self {
+                    backup_pending: true,
+                    load_error: Some(format!(
+                        "{} is not valid history, starting fresh (the old file is kept as {})",
+                        path.display(),
+                        path.with_extension("json.bak").display()
+                    )),
+                    ..Self::default()
+                },
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => Self {
+                load_error: Some(format!("Couldn't read {}: {}", path.display(), e)),
+                ..Self::default()
+            },
         };
 
-        if !path.exists() {
-            return Self::default();
-        }
+        Self { path: Some(path), ..history }
+    }
 
-        let file = match File::open(&path) {
-            Ok(f) => f,
-            Err(_) => return Self::default(),
+    /// Parse history JSON of any schema version, salvaging the good
+    /// records if some are malformed
+    ///
+    /// Records are parsed one by one so a single bad entry only costs
+    /// itself. Unknown fields (from newer versions) are ignored and missing
+    /// ones (from older versions) take their defaults. Returns `None` only
+    /// if the text isn't a history file at all.
+    fn parse(contents: &str) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(contents).ok()?;
+
+        let (version, raw) = match &value {
+            serde_json::Value::Array(raw) => (0, raw),
+            serde_json::Value::Object(fields) => (
+                fields.get("version").and_then(|v| v.as_u64()).unwrap_or(0),
+                fields.get("records")?.as_array()?,
+            ),
+            _ => return None,
         };
 
-        let reader = BufReader::new(file);
-        serde_json::from_reader(reader).unwrap_or_default()
+        let records: Vec<InstallRecord> = raw
+            .iter()
+            .filter_map(|record| InstallRecord::deserialize(record).ok())
+            .collect();
+        let skipped = raw.len() - records.len();
+
+        Some(Self {
+            records,
+            skipped,
+            // Saving rewrites the file as the current version, which would
+            // drop fields a newer release understands
+            backup_pending: skipped > 0 || version > HISTORY_VERSION,
+            ..Self::default()
+        })
+    }
+
+    /// Keep at most `limit` records, dropping the oldest ones now and
+    /// whenever `add` goes over
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self.records.truncate(limit);
+        self
     }
 
     /// Save history to disk
-    pub fn save(&self) -> std::io::Result<()> {
-        let path = match Self::path() {
-            Some(p) => p,
-            None => return Ok(()),
+    ///
+    /// If loading had to drop records, the original file is first copied
+    /// to `history.json.bak` so nothing is lost for good. The new file is
+    /// written next to it and renamed over it, so an interrupted save
+    /// leaves the previous history in place.
+    pub fn save(&mut self) -> std::io::Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
         };
 
-        let file = File::create(&path)?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, self)?;
+        if self.backup_pending {
+            if path.exists() {
+                fs::copy(&path, path.with_extension("json.bak"))?;
+            }
+            self.backup_pending = false;
+        }
+
+        write_atomically(&path, |writer| self.export(HistoryFormat::Json, writer))?;
+        self.unsaved = false;
         Ok(())
     }
 
+    /// Save if records changed since the last save
+    ///
+    /// Recording doesn't write the file itself, so a batch of installs
+    /// costs one write at the end rather than one per package.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.unsaved {
+            self.save()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Write every record (newest first) to `out`
+    pub fn export(&self, format: HistoryFormat, mut out: impl Write) -> io::Result<()> {
+        match format {
+            HistoryFormat::Json => {
+                let contents = HistoryFile {
+                    version: HISTORY_VERSION,
+                    records: &self.records,
+                };
+                serde_json::to_writer_pretty(&mut out, &contents)?;
+                writeln!(out)?;
+            }
+            HistoryFormat::Csv => {
+                writeln!(out, "{}", CSV_HEADER)?;
+                for record in &self.records {
+                    let row = [
+                        record.name.clone(),
+                        variant_name(&record.source),
+                        variant_name(&record.action),
+                        record.timestamp.to_string(),
+                        record.success.to_string(),
+                        record.error.clone().unwrap_or_default(),
+                        record.version.clone().unwrap_or_default(),
+                    ];
+                    let row: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+                    writeln!(out, "{}", row.join(","))?;
+                }
+            }
+        }
+        out.flush()
+    }
+
+    /// Parse exported history, returning its records and how many rows
+    /// couldn't be read
+    ///
+    /// Returns `None` if the text isn't history in that format at all.
+    pub fn parse_export(contents: &str, format: HistoryFormat) -> Option<(Vec<InstallRecord>, usize)> {
+        match format {
+            HistoryFormat::Json => Self::parse(contents).map(|history| (history.records, history.skipped)),
+            HistoryFormat::Csv => {
+                let mut rows = parse_csv(contents).into_iter();
+                // Exports from before the version column have the rest
+                let header = rows.next()?.join(",");
+                if header != CSV_HEADER && Some(header.as_str()) != CSV_HEADER.strip_suffix(",version") {
+                    return None;
+                }
+                let mut skipped = 0;
+                let records = rows
+                    .filter_map(|row| {
+                        let record = record_from_row(&row);
+                        skipped += usize::from(record.is_none());
+                        record
+                    })
+                    .collect();
+                Some((records, skipped))
+            }
+        }
+    }
+
+    /// Merge `records` into the history, returning how many were new
+    ///
+    /// Records already present (same name and timestamp) are skipped. The
+    /// result is sorted newest first and trimmed to the limit.
+    pub fn merge(&mut self, records: Vec<InstallRecord>) -> usize {
+        let mut seen: HashSet<(String, u64)> =
+            self.records.iter().map(|record| (record.name.clone(), record.timestamp)).collect();
+        let before = self.records.len();
+        self.records
+            .extend(records.into_iter().filter(|record| seen.insert((record.name.clone(), record.timestamp))));
+        let added = self.records.len() - before;
+        self.unsaved |= added > 0;
+
+        // Stable, so records sharing a timestamp keep their order
+        self.records.sort_by_key(|record| Reverse(record.timestamp));
+        self.records.truncate(self.limit);
+        added
+    }
+
     /// Add a new installation record
     pub fn add(&mut self, record: InstallRecord) {
         self.records.insert(0, record);
+        self.unsaved = true;
 
         // Trim to max size
-        if self.records.len() > MAX_HISTORY_ENTRIES {
-            self.records.truncate(MAX_HISTORY_ENTRIES);
-        }
+        self.records.truncate(self.limit);
+    }
+
+    /// Record a successful install or removal of `version` (saved by
+    /// the next `flush`)
+    pub fn record_success(&mut self, name: &str, source: PackageSource, action: Action, version: Option<String>) {
+        self.add(InstallRecord::success(name, source).with_action(action).with_version(version));
     }
 
-    /// Record a successful installation
-    pub fn record_success(&mut self, name: &str, source: PackageSource) {
-        self.add(InstallRecord::success(name, source));
-        let _ = self.save();
+    /// Record a failed install or removal (saved by the next `flush`)
+    pub fn record_failure(&mut self, name: &str, source: PackageSource, action: Action, error: &str) {
+        self.add(InstallRecord::failure(name, source, error).with_action(action));
     }
 
-    /// Record a failed installation
-    pub fn record_failure(&mut self, name: &str, source: PackageSource, error: &str) {
-        self.add(InstallRecord::failure(name, source, error));
-        let _ = self.save();
+    /// Indices of the records whose name contains `name` (ignoring case)
+    /// and that pass `status`, newest first
+    pub fn matching(&self, name: &str, status: StatusFilter) -> Vec<usize> {
+        let name = name.to_lowercase();
+        self.records
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| status.matches(record) && record.name.to_lowercase().contains(&name))
+            .map(|(i, _)| i)
+            .collect()
     }
 
-    /// Get recent installations (last N)
-    pub fn recent(&self, count: usize) -> &[InstallRecord] {
-        let end = count.min(self.records.len());
-        &self.records[..end]
+    /// The newest successful install of each package that wasn't removed
+    /// since, newest first
+    pub fn recent_installs(&self) -> impl Iterator<Item = &InstallRecord> {
+        let mut seen = HashSet::new();
+        self.records
+            .iter()
+            .filter(|record| record.success && record.action != Action::Upgrade)
+            .filter(move |record| seen.insert(record.name.as_str()))
+            .filter(|record| record.action == Action::Install)
     }
 
     /// Get count of successful installations
@@ -167,12 +535,83 @@ impl History {
     #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.records.clear();
-        let _ = self.save();
+        self.unsaved = true;
+    }
+}
+
+/// Serialized name of a unit enum variant (`Official`, `Remove`, ...)
+fn variant_name(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
     }
 }
 
+/// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Split CSV text into rows of fields, honoring quoted fields (which may
+/// hold commas, doubled quotes and line breaks)
+fn parse_csv(contents: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = contents.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Build a record from a CSV row in `CSV_HEADER` order (the version
+/// column is optional)
+fn record_from_row(row: &[String]) -> Option<InstallRecord> {
+    let (name, source, action, timestamp, success, error, version) = match row {
+        [name, source, action, timestamp, success, error] => (name, source, action, timestamp, success, error, None),
+        [name, source, action, timestamp, success, error, version] => {
+            (name, source, action, timestamp, success, error, Some(version))
+        }
+        _ => return None,
+    };
+    let variant = |name: &str| serde_json::Value::String(name.to_string());
+    Some(InstallRecord {
+        name: name.clone(),
+        source: PackageSource::deserialize(variant(source)).ok()?,
+        action: Action::deserialize(variant(action)).ok()?,
+        timestamp: timestamp.parse().ok()?,
+        success: success.parse().ok()?,
+        error: (!error.is_empty()).then(|| error.clone()),
+        version: version.filter(|version| !version.is_empty()).cloned(),
+    })
+}
+
 /// Get current unix timestamp
-fn current_timestamp() -> u64 {
+pub(crate) fn current_timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
@@ -190,6 +629,32 @@ mod tests {
         assert!(record.error.is_none());
     }
 
+    #[test]
+    fn test_iso_time() {
+        let mut record = InstallRecord::success("htop", PackageSource::Official);
+        record.timestamp = 0;
+        assert_eq!(record.iso_time(), "1970-01-01T00:00:00Z");
+
+        // Leap day, and the last second of a year
+        record.timestamp = 1_709_217_000;
+        assert_eq!(record.iso_time(), "2024-02-29T14:30:00Z");
+        record.timestamp = 1_735_689_599;
+        assert_eq!(record.iso_time(), "2024-12-31T23:59:59Z");
+        assert_eq!(record.iso_date(), "2024-12-31");
+    }
+
+    #[test]
+    fn test_action_defaults_to_install() {
+        let json = r#"{"name": "htop", "source": "Official", "timestamp": 1, "success": true, "error": null}"#;
+        let record: InstallRecord = serde_json::from_str(json).unwrap();
+        assert_eq!(record.action, Action::Install);
+        assert_eq!(record.version, None);
+
+        let record = InstallRecord::success("htop", PackageSource::Official).with_action(Action::Remove);
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains(r#""action":"Remove""#));
+    }
+
     #[test]
     fn test_history_add() {
         let mut history = History::default();
@@ -197,5 +662,176 @@ mod tests {
         history.add(InstallRecord::success("htop", PackageSource::Official));
         assert_eq!(history.records.len(), 2);
         assert_eq!(history.records[0].name, "htop"); // Newest first
+
+        // A configured limit drops the oldest records
+        let mut history = history.with_limit(1);
+        assert_eq!(history.records.len(), 1);
+        history.add(InstallRecord::success("paru", PackageSource::Aur));
+        assert_eq!(history.records[0].name, "paru");
+        assert_eq!(history.records.len(), 1);
+    }
+
+    #[test]
+    fn test_records_are_saved_on_flush() {
+        let dir = std::env::temp_dir().join(format!("terra-history-flush-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.json");
+        let mut history = History::load_from(path.clone());
+
+        history.record_success("htop", PackageSource::Official, Action::Install, None);
+        history.record_failure("paru", PackageSource::Aur, Action::Install, "exit 1");
+        assert!(!path.exists(), "recording alone doesn't write");
+
+        history.flush().unwrap();
+        assert_eq!(History::load_from(path.clone()).records.len(), 2);
+
+        // Nothing new, so nothing to write
+        fs::remove_file(&path).unwrap();
+        history.flush().unwrap();
+        assert!(!path.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_replaces_file_atomically() {
+        let dir = std::env::temp_dir().join(format!("terra-history-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.json");
+        let tmp = dir.join("history.json.tmp");
+        let mut history = History::load_from(path.clone());
+        history.add(InstallRecord::success("htop", PackageSource::Official));
+        history.save().unwrap();
+        assert!(!tmp.exists());
+
+        // A save that can't write its temp file leaves the old file alone
+        fs::create_dir(&tmp).unwrap();
+        history.add(InstallRecord::success("paru", PackageSource::Aur));
+        assert!(history.save().is_err());
+        let on_disk = History::load_from(path.clone());
+        assert_eq!(on_disk.records.len(), 1);
+        assert!(on_disk.load_error.is_none());
+
+        // A corrupt file is reported rather than silently emptied
+        fs::write(&path, "{ not json").unwrap();
+        assert!(History::load_from(path.clone()).load_error.unwrap().contains("not valid history"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_matching_filters_name_and_status() {
+        let mut history = History::default();
+        history.add(InstallRecord::success("neovim", PackageSource::Official));
+        history.add(InstallRecord::failure("neofetch-git", PackageSource::Aur, "exit 1"));
+        history.add(InstallRecord::success("htop", PackageSource::Official));
+
+        assert_eq!(history.matching("", StatusFilter::All), vec![0, 1, 2]);
+        assert_eq!(history.matching("NEO", StatusFilter::All), vec![1, 2]);
+        assert_eq!(history.matching("neo", StatusFilter::Success), vec![2]);
+        assert_eq!(history.matching("", StatusFilter::Failed), vec![1]);
+        assert!(history.matching("vim", StatusFilter::Failed).is_empty());
+    }
+
+    #[test]
+    fn test_recent_installs_skip_removed_and_failed() {
+        let mut history = History::default();
+        history.add(InstallRecord::success("htop", PackageSource::Official));
+        history.add(InstallRecord::success("neovim", PackageSource::Official));
+        history.add(InstallRecord::success("htop", PackageSource::Official).with_action(Action::Remove));
+        history.add(InstallRecord::failure("paru", PackageSource::Aur, "exit 1"));
+        history.add(InstallRecord::success("neovim", PackageSource::Official));
+        history.add(InstallRecord::success("btop", PackageSource::Official));
+
+        let names: Vec<&str> = history.recent_installs().map(|record| record.name.as_str()).collect();
+        assert_eq!(names, vec!["btop", "neovim"]);
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let mut history = History::default();
+        history.add(InstallRecord::success("htop", PackageSource::Official).with_version(Some("3.3.0-1".to_string())));
+        history.add(
+            InstallRecord::failure("paru", PackageSource::Aur, "error: \"paru\", exit 1\nretry").with_action(Action::Remove),
+        );
+
+        let mut out = Vec::new();
+        history.export(HistoryFormat::Csv, &mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        assert!(csv.starts_with("name,source,action,timestamp,success,error,version\n"));
+        assert!(csv.contains(",Aur,Remove,"));
+
+        let (records, skipped) = History::parse_export(&format!("{}broken,row\n", csv), HistoryFormat::Csv).unwrap();
+        assert_eq!(skipped, 1);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].error, history.records[0].error);
+        assert_eq!(records[0].action, Action::Remove);
+        assert_eq!(records[1].error, None);
+        assert_eq!(records[1].version.as_deref(), Some("3.3.0-1"));
+
+        // Exports without the version column still import
+        let old = "name,source,action,timestamp,success,error\nhtop,Official,Install,1,true,\n";
+        let (records, _) = History::parse_export(old, HistoryFormat::Csv).unwrap();
+        assert_eq!(records[0].version, None);
+
+        assert!(History::parse_export("name,source\n", HistoryFormat::Csv).is_none());
+    }
+
+    #[test]
+    fn test_merge_dedups_and_sorts() {
+        let at = |name: &str, timestamp| InstallRecord {
+            timestamp,
+            ..InstallRecord::success(name, PackageSource::Official)
+        };
+        let mut history = History::default().with_limit(3);
+        history.records = vec![at("htop", 30), at("vim", 10)];
+
+        let added = history.merge(vec![at("htop", 30), at("git", 20), at("zsh", 40), at("old", 5)]);
+        assert_eq!(added, 3);
+        let names: Vec<&str> = history.records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["zsh", "htop", "git"]);
+    }
+
+    #[test]
+    fn test_parse_skips_malformed_records() {
+        let contents = r#"{"records": [
+            {"name": "htop", "source": "Official", "timestamp": 1, "success": true, "error": null},
+            {"name": "broken", "source": "Nowhere", "timestamp": "soon"},
+            {"name": "paru", "source": "Aur", "timestamp": 2, "success": false, "error": "exit 1"}
+        ]}"#;
+
+        let history = History::parse(contents).unwrap();
+        let names: Vec<&str> = history.records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["htop", "paru"]);
+        assert_eq!(history.skipped, 1);
+        assert!(history.backup_pending);
+
+        assert!(History::parse("not json").is_none());
+    }
+
+    #[test]
+    fn test_parse_schema_versions() {
+        // v0: bare array, written before the action field existed
+        let v0 = r#"[{"name": "htop", "source": "Official", "timestamp": 1, "success": true}]"#;
+        let history = History::parse(v0).unwrap();
+        assert_eq!(history.records.len(), 1);
+        assert_eq!(history.records[0].action, Action::Install);
+        assert!(!history.backup_pending);
+
+        // v1: versioned wrapper
+        let v1 = r#"{"version": 1, "records": [
+            {"name": "htop", "action": "Remove", "source": "Official", "timestamp": 1, "success": true, "error": null}
+        ]}"#;
+        let history = History::parse(v1).unwrap();
+        assert_eq!(history.records[0].action, Action::Remove);
+        assert_eq!(history.skipped, 0);
+
+        // A newer file still loads, but is backed up before being rewritten
+        let v2 = r#"{"version": 2, "records": [
+            {"name": "htop", "source": "Official", "timestamp": 1, "success": true, "reason": "test"}
+        ]}"#;
+        let history = History::parse(v2).unwrap();
+        assert_eq!(history.records.len(), 1);
+        assert!(history.backup_pending);
     }
 }
@@ -0,0 +1,342 @@
+//! Terra Store v1.0 - Key Bindings
+//!
+//! Loads remappable keys from ~/.config/terra-store/keys.toml.
+//! Unset entries, and ones that can't be used, keep their default
+//! binding.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crossterm::event::KeyCode;
+use serde::Deserialize;
+
+use crate::config::describe_error;
+
+/// Actions that can be bound to a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Quit,
+    /// Cycle to the next mode
+    NextMode,
+    /// Install the selection (Enter in Search and Universal modes)
+    Install,
+    /// Cycle the source filter (Flatpak summary matching in Universal mode)
+    ToggleSource,
+    /// Rebuild the package database
+    Refresh,
+}
+
+impl KeyAction {
+    /// All actions, in lookup order
+    const ALL: [KeyAction; 5] = [
+        KeyAction::Quit,
+        KeyAction::NextMode,
+        KeyAction::Install,
+        KeyAction::ToggleSource,
+        KeyAction::Refresh,
+    ];
+
+    /// Name of the action in keys.toml
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyAction::Quit => "quit",
+            KeyAction::NextMode => "next_mode",
+            KeyAction::Install => "install",
+            KeyAction::ToggleSource => "toggle_source",
+            KeyAction::Refresh => "refresh",
+        }
+    }
+}
+
+/// Keys `ui::handle_key` gives a fixed meaning, which a remapped action
+/// would shadow
+const FIXED_KEYS: [KeyCode; 29] = [
+    KeyCode::F(2),
+    KeyCode::F(3),
+    KeyCode::F(4),
+    KeyCode::F(6),
+    KeyCode::F(8),
+    KeyCode::F(9),
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Backspace,
+    KeyCode::Char('1'),
+    KeyCode::Char('2'),
+    KeyCode::Char('3'),
+    KeyCode::Char('4'),
+    KeyCode::Char('j'),
+    KeyCode::Char('k'),
+    KeyCode::Char('g'),
+    KeyCode::Char('G'),
+    KeyCode::Char('t'),
+    KeyCode::Char('y'),
+    KeyCode::Char('f'),
+    KeyCode::Char('p'),
+];
+
+/// Key names as written in keys.toml
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct KeymapFile {
+    quit: Option<String>,
+    next_mode: Option<String>,
+    install: Option<String>,
+    toggle_source: Option<String>,
+    refresh: Option<String>,
+}
+
+impl KeymapFile {
+    /// Each action with the key name the file gives it
+    fn bindings(self) -> [(KeyAction, Option<String>); 5] {
+        [
+            (KeyAction::Quit, self.quit),
+            (KeyAction::NextMode, self.next_mode),
+            (KeyAction::Install, self.install),
+            (KeyAction::ToggleSource, self.toggle_source),
+            (KeyAction::Refresh, self.refresh),
+        ]
+    }
+}
+
+/// Key bound to each action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap {
+    pub quit: KeyCode,
+    pub next_mode: KeyCode,
+    pub install: KeyCode,
+    pub toggle_source: KeyCode,
+    pub refresh: KeyCode,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            quit: KeyCode::Esc,
+            next_mode: KeyCode::BackTab,
+            install: KeyCode::Enter,
+            toggle_source: KeyCode::Tab,
+            refresh: KeyCode::F(5),
+        }
+    }
+}
+
+impl Keymap {
+    /// Get the key bindings file path
+    fn path() -> Option<PathBuf> {
+        let config_dir = dirs::config_dir()?;
+        Some(config_dir.join("terra-store").join("keys.toml"))
+    }
+
+    /// Load key bindings from disk, with the reasons any were ignored
+    pub fn load() -> (Self, Vec<String>) {
+        match Self::path().and_then(|path| fs::read_to_string(path).ok()) {
+            Some(contents) => Self::parse(&contents),
+            None => (Self::default(), Vec::new()),
+        }
+    }
+
+    /// Parse key bindings from TOML text, with the reasons any were
+    /// ignored
+    ///
+    /// A binding keeps its default if the key name is unknown, the key
+    /// has a fixed meaning, it is a character key for quit (which would
+    /// type into the search instead), or another action has the key. A
+    /// file that isn't valid TOML keeps every default.
+    pub fn parse(contents: &str) -> (Self, Vec<String>) {
+        let file: KeymapFile = match toml::from_str(contents) {
+            Ok(file) => file,
+            Err(e) => return (Self::default(), vec![describe_error(&e)]),
+        };
+
+        let mut keymap = Self::default();
+        let mut problems = Vec::new();
+        let mut remapped = Vec::new();
+        for (action, name) in file.bindings() {
+            let Some(name) = name else {
+                continue;
+            };
+            let reason = match parse_key(&name) {
+                None => "not a key name".to_string(),
+                Some(code) if FIXED_KEYS.contains(&code) => format!("{} has a fixed meaning", key_name(code)),
+                Some(KeyCode::Char(_)) if action == KeyAction::Quit => {
+                    "quit needs a key that doesn't type text".to_string()
+                }
+                Some(code) => {
+                    *keymap.key_mut(action) = code;
+                    remapped.push((action, name));
+                    continue;
+                }
+            };
+            problems.push(format!("{} = \"{}\" ignored: {}", action.name(), name, reason));
+        }
+
+        // Remapped actions sharing a key all go back to their defaults,
+        // which may in turn clash with other remapped ones
+        loop {
+            let clashes: Vec<(KeyAction, KeyAction)> = remapped
+                .iter()
+                .filter_map(|&(action, _)| {
+                    let code = keymap.key(action);
+                    let other = KeyAction::ALL
+                        .into_iter()
+                        .find(|&other| other != action && keymap.key(other) == code)?;
+                    Some((action, other))
+                })
+                .collect();
+            if clashes.is_empty() {
+                break;
+            }
+
+            for (action, other) in clashes {
+                let code = keymap.key(action);
+                *keymap.key_mut(action) = Self::default().key(action);
+                let position = remapped.iter().position(|&(remapped, _)| remapped == action);
+                if let Some((_, name)) = position.map(|position| remapped.remove(position)) {
+                    problems.push(format!(
+                        "{} = \"{}\" ignored: {} is also bound to {}",
+                        action.name(),
+                        name,
+                        key_name(code),
+                        other.name()
+                    ));
+                }
+            }
+        }
+
+        (keymap, problems)
+    }
+
+    /// Key bound to `action`
+    pub fn key(&self, action: KeyAction) -> KeyCode {
+        match action {
+            KeyAction::Quit => self.quit,
+            KeyAction::NextMode => self.next_mode,
+            KeyAction::Install => self.install,
+            KeyAction::ToggleSource => self.toggle_source,
+            KeyAction::Refresh => self.refresh,
+        }
+    }
+
+    fn key_mut(&mut self, action: KeyAction) -> &mut KeyCode {
+        match action {
+            KeyAction::Quit => &mut self.quit,
+            KeyAction::NextMode => &mut self.next_mode,
+            KeyAction::Install => &mut self.install,
+            KeyAction::ToggleSource => &mut self.toggle_source,
+            KeyAction::Refresh => &mut self.refresh,
+        }
+    }
+
+    /// Action bound to `code`, if any
+    pub fn action(&self, code: KeyCode) -> Option<KeyAction> {
+        KeyAction::ALL.into_iter().find(|&action| self.key(action) == code)
+    }
+
+    /// Display name of the key bound to `action`, for footer hints
+    pub fn label(&self, action: KeyAction) -> String {
+        key_name(self.key(action))
+    }
+}
+
+/// Parse a key name such as `esc`, `f5` or `q`
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+
+    let lower = name.to_ascii_lowercase();
+    let code = match lower.as_str() {
+        "esc" | "escape" => KeyCode::Esc,
+        "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backtab" | "shift-tab" => KeyCode::BackTab,
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "insert" | "ins" => KeyCode::Insert,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "space" => KeyCode::Char(' '),
+        _ => {
+            let n = lower.strip_prefix('f')?.parse().ok()?;
+            KeyCode::F(n)
+        }
+    };
+    Some(code)
+}
+
+/// Short display name of a key
+fn key_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::BackTab => "S-Tab".to_string(),
+        KeyCode::PageUp => "PgUp".to_string(),
+        KeyCode::PageDown => "PgDn".to_string(),
+        KeyCode::Delete => "Del".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keymap() {
+        let (keymap, problems) = Keymap::parse("quit = \"F10\"\nrefresh = \"r\"\ninstall = \"nonsense\"");
+        assert_eq!(keymap.quit, KeyCode::F(10));
+        assert_eq!(keymap.refresh, KeyCode::Char('r'));
+        // Unrecognized names keep the default, and say so
+        assert_eq!(keymap.install, KeyCode::Enter);
+        assert_eq!(problems, vec!["install = \"nonsense\" ignored: not a key name"]);
+        assert_eq!(keymap.action(KeyCode::F(10)), Some(KeyAction::Quit));
+        assert_eq!(keymap.action(KeyCode::Esc), None);
+
+        assert_eq!(Keymap::parse(""), (Keymap::default(), Vec::new()));
+    }
+
+    #[test]
+    fn test_unusable_bindings_are_rejected() {
+        // Fixed keys, and characters for quit
+        let (keymap, problems) = Keymap::parse("refresh = \"F6\"\ninstall = \"up\"\nquit = \"q\"");
+        assert_eq!(keymap, Keymap::default());
+        assert_eq!(
+            problems,
+            vec![
+                "quit = \"q\" ignored: quit needs a key that doesn't type text",
+                "install = \"up\" ignored: Up has a fixed meaning",
+                "refresh = \"F6\" ignored: F6 has a fixed meaning",
+            ]
+        );
+
+        // Two actions on one key, or a key another action keeps
+        let (keymap, problems) = Keymap::parse("next_mode = \"F10\"\nrefresh = \"f10\"\ninstall = \"tab\"");
+        assert_eq!(keymap.next_mode, KeyCode::BackTab);
+        assert_eq!(keymap.refresh, KeyCode::F(5));
+        assert_eq!(keymap.install, KeyCode::Enter);
+        assert_eq!(problems.len(), 3);
+        assert!(problems.contains(&"install = \"tab\" ignored: Tab is also bound to toggle_source".to_string()));
+        assert!(problems.contains(&"refresh = \"f10\" ignored: F10 is also bound to next_mode".to_string()));
+
+        // A syntax error keeps every default
+        let (keymap, problems) = Keymap::parse("quit = ");
+        assert_eq!(keymap, Keymap::default());
+        assert_eq!(problems.len(), 1);
+    }
+}
@@ -3,12 +3,24 @@
 //! A native TUI package manager for Arch Linux.
 //! Features Zero-Stress indexing for instant package search.
 
+mod arena;
 mod auth;
+mod aur;
+mod cli;
+mod clipboard;
+mod config;
 mod database;
+mod deps;
+mod favorites;
 mod flatpak;
 mod history;
+mod keymap;
 mod package;
+mod paths;
 mod repos;
+mod search;
+mod session;
+mod signals;
 #[cfg(feature = "terraflow")]
 mod terraflow;
 mod theme;
@@ -16,14 +28,26 @@ mod ui;
 
 use std::io;
 use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
 
-use auth::AuthManager;
-use history::History;
+use auth::{AuthError, AuthManager};
+use cli::Command;
+use config::{describe_error, Config};
+use database::PackageDatabase;
+use favorites::Favorites;
+use keymap::Keymap;
+use flatpak::FlatpakDatabase;
+use history::{Action, History};
 use package::PackageSource;
-use repos::Repository;
+use repos::{Pacman, RepoError, Repository};
+use session::Session;
+use signals::Signals;
 #[cfg(feature = "terraflow")]
 use terraflow::TerraFlow;
-use ui::{draw, handle_input, init_terminal, restore_terminal, App, AppMode};
+use ui::{draw, handle_input, init_terminal, install_panic_hook, restore_terminal, App, PendingAction};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const ASCII_BANNER: &str = r#"
@@ -47,21 +71,36 @@ const ASCII_BANNER: &str = r#"
 "#;
 
 fn main() -> ExitCode {
+    // Subcommands run without the banner, auth or TUI
+    match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Tui) => {}
+        Ok(Command::Help) => {
+            println!("Terra Store v{} - a TUI package manager for Arch Linux\n", VERSION);
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("terra-store {}", VERSION);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Search(args)) => return cli::run_search(&args),
+        Ok(Command::HistoryExport { format, output }) => return cli::run_history_export(format, output.as_deref()),
+        Ok(Command::HistoryImport { path, format }) => return cli::run_history_import(&path, format),
+        Ok(Command::Audit { json }) => return cli::run_audit(json),
+        Err(e) => {
+            eprintln!("terra-store: {}", e);
+            eprintln!("Run 'terra-store --help' for usage.");
+            return ExitCode::from(2);
+        }
+    }
+
     // Print banner
     println!("{}", ASCII_BANNER);
     println!("   TERRA STORE v{} | Zero-Stress Edition", VERSION);
     println!("   ─────────────────────────────────────────────────────────\n");
 
-    // Initialize authentication
-    let mut auth = AuthManager::new();
-
-    if let Err(e) = auth.authenticate() {
-        eprintln!("\n   ✗ {}", e);
-        return ExitCode::from(1);
-    }
-
     // Run TUI mode
-    match run_tui(&mut auth) {
+    match run_tui() {
         Ok(_) => ExitCode::SUCCESS,
         Err(e) => {
             eprintln!("Error: {}", e);
@@ -70,26 +109,88 @@ fn main() -> ExitCode {
     }
 }
 
-fn run_tui(auth: &mut AuthManager) -> io::Result<()> {
-    // Initialize terminal
+fn run_tui() -> io::Result<()> {
+    // Initialize terminal, and put it back if anything panics
+    install_panic_hook();
     let mut terminal = init_terminal()?;
+    let signals = Signals::install()?;
 
     // Create app state
-    let mut app = App::new();
+    let (config, config_error) = match Config::load() {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(e)),
+    };
+    let helper_warning = repos::init_aur_helper(&config);
+    let (keymap, ignored_keys) = Keymap::load();
+    let mut app = App::new(config, keymap);
+
+    // Authentication is deferred until the first action that needs root
+    let mut auth = AuthManager::new(app.repo_manager.pacman.auth);
 
     // Show loading screen
     terminal.draw(|f| draw(f, &mut app))?;
 
-    // Load package database (uses binary cache if available)
-    app.load_database();
+    // Load package database (uses binary cache if available) on a worker
+    // thread, so the loading screen keeps drawing and Esc can abort startup
+    let start = Instant::now();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let loader = {
+        let cancel = Arc::clone(&cancel);
+        thread::spawn(move || PackageDatabase::load_or_build_cancellable(&cancel))
+    };
+
+    while !loader.is_finished() {
+        app.tick();
+        terminal.draw(|f| draw(f, &mut app))?;
+        handle_input(&mut app)?;
+
+        if app.should_quit || signals.take_interrupt() || signals.terminated() {
+            // The loader skips writing the cache once cancelled
+            cancel.store(true, Ordering::SeqCst);
+            restore_terminal(&mut terminal)?;
+            auth.shutdown();
+            println!("\n   Startup cancelled.\n");
+            return Ok(());
+        }
+    }
+
+    let database = loader
+        .join()
+        .map_err(|_| io::Error::other("package database loader panicked"))?;
+    app.finish_loading(database, start.elapsed());
 
     // Load installation history
-    app.history = History::load();
+    app.history = History::load().with_limit(app.config.history_limit);
+    if app.history.skipped > 0 {
+        app.status = format!(
+            "{} | ⚠ Skipped {} unreadable history records",
+            app.status, app.history.skipped
+        );
+    }
+    if let Some(error) = app.history.load_error.clone() {
+        app.notify(format!("⚠ {}", error));
+    }
+
+    app.favorites = Favorites::load();
+    if let Some(error) = app.favorites.load_error.clone() {
+        app.notify(format!("⚠ {}", error));
+    }
+
+    // Everything runs on the defaults until the file is fixed
+    if let Some(error) = config_error {
+        app.notify(format!("⚠ config.toml: {}", describe_error(&error)));
+    }
+    if let Some(warning) = helper_warning {
+        app.notify(format!("⚠ {}", warning));
+    }
+    for problem in ignored_keys {
+        app.notify(format!("⚠ keys.toml: {}", problem));
+    }
 
     // Try to auto-detect TerraFlow config (if feature enabled)
     #[cfg(feature = "terraflow")]
     {
-        app.terraflow = TerraFlow::auto_detect();
+        app.terraflow = TerraFlow::from_config(&app.config);
         if app.terraflow.is_some() {
             app.status = format!(
                 "{} | TerraFlow detected",
@@ -98,74 +199,335 @@ fn run_tui(auth: &mut AuthManager) -> io::Result<()> {
         }
     }
 
+    // Pick up where the last session left off
+    app.restore_session(Session::load());
+
     // Main event loop
     loop {
+        // Pick up finished background work and advance the spinner
+        app.poll_search();
+        app.poll_details();
+        app.poll_aur();
+        app.poll_rebuild();
+        app.poll_flatpak();
+        app.poll_updates();
+        app.poll_deps();
+        app.poll_pkgbuild();
+        app.tick();
+
         // Draw UI
         terminal.draw(|f| draw(f, &mut app))?;
 
         // Handle input
-        let should_break = handle_input(&mut app)?;
+        handle_input(&mut app)?;
 
-        if app.should_quit {
+        if app.should_quit || signals.take_interrupt() || signals.terminated() {
             break;
         }
 
-        if should_break && app.mode == AppMode::Search {
-            // User pressed Enter - install the selected package
-            if let Some((name, source)) = app.selected_package() {
-                let name = name.to_string(); // Clone before leaving TUI
-
-                // Temporarily restore terminal for installation output
-                restore_terminal(&mut terminal)?;
-
-                println!("\n   ═══════════════════════════════════════════════════════════");
-                println!("   Installing: {}", name);
-                println!("   ═══════════════════════════════════════════════════════════\n");
-
-                let result = match source {
-                    PackageSource::Official => app.repo_manager.pacman.install(&name),
-                    PackageSource::Aur => app.repo_manager.aur.install(&name),
-                };
-
-                match result {
-                    Ok(()) => {
-                        println!(
-                            "\n   ═══════════════════════════════════════════════════════════"
-                        );
-                        println!("   ✓ Successfully installed: {}", name);
-                        println!(
-                            "   ═══════════════════════════════════════════════════════════"
-                        );
-                        app.status = format!("✓ Installed {}", name);
-                        app.history.record_success(&name, source);
-                    }
-                    Err(e) => {
-                        println!(
-                            "\n   ═══════════════════════════════════════════════════════════"
-                        );
-                        eprintln!("   ✗ Installation failed: {}", e);
-                        println!(
-                            "   ═══════════════════════════════════════════════════════════"
-                        );
-                        app.status = format!("✗ Failed: {}", e);
-                        app.history.record_failure(&name, source, &e.to_string());
-                    }
+        if let Some(action) = app.pending_action.take() {
+            // Hand the terminal back (raw mode off) so pacman can prompt
+            restore_terminal(&mut terminal)?;
+
+            // Ask for the sudo password the first time it is needed; a
+            // failure cancels the action but keeps the TUI running
+            if action.needs_root() && !auth.is_authenticated() {
+                // Ctrl+C at the prompt is only seen once the read returns,
+                // and cancels like an empty password
+                let mut result = auth.authenticate();
+                if signals.take_interrupt() {
+                    result = Err(AuthError::Cancelled);
+                }
+                if let Err(e) = result {
+                    app.notify(format!("✗ {}", e));
+                    terminal = init_terminal()?;
+                    continue;
                 }
+            }
 
-                println!("\n   Press Enter to continue...");
-                let mut _input = String::new();
-                let _ = io::stdin().read_line(&mut _input);
+            run_action(&mut app, action);
 
-                // Re-initialize terminal
-                terminal = init_terminal()?;
-            }
+            println!("\n   Press Enter to continue...");
+            let mut _input = String::new();
+            let _ = io::stdin().read_line(&mut _input);
+
+            // A Ctrl+C meant for pacman doesn't quit Terra Store too
+            signals.take_interrupt();
+
+            // Re-initialize terminal
+            terminal = init_terminal()?;
         }
     }
 
+    let _ = app.session().save();
+
     // Cleanup
     restore_terminal(&mut terminal)?;
+    if app.is_busy() {
+        println!("\n   Waiting for background work to finish...");
+    }
+    app.shutdown();
     auth.shutdown();
 
     println!("\n   Goodbye!\n");
     Ok(())
 }
+
+/// Run a queued action with the terminal restored
+fn run_action(app: &mut App, action: PendingAction) {
+    match action {
+        PendingAction::Install { name, source } => {
+            install_package(app, &name, source);
+        }
+        PendingAction::InstallFlatpak { app_id, name, remote } => install_flatpak(app, &app_id, &name, &remote),
+        PendingAction::Remove { name, source } => {
+            remove_package(app, &name, source);
+        }
+        PendingAction::RemoveFlatpak { app_id, name } => remove_flatpak(app, &app_id, &name),
+        PendingAction::InstallBatch { packages } => install_batch(app, &packages),
+        #[cfg(feature = "terraflow")]
+        PendingAction::Sync { install, remove } => sync_system(app, &install, &remove),
+        PendingAction::Upgrade => upgrade_system(app),
+    }
+
+    // One write for the whole action, however many records it added
+    app.flush_history();
+    app.show_recent();
+
+    // Installs from Audit mode change what it reports; the action's
+    // outcome stays in the status line
+    #[cfg(feature = "terraflow")]
+    if app.mode == ui::AppMode::Audit {
+        let status = std::mem::take(&mut app.status);
+        app.run_audit();
+        app.status = status;
+    }
+}
+
+/// Upgrade the whole system, then rebuild the database since versions
+/// changed
+fn upgrade_system(app: &mut App) {
+    println!("\n   ═══════════════════════════════════════════════════════════");
+    println!("   Upgrading system");
+    println!("   ═══════════════════════════════════════════════════════════\n");
+
+    match app.repo_manager.upgrade_system(app.config.upgrade_aur) {
+        Ok(()) => {
+            println!("\n   ═══════════════════════════════════════════════════════════");
+            println!("   ✓ System upgraded");
+            println!("   ═══════════════════════════════════════════════════════════");
+            app.notify("✓ System upgraded");
+            app.history.record_success("system", PackageSource::Official, Action::Upgrade, None);
+        }
+        Err(e) => {
+            println!("\n   ═══════════════════════════════════════════════════════════");
+            eprintln!("   ✗ Upgrade failed: {}", e);
+            println!("   ═══════════════════════════════════════════════════════════");
+            app.notify(format!("✗ Upgrade failed: {}", e));
+            app.history.record_failure("system", PackageSource::Official, Action::Upgrade, &e.to_string());
+        }
+    }
+
+    // Even a failed upgrade may have replaced some packages
+    app.refresh_database();
+}
+
+/// Install packages one by one, carrying on past failures
+fn install_batch(app: &mut App, packages: &[(String, PackageSource)]) {
+    let mut failed = Vec::new();
+
+    for (i, (name, source)) in packages.iter().enumerate() {
+        println!("\n   [{}/{}]", i + 1, packages.len());
+        if !install_package(app, name, *source) {
+            failed.push(name.as_str());
+        }
+    }
+
+    let installed = packages.len() - failed.len();
+    println!("\n   ═══════════════════════════════════════════════════════════");
+    println!("   Batch complete: {} installed, {} failed", installed, failed.len());
+    if !failed.is_empty() {
+        println!("   Failed: {}", failed.join(", "));
+    }
+    println!("   ═══════════════════════════════════════════════════════════");
+
+    app.notify(if failed.is_empty() {
+        format!("✓ Installed {} packages", installed)
+    } else {
+        format!("⚠ Installed {}, failed {}: {}", installed, failed.len(), failed.join(", "))
+    });
+}
+
+/// Apply a TerraFlow sync plan: install the missing packages, then
+/// remove the extra ones, carrying on past failures
+#[cfg(feature = "terraflow")]
+fn sync_system(app: &mut App, install: &[(String, PackageSource)], remove: &[(String, PackageSource)]) {
+    if !install.is_empty() {
+        install_batch(app, install);
+    }
+    let install_status = std::mem::take(&mut app.status);
+
+    let mut failed = Vec::new();
+    for (i, (name, source)) in remove.iter().enumerate() {
+        println!("\n   [{}/{}]", i + 1, remove.len());
+        if !remove_package(app, name, *source) {
+            failed.push(name.as_str());
+        }
+    }
+
+    let removed = remove.len() - failed.len();
+    let mut status = install_status;
+    if !remove.is_empty() {
+        println!("\n   ═══════════════════════════════════════════════════════════");
+        println!("   Removals complete: {} removed, {} failed", removed, failed.len());
+        if !failed.is_empty() {
+            println!("   Failed: {}", failed.join(", "));
+        }
+        println!("   ═══════════════════════════════════════════════════════════");
+
+        let removals = if failed.is_empty() {
+            format!("removed {}", removed)
+        } else {
+            format!("removed {}, failed to remove {}: {}", removed, failed.len(), failed.join(", "))
+        };
+        status = if status.is_empty() { removals } else { format!("{} · {}", status, removals) };
+    }
+    app.notify(status);
+}
+
+/// Install a package, printing progress and recording the result
+///
+/// Returns whether the install succeeded.
+fn install_package(app: &mut App, name: &str, source: PackageSource) -> bool {
+    println!("\n   ═══════════════════════════════════════════════════════════");
+    println!("   Installing: {}", name);
+    println!("   ═══════════════════════════════════════════════════════════\n");
+
+    let result = match source {
+        PackageSource::Official => app.repo_manager.pacman.install(name),
+        PackageSource::Aur => app.repo_manager.aur.install(name),
+        PackageSource::Flatpak => {
+            let remote = app.flatpak_remote(name);
+            app.flatpak.install(name, &remote).map_err(RepoError::Flatpak)
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            println!("\n   ═══════════════════════════════════════════════════════════");
+            println!("   ✓ Successfully installed: {}", name);
+            println!("   ═══════════════════════════════════════════════════════════");
+            app.notify(format!("✓ Installed {}", name));
+            let version = match source {
+                PackageSource::Flatpak => FlatpakDatabase::installed_version(name),
+                _ => Pacman::installed_version(name),
+            };
+            app.history.record_success(name, source, Action::Install, version);
+            app.installed.insert(name.to_string());
+            true
+        }
+        Err(e) => {
+            println!("\n   ═══════════════════════════════════════════════════════════");
+            eprintln!("   ✗ Installation failed: {}", e);
+            println!("   ═══════════════════════════════════════════════════════════");
+            app.notify(format!("✗ Failed: {}", e));
+            app.history.record_failure(name, source, Action::Install, &e.to_string());
+            false
+        }
+    }
+}
+
+/// Install a Flatpak from `remote`, recording it in history by app ID
+fn install_flatpak(app: &mut App, app_id: &str, name: &str, remote: &str) {
+    println!("\n   ═══════════════════════════════════════════════════════════");
+    println!("   Installing Flatpak: {} ({} from {})", name, app_id, remote);
+    println!("   ═══════════════════════════════════════════════════════════\n");
+
+    match app.flatpak.install(app_id, remote) {
+        Ok(()) => {
+            println!("\n   ═══════════════════════════════════════════════════════════");
+            println!("   ✓ Successfully installed: {}", name);
+            println!("   ═══════════════════════════════════════════════════════════");
+            app.notify(format!("✓ Installed {}", name));
+            let version = FlatpakDatabase::installed_version(app_id);
+            app.history.record_success(app_id, PackageSource::Flatpak, Action::Install, version);
+            app.flatpak.refresh_installed();
+        }
+        Err(e) => {
+            println!("\n   ═══════════════════════════════════════════════════════════");
+            eprintln!("   ✗ Installation failed: {}", e);
+            println!("   ═══════════════════════════════════════════════════════════");
+            app.notify(format!("✗ Failed: {}", e));
+            app.history.record_failure(app_id, PackageSource::Flatpak, Action::Install, &e);
+        }
+    }
+}
+
+/// Uninstall a Flatpak, recording it in history by app ID
+fn remove_flatpak(app: &mut App, app_id: &str, name: &str) {
+    println!("\n   ═══════════════════════════════════════════════════════════");
+    println!("   Removing Flatpak: {} ({})", name, app_id);
+    println!("   ═══════════════════════════════════════════════════════════\n");
+
+    // Looked up first: once removed, the version is gone
+    let version = FlatpakDatabase::installed_version(app_id);
+    match app.flatpak.uninstall(app_id) {
+        Ok(()) => {
+            println!("\n   ═══════════════════════════════════════════════════════════");
+            println!("   ✓ Successfully removed: {}", name);
+            println!("   ═══════════════════════════════════════════════════════════");
+            app.notify(format!("✓ Removed {}", name));
+            app.history.record_success(app_id, PackageSource::Flatpak, Action::Remove, version);
+        }
+        Err(e) => {
+            println!("\n   ═══════════════════════════════════════════════════════════");
+            eprintln!("   ✗ Removal failed: {}", e);
+            println!("   ═══════════════════════════════════════════════════════════");
+            app.notify(format!("✗ Failed: {}", e));
+            app.history.record_failure(app_id, PackageSource::Flatpak, Action::Remove, &e);
+        }
+    }
+
+    // Another copy may remain in the other installation
+    app.flatpak.refresh_installed();
+}
+
+/// Remove a package, printing progress and recording the result
+///
+/// Returns whether the removal succeeded.
+fn remove_package(app: &mut App, name: &str, source: PackageSource) -> bool {
+    println!("\n   ═══════════════════════════════════════════════════════════");
+    println!("   Removing: {}", name);
+    println!("   ═══════════════════════════════════════════════════════════\n");
+
+    // Looked up first: once removed, the version is gone
+    let version = match source {
+        PackageSource::Flatpak => FlatpakDatabase::installed_version(name),
+        _ => Pacman::installed_version(name),
+    };
+    let result = match source {
+        PackageSource::Official => app.repo_manager.pacman.remove(name),
+        PackageSource::Aur => app.repo_manager.aur.remove(name),
+        PackageSource::Flatpak => app.flatpak.uninstall(name).map_err(RepoError::Flatpak),
+    };
+
+    match result {
+        Ok(()) => {
+            println!("\n   ═══════════════════════════════════════════════════════════");
+            println!("   ✓ Successfully removed: {}", name);
+            println!("   ═══════════════════════════════════════════════════════════");
+            app.notify(format!("✓ Removed {}", name));
+            app.history.record_success(name, source, Action::Remove, version);
+            app.installed.remove(name);
+            true
+        }
+        Err(e) => {
+            println!("\n   ═══════════════════════════════════════════════════════════");
+            eprintln!("   ✗ Removal failed: {}", e);
+            println!("   ═══════════════════════════════════════════════════════════");
+            app.notify(format!("✗ Failed: {}", e));
+            app.history.record_failure(name, source, Action::Remove, &e.to_string());
+            false
+        }
+    }
+}
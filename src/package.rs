@@ -3,16 +3,24 @@
 //! This module defines the core data types for representing packages
 //! and their metadata across different repository sources.
 
+use std::collections::HashSet;
+
 use serde::{Deserialize, Serialize};
 
 /// Represents the source repository of a package
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+///
+/// History files store the variant name and the binary cache stores the
+/// variant index, so new variants must be appended at the end to keep
+/// existing files loadable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 pub enum PackageSource {
     /// Official Arch Linux repositories (core, extra, multilib)
     #[default]
     Official,
     /// Arch User Repository (AUR)
     Aur,
+    /// Flatpak apps from Flathub
+    Flatpak,
 }
 
 impl std::fmt::Display for PackageSource {
@@ -20,6 +28,7 @@ impl std::fmt::Display for PackageSource {
         match self {
             PackageSource::Official => write!(f, "Official"),
             PackageSource::Aur => write!(f, "AUR"),
+            PackageSource::Flatpak => write!(f, "Flatpak"),
         }
     }
 }
@@ -82,59 +91,48 @@ pub struct PackageInfo {
     pub packager: String,
     pub build_date: String,
     pub install_reason: Option<String>,
+    /// Installed packages that depend on this one (`-Qi` only; `None`
+    /// when not looked up)
+    pub required_by: Option<Vec<String>>,
     pub source: PackageSource,
 }
 
 impl PackageInfo {
-    /// Parse package info from `pacman -Si` or `paru -Si` output
-    #[allow(dead_code)]
+    /// Parse package info from `pacman -Si`/`-Qi` or `paru -Si` output
+    ///
+    /// Long fields wrap onto indented continuation lines, which are
+    /// folded into the field above them. A package in several repos gets
+    /// one blank-line-separated block per repo; only the first is read.
     pub fn from_pacman_output(output: &str, source: PackageSource) -> Option<Self> {
         let mut info = PackageInfo {
             source,
             ..Default::default()
         };
+        let mut current_key = None;
 
         for line in output.lines() {
-            let line = line.trim();
-            if let Some((key, value)) = line.split_once(':') {
-                let key = key.trim();
-                let value = value.trim();
-
-                match key {
-                    "Name" => info.name = value.to_string(),
-                    "Version" => info.version = value.to_string(),
-                    "Description" => info.description = value.to_string(),
-                    "URL" => info.url = value.to_string(),
-                    "Licenses" => {
-                        info.licenses = value.split_whitespace().map(String::from).collect()
-                    }
-                    "Groups" => info.groups = value.split_whitespace().map(String::from).collect(),
-                    "Provides" => {
-                        info.provides = value.split_whitespace().map(String::from).collect()
-                    }
-                    "Depends On" => {
-                        info.depends = value.split_whitespace().map(String::from).collect()
-                    }
-                    "Optional Deps" => {
-                        info.optional_deps = value.split_whitespace().map(String::from).collect()
-                    }
-                    "Conflicts With" => {
-                        info.conflicts = value.split_whitespace().map(String::from).collect()
-                    }
-                    "Replaces" => {
-                        info.replaces = value.split_whitespace().map(String::from).collect()
-                    }
-                    "Download Size" => {
-                        info.download_size = parse_size(value);
-                    }
-                    "Installed Size" => {
-                        info.installed_size = parse_size(value);
-                    }
-                    "Packager" => info.packager = value.to_string(),
-                    "Build Date" => info.build_date = value.to_string(),
-                    _ => {}
+            if line.trim().is_empty() {
+                if current_key.is_some() {
+                    break;
                 }
+                continue;
             }
+
+            // Continuation lines are indented and belong to the last key
+            let (key, value) = if line.starts_with(char::is_whitespace) {
+                match current_key {
+                    Some(key) => (key, line.trim()),
+                    None => continue,
+                }
+            } else if let Some((key, value)) = line.split_once(':') {
+                let key = key.trim();
+                current_key = Some(key);
+                (key, value.trim())
+            } else {
+                continue;
+            };
+
+            info.add_field(key, value);
         }
 
         if info.name.is_empty() {
@@ -144,8 +142,36 @@ impl PackageInfo {
         }
     }
 
+    /// Add one line's worth of a `-Si` field
+    ///
+    /// List fields accumulate across lines (pacman's "None" adds nothing);
+    /// text fields join wrapped lines with a space.
+    fn add_field(&mut self, key: &str, value: &str) {
+        let words = || value.split_whitespace().filter(|w| *w != "None").map(String::from);
+
+        match key {
+            "Name" => append_text(&mut self.name, value),
+            "Version" => append_text(&mut self.version, value),
+            "Description" => append_text(&mut self.description, value),
+            "URL" => append_text(&mut self.url, value),
+            "Licenses" => self.licenses.extend(words()),
+            "Groups" => self.groups.extend(words()),
+            "Provides" => self.provides.extend(words()),
+            "Depends On" => self.depends.extend(words()),
+            "Required By" => self.required_by.get_or_insert_with(Vec::new).extend(words()),
+            // One "name: reason" entry per line
+            "Optional Deps" if value != "None" => self.optional_deps.push(value.to_string()),
+            "Conflicts With" => self.conflicts.extend(words()),
+            "Replaces" => self.replaces.extend(words()),
+            "Download Size" => self.download_size = parse_size(value),
+            "Installed Size" => self.installed_size = parse_size(value),
+            "Packager" => append_text(&mut self.packager, value),
+            "Build Date" => append_text(&mut self.build_date, value),
+            _ => {}
+        }
+    }
+
     /// Format the info for display in the preview pane
-    #[allow(dead_code)]
     pub fn to_display_string(&self) -> String {
         let mut output = String::new();
 
@@ -183,12 +209,111 @@ impl PackageInfo {
             }
         }
 
+        match self.required_by.as_deref() {
+            Some([]) => output.push_str("\n📋 Required By: nothing\n"),
+            Some(dependents) => {
+                output.push_str(&format!("\n⚠ Required By ({}):\n", dependents.len()));
+                for dependent in dependents {
+                    output.push_str(&format!("   • {}\n", dependent));
+                }
+            }
+            None => {}
+        }
+
         output
     }
 }
 
+/// An installed package with a newer version available
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageUpdate {
+    pub name: String,
+    pub old_version: String,
+    pub new_version: String,
+    pub source: PackageSource,
+}
+
+impl std::fmt::Display for PackageUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} -> {}", self.name, self.old_version, self.new_version)
+    }
+}
+
+/// A conflict that would make a batch install fail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// Queued package declaring the conflict
+    pub package: String,
+    /// Package it conflicts with
+    pub other: String,
+    /// Whether `other` is installed (otherwise it is queued too)
+    pub installed: bool,
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.installed {
+            write!(f, "{} conflicts with installed {}", self.package, self.other)
+        } else {
+            write!(f, "{} conflicts with queued {}", self.package, self.other)
+        }
+    }
+}
+
+/// Find conflicts among `queued` packages and against `installed` ones
+///
+/// Uses each package's `conflicts` list; version constraints like
+/// `foo<2.0` are ignored. A pair declaring each other is reported once.
+pub fn find_conflicts(queued: &[PackageInfo], installed: &HashSet<String>) -> Vec<Conflict> {
+    let mut found: Vec<Conflict> = Vec::new();
+
+    for info in queued {
+        for entry in &info.conflicts {
+            let other = entry
+                .split(['<', '>', '='])
+                .next()
+                .unwrap_or(entry);
+            if other == info.name {
+                continue;
+            }
+
+            let queued_other = queued
+                .iter()
+                .find(|q| q.name == other || q.provides.iter().any(|p| p == other));
+
+            if let Some(queued_other) = queued_other {
+                let reported = found
+                    .iter()
+                    .any(|c| c.package == queued_other.name && c.other == info.name);
+                if !reported {
+                    found.push(Conflict {
+                        package: info.name.clone(),
+                        other: queued_other.name.clone(),
+                        installed: false,
+                    });
+                }
+            } else if installed.contains(other) {
+                found.push(Conflict {
+                    package: info.name.clone(),
+                    other: other.to_string(),
+                    installed: true,
+                });
+            }
+        }
+    }
+
+    found
+}
+
+/// Append a wrapped line to a text field
+fn append_text(field: &mut String, value: &str) {
+    if !field.is_empty() {
+        field.push(' ');
+    }
+    field.push_str(value);
+}
+
 /// Parse size string like "1.5 MiB" to bytes
-#[allow(dead_code)]
 fn parse_size(s: &str) -> u64 {
     let parts: Vec<&str> = s.split_whitespace().collect();
     if parts.len() != 2 {
@@ -210,8 +335,7 @@ fn parse_size(s: &str) -> u64 {
 }
 
 /// Format bytes to human-readable size
-#[allow(dead_code)]
-fn format_size(bytes: u64) -> String {
+pub fn format_size(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
     const GB: u64 = MB * 1024;
@@ -227,10 +351,40 @@ fn format_size(bytes: u64) -> String {
     }
 }
 
+/// Format a count with thousands separators, e.g. `2,314`
+pub fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_source_serialization_is_stable() {
+        // History JSON uses variant names
+        assert_eq!(serde_json::to_string(&PackageSource::Aur).unwrap(), "\"Aur\"");
+        let source: PackageSource = serde_json::from_str("\"Flatpak\"").unwrap();
+        assert_eq!(source, PackageSource::Flatpak);
+
+        // The binary cache uses variant indices
+        for (source, index) in [
+            (PackageSource::Official, 0u32),
+            (PackageSource::Aur, 1),
+            (PackageSource::Flatpak, 2),
+        ] {
+            assert_eq!(bincode::serialize(&source).unwrap(), index.to_le_bytes());
+        }
+    }
+
     #[test]
     fn test_parse_size() {
         assert_eq!(parse_size("1.5 MiB"), 1572864);
@@ -242,5 +396,130 @@ mod tests {
     fn test_format_size() {
         assert_eq!(format_size(1572864), "1.50 MiB");
         assert_eq!(format_size(102400), "100.00 KiB");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(2314), "2,314");
+        assert_eq!(format_count(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn test_parse_wrapped_fields() {
+        let output = "\
+Repository      : extra
+Name            : htop
+Version         : 3.3.0-3
+Description     : Interactive process viewer
+Depends On      : glibc  libcap  libcap.so=2-64  libnl  ncurses  libncursesw.so=6-64
+                  libunwind  libunwind.so=8-64
+Optional Deps   : lm_sensors: show cpu temperatures
+                  lsof: show files opened by a process
+                  strace: attach to a running process
+Conflicts With  : None
+Download Size   : 160.58 KiB
+Installed Size  : 408.13 KiB
+";
+        let info = PackageInfo::from_pacman_output(output, PackageSource::Official).unwrap();
+
+        assert_eq!(info.name, "htop");
+        assert_eq!(
+            info.depends,
+            vec![
+                "glibc",
+                "libcap",
+                "libcap.so=2-64",
+                "libnl",
+                "ncurses",
+                "libncursesw.so=6-64",
+                "libunwind",
+                "libunwind.so=8-64",
+            ]
+        );
+        assert_eq!(
+            info.optional_deps,
+            vec![
+                "lm_sensors: show cpu temperatures",
+                "lsof: show files opened by a process",
+                "strace: attach to a running process",
+            ]
+        );
+        assert!(info.conflicts.is_empty());
+        assert_eq!(info.download_size, parse_size("160.58 KiB"));
+    }
+
+    #[test]
+    fn test_parse_first_of_several_repos() {
+        let output = "\
+Repository      : core-testing
+Name            : linux
+Version         : 6.12.1.arch1-1
+Depends On      : coreutils  kmod  initramfs
+Download Size   : 139.51 MiB
+
+Repository      : core
+Name            : linux
+Version         : 6.11.9.arch1-1
+Depends On      : coreutils  kmod  mkinitcpio
+Download Size   : 138.20 MiB
+
+";
+        let info = PackageInfo::from_pacman_output(output, PackageSource::Official).unwrap();
+
+        assert_eq!(info.name, "linux");
+        assert_eq!(info.version, "6.12.1.arch1-1");
+        assert_eq!(info.depends, vec!["coreutils", "kmod", "initramfs"]);
+        assert_eq!(info.download_size, parse_size("139.51 MiB"));
+    }
+
+    #[test]
+    fn test_parse_required_by() {
+        let output = "\
+Name            : glibc
+Version         : 2.40-1
+Required By     : bash  coreutils  curl
+                  zlib
+Optional For    : None
+";
+        let info = PackageInfo::from_pacman_output(output, PackageSource::Official).unwrap();
+        assert_eq!(info.required_by, Some(vec!["bash".into(), "coreutils".into(), "curl".into(), "zlib".into()]));
+        assert!(info.to_display_string().contains("Required By (4)"));
+
+        let leaf = PackageInfo::from_pacman_output("Name : htop\nRequired By : None\n", PackageSource::Official).unwrap();
+        assert_eq!(leaf.required_by, Some(Vec::new()));
+
+        // `-Si` output has no such field
+        let remote = PackageInfo::from_pacman_output("Name : htop\n", PackageSource::Official).unwrap();
+        assert_eq!(remote.required_by, None);
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let info = |name: &str, conflicts: &[&str]| PackageInfo {
+            name: name.to_string(),
+            conflicts: conflicts.iter().map(|c| c.to_string()).collect(),
+            ..PackageInfo::default()
+        };
+        let queued = [
+            info("pipewire-pulse", &["pulseaudio"]),
+            info("pulseaudio", &["pipewire-pulse"]),
+            info("vim-git", &["vim<9.1", "vim-git"]),
+        ];
+        let installed = HashSet::from(["vim".to_string()]);
+
+        let conflicts = find_conflicts(&queued, &installed);
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict {
+                    package: "pipewire-pulse".to_string(),
+                    other: "pulseaudio".to_string(),
+                    installed: false,
+                },
+                Conflict {
+                    package: "vim-git".to_string(),
+                    other: "vim".to_string(),
+                    installed: true,
+                },
+            ]
+        );
+        assert!(find_conflicts(&queued[2..], &HashSet::new()).is_empty());
     }
 }
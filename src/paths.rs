@@ -0,0 +1,61 @@
+//! Terra Store v1.0 - File Locations
+//!
+//! Everything Terra Store writes lives in one of two directories: the
+//! cache directory (package index) and the data directory (history,
+//! session). `TERRA_CACHE_DIR` and `TERRA_DATA_DIR` replace them;
+//! otherwise they are `terra-store` under the XDG cache and data dirs.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+/// Environment variable replacing the cache directory
+const CACHE_DIR_ENV: &str = "TERRA_CACHE_DIR";
+/// Environment variable replacing the data directory
+const DATA_DIR_ENV: &str = "TERRA_DATA_DIR";
+
+/// Path of `name` in the cache directory, which is created if needed
+pub fn cache_file(name: &str) -> Option<PathBuf> {
+    file_in(resolve(env::var_os(CACHE_DIR_ENV), dirs::cache_dir())?, name)
+}
+
+/// Path of `name` in the data directory, which is created if needed
+pub fn data_file(name: &str) -> Option<PathBuf> {
+    file_in(resolve(env::var_os(DATA_DIR_ENV), dirs::data_dir())?, name)
+}
+
+/// The override if it is set, else `terra-store` under `base`
+fn resolve(override_dir: Option<OsString>, base: Option<PathBuf>) -> Option<PathBuf> {
+    match override_dir.filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => Some(base?.join("terra-store")),
+    }
+}
+
+fn file_in(dir: PathBuf, name: &str) -> Option<PathBuf> {
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir.join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_prefers_override() {
+        let base = Some(PathBuf::from("/home/me/.cache"));
+        assert_eq!(resolve(Some("/tmp/terra".into()), base.clone()), Some(PathBuf::from("/tmp/terra")));
+        assert_eq!(resolve(Some("".into()), base.clone()), Some(PathBuf::from("/home/me/.cache/terra-store")));
+        assert_eq!(resolve(None, base), Some(PathBuf::from("/home/me/.cache/terra-store")));
+        assert_eq!(resolve(None, None), None);
+    }
+
+    #[test]
+    fn test_file_in_creates_dir() {
+        let dir = env::temp_dir().join(format!("terra-paths-{}", std::process::id())).join("nested");
+        assert_eq!(file_in(dir.clone(), "history.json"), Some(dir.join("history.json")));
+        assert!(dir.is_dir());
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
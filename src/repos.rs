@@ -3,12 +3,17 @@
 //! This module defines the `Repository` trait and implementations for
 //! Pacman (Official repos) and Paru (AUR).
 
+use std::collections::HashSet;
+use std::env;
 use std::io;
 use std::process::{Command, Stdio};
+use std::sync::OnceLock;
 
 use thiserror::Error;
 
-use crate::package::{Package, PackageInfo, PackageSource};
+use crate::auth::AuthBackend;
+use crate::config::Config;
+use crate::package::{Package, PackageInfo, PackageSource, PackageUpdate};
 
 #[derive(Error, Debug)]
 #[allow(dead_code)]
@@ -28,8 +33,17 @@ pub enum RepoError {
     #[error("Installation failed with exit code: {0}")]
     InstallFailed(i32),
 
+    #[error("Removal failed with exit code: {0}")]
+    RemoveFailed(i32),
+
+    #[error("Upgrade failed with exit code: {0}")]
+    UpgradeFailed(i32),
+
     #[error("AUR helper not installed. Please install paru or yay.")]
     AurHelperNotFound,
+
+    #[error("Flatpak error: {0}")]
+    Flatpak(String),
 }
 
 /// Trait defining the interface for package repositories
@@ -53,6 +67,9 @@ pub trait Repository {
     /// Install a package (with inherited stdout for progress display)
     fn install(&self, name: &str) -> Result<(), RepoError>;
 
+    /// Remove a package along with its unneeded dependencies
+    fn remove(&self, name: &str) -> Result<(), RepoError>;
+
     /// Search packages by name (returns matching packages with basic info)
     fn search(&self, query: &str) -> Result<Vec<Package>, RepoError>;
 }
@@ -62,11 +79,125 @@ pub trait Repository {
 // ============================================================================
 
 /// Official Arch Linux repository handler
-pub struct Pacman;
+pub struct Pacman {
+    /// Program that runs pacman as root
+    pub auth: AuthBackend,
+}
 
 impl Pacman {
     pub fn new() -> Self {
-        Self
+        Self {
+            auth: AuthBackend::default(),
+        }
+    }
+
+    /// Get the names of all locally installed packages (official and AUR)
+    pub fn installed_packages() -> HashSet<String> {
+        let mut installed = HashSet::new();
+
+        if let Ok(output) = Command::new("pacman").args(["-Qq"]).output() {
+            if output.status.success() {
+                let text = String::from_utf8_lossy(&output.stdout);
+                installed.extend(text.lines().filter(|l| !l.is_empty()).map(String::from));
+            }
+        }
+
+        installed
+    }
+
+    /// Installed version of `name` (official or AUR), from `pacman -Q`
+    pub fn installed_version(name: &str) -> Option<String> {
+        let output = Command::new("pacman").args(["-Q", name]).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        // `htop 3.3.0-1`
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout.split_whitespace().nth(1).map(String::from)
+    }
+
+    /// Installed packages that depend on `name`, from `pacman -Qi`
+    ///
+    /// Returns `None` if the package isn't installed.
+    pub fn required_by(name: &str) -> Option<Vec<String>> {
+        let output = Command::new("pacman").args(["-Qi", name]).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        PackageInfo::from_pacman_output(&stdout, PackageSource::Official)?.required_by
+    }
+
+    /// Installed packages that depend on each of `names`, from a single
+    /// `pacman -Qi` call
+    ///
+    /// Packages nothing depends on, or that aren't installed, are left out.
+    #[cfg(feature = "terraflow")]
+    pub fn required_by_all(names: &[String]) -> std::collections::HashMap<String, Vec<String>> {
+        if names.is_empty() {
+            return Default::default();
+        }
+        // pacman still prints the packages it found when some are missing
+        match Command::new("pacman").arg("-Qi").args(names).output() {
+            Ok(output) => parse_required_by(&String::from_utf8_lossy(&output.stdout)),
+            Err(_) => Default::default(),
+        }
+    }
+
+    /// Names of foreign packages (installed, but in no sync database),
+    /// usually from the AUR
+    ///
+    /// Asks the AUR helper when there is one, pacman otherwise.
+    #[cfg(feature = "terraflow")]
+    pub fn foreign_packages() -> HashSet<String> {
+        let program = aur_helper().unwrap_or_else(|| "pacman".to_string());
+        match Command::new(program).args(["-Qmq"]).output() {
+            Ok(output) if output.status.success() => {
+                String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect()
+            }
+            _ => HashSet::new(),
+        }
+    }
+
+    /// Arguments to `sudo`/`pkexec` for installing a package
+    ///
+    /// No `--noconfirm`: pacman's conflict and replacement prompts reach
+    /// the user, since the child inherits the terminal.
+    pub(crate) fn install_args(name: &str) -> [&str; 3] {
+        ["pacman", "-S", name]
+    }
+
+    /// Arguments to `sudo`/`pkexec` for removing a package
+    pub(crate) fn remove_args(name: &str) -> [&str; 3] {
+        ["pacman", "-Rns", name]
+    }
+
+    /// List pending updates from the local sync databases (`pacman -Qu`)
+    ///
+    /// pacman exits 1 when nothing is outdated, so only a failure to run
+    /// at all is an error.
+    pub fn check_updates(&self) -> Result<Vec<PackageUpdate>, RepoError> {
+        let output = Command::new("pacman").arg("-Qu").output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(parse_update_output(&stdout, PackageSource::Official))
+    }
+
+    /// Sync the databases and upgrade every official package
+    pub fn upgrade(&self) -> Result<(), RepoError> {
+        let status = Command::new(self.auth.program())
+            .args(["pacman", "-Syu"])
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(RepoError::UpgradeFailed(status.code().unwrap_or(-1)))
+        }
     }
 }
 
@@ -125,8 +256,8 @@ impl Repository for Pacman {
     }
 
     fn install(&self, name: &str) -> Result<(), RepoError> {
-        let status = Command::new("sudo")
-            .args(["pacman", "-S", "--noconfirm", name])
+        let status = Command::new(self.auth.program())
+            .args(Self::install_args(name))
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
@@ -139,6 +270,21 @@ impl Repository for Pacman {
         }
     }
 
+    fn remove(&self, name: &str) -> Result<(), RepoError> {
+        let status = Command::new(self.auth.program())
+            .args(Self::remove_args(name))
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(RepoError::RemoveFailed(status.code().unwrap_or(-1)))
+        }
+    }
+
     fn search(&self, query: &str) -> Result<Vec<Package>, RepoError> {
         let output = Command::new("pacman").args(["-Ss", query]).output()?;
 
@@ -158,43 +304,76 @@ impl Repository for Pacman {
 // ============================================================================
 
 /// AUR repository handler using paru
-pub struct Paru;
+pub struct Paru {
+    /// Program the helper uses to run pacman as root
+    pub auth: AuthBackend,
+}
 
 impl Paru {
     pub fn new() -> Self {
-        Self
+        Self {
+            auth: AuthBackend::default(),
+        }
     }
 
-    /// Check if paru is installed
-    fn paru_available() -> bool {
-        Command::new("paru")
-            .arg("--version")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .map(|s| s.success())
-            .unwrap_or(false)
+    /// Command running `helper` for a privileged operation
+    ///
+    /// paru and yay call sudo themselves, so pkexec is passed via `--sudo`.
+    fn privileged_command(&self, helper: &str) -> Command {
+        let mut command = Command::new(helper);
+        if self.auth == AuthBackend::Pkexec {
+            command.args(["--sudo", self.auth.program()]);
+        }
+        command
     }
 
-    /// Check if yay is installed as fallback
-    fn yay_available() -> bool {
-        Command::new("yay")
-            .arg("--version")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .map(|s| s.success())
-            .unwrap_or(false)
+    /// Arguments to the AUR helper for installing a package (interactive,
+    /// like `Pacman::install_args`)
+    pub(crate) fn install_args(name: &str) -> [&str; 2] {
+        ["-S", name]
+    }
+
+    /// Arguments to the AUR helper for removing a package
+    pub(crate) fn remove_args(name: &str) -> [&str; 2] {
+        ["-Rns", name]
+    }
+
+    /// List pending AUR updates (`-Qua`)
+    pub fn check_updates(&self) -> Result<Vec<PackageUpdate>, RepoError> {
+        let helper = aur_helper().ok_or(RepoError::AurHelperNotFound)?;
+        let output = Command::new(&helper).arg("-Qua").output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(parse_update_output(&stdout, PackageSource::Aur))
+    }
+
+    /// Print a package's PKGBUILD through the AUR helper (`-Gp`)
+    pub fn pkgbuild(name: &str) -> Result<String, RepoError> {
+        let helper = aur_helper().ok_or(RepoError::AurHelperNotFound)?;
+        let output = Command::new(&helper).args(["-Gp", name]).output()?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() || stdout.trim().is_empty() {
+            return Err(RepoError::PackageNotFound(name.to_string()));
+        }
+        Ok(stdout.into_owned())
     }
 
-    /// Get the available AUR helper command
-    fn get_helper() -> Option<&'static str> {
-        if Self::paru_available() {
-            Some("paru")
-        } else if Self::yay_available() {
-            Some("yay")
+    /// Upgrade every installed AUR package
+    pub fn upgrade(&self) -> Result<(), RepoError> {
+        let helper = aur_helper().ok_or(RepoError::AurHelperNotFound)?;
+
+        let status = self
+            .privileged_command(&helper)
+            .arg("-Sua")
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?;
+
+        if status.success() {
+            Ok(())
         } else {
-            None
+            Err(RepoError::UpgradeFailed(status.code().unwrap_or(-1)))
         }
     }
 }
@@ -215,13 +394,13 @@ impl Repository for Paru {
     }
 
     fn is_available(&self) -> bool {
-        Self::get_helper().is_some()
+        aur_helper().is_some()
     }
 
     fn list_packages(&self) -> Result<Vec<String>, RepoError> {
-        let helper = Self::get_helper().ok_or(RepoError::AurHelperNotFound)?;
+        let helper = aur_helper().ok_or(RepoError::AurHelperNotFound)?;
 
-        let output = Command::new(helper).args(["-Slq"]).output()?;
+        let output = Command::new(&helper).args(["-Slq"]).output()?;
 
         if !output.status.success() {
             return Err(RepoError::Unavailable(
@@ -238,9 +417,9 @@ impl Repository for Paru {
     }
 
     fn get_info(&self, name: &str) -> Result<PackageInfo, RepoError> {
-        let helper = Self::get_helper().ok_or(RepoError::AurHelperNotFound)?;
+        let helper = aur_helper().ok_or(RepoError::AurHelperNotFound)?;
 
-        let output = Command::new(helper).args(["-Si", name]).output()?;
+        let output = Command::new(&helper).args(["-Si", name]).output()?;
 
         if !output.status.success() {
             return Err(RepoError::PackageNotFound(name.to_string()));
@@ -251,10 +430,11 @@ impl Repository for Paru {
     }
 
     fn install(&self, name: &str) -> Result<(), RepoError> {
-        let helper = Self::get_helper().ok_or(RepoError::AurHelperNotFound)?;
+        let helper = aur_helper().ok_or(RepoError::AurHelperNotFound)?;
 
-        let status = Command::new(helper)
-            .args(["-S", "--noconfirm", name])
+        let status = self
+            .privileged_command(&helper)
+            .args(Self::install_args(name))
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
@@ -267,10 +447,28 @@ impl Repository for Paru {
         }
     }
 
+    fn remove(&self, name: &str) -> Result<(), RepoError> {
+        let helper = aur_helper().ok_or(RepoError::AurHelperNotFound)?;
+
+        let status = self
+            .privileged_command(&helper)
+            .args(Self::remove_args(name))
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(RepoError::RemoveFailed(status.code().unwrap_or(-1)))
+        }
+    }
+
     fn search(&self, query: &str) -> Result<Vec<Package>, RepoError> {
-        let helper = Self::get_helper().ok_or(RepoError::AurHelperNotFound)?;
+        let helper = aur_helper().ok_or(RepoError::AurHelperNotFound)?;
 
-        let output = Command::new(helper).args(["-Ss", query]).output()?;
+        let output = Command::new(&helper).args(["-Ss", query]).output()?;
 
         if !output.status.success() {
             return Ok(Vec::new());
@@ -287,9 +485,105 @@ impl Repository for Paru {
 // Helper Functions
 // ============================================================================
 
+/// Environment variable naming the AUR helper, overriding config.toml
+const AUR_HELPER_ENV: &str = "TERRA_AUR_HELPER";
+
+/// AUR helpers that take pacman's `-Slq`, `-Si` and `-Ss` (with `--aur`)
+/// as well as `--sudo <program>` and `-Gp`, in order of preference
+const SUPPORTED_HELPERS: [&str; 2] = ["paru", "yay"];
+
+/// The AUR helper, found once per run, and why the configured one
+/// wasn't used if it wasn't
+static AUR_HELPER: OnceLock<(Option<String>, Option<String>)> = OnceLock::new();
+
+/// Find the AUR helper from an already loaded config, so later
+/// `aur_helper` calls don't read config.toml again
+///
+/// Returns why the configured helper was passed over, for the caller to
+/// report. Only finds the helper the first time.
+pub fn init_aur_helper(config: &Config) -> Option<String> {
+    AUR_HELPER.get_or_init(|| find_aur_helper(config.aur_helper.as_deref())).1.clone()
+}
+
+/// The AUR helper to use, found on first use unless `init_aur_helper`
+/// already did
+///
+/// Front ends call `init_aur_helper` with the config they loaded (and
+/// reported errors in), so a config.toml that doesn't parse just gives
+/// the defaults here.
+pub fn aur_helper() -> Option<String> {
+    AUR_HELPER
+        .get_or_init(|| find_aur_helper(Config::load().unwrap_or_default().aur_helper.as_deref()))
+        .0
+        .clone()
+}
+
+/// Find the AUR helper to use
+///
+/// `TERRA_AUR_HELPER`, then `aur_helper` in config.toml, can pick one
+/// explicitly. A choice that isn't installed or isn't supported (e.g.
+/// aura or pikaur) falls back to paru, then yay, and says so.
+fn find_aur_helper(configured: Option<&str>) -> (Option<String>, Option<String>) {
+    let from_env = env::var(AUR_HELPER_ENV).ok().filter(|helper| !helper.is_empty());
+    let setting = if from_env.is_some() { AUR_HELPER_ENV } else { "aur_helper in config.toml" };
+    let (helper, rejected) = choose_aur_helper(from_env.as_deref().or(configured), helper_installed);
+
+    let warning = rejected.map(|reason| match &helper {
+        Some(helper) => format!("{}: {}, using {} instead", setting, reason, helper),
+        None => format!("{}: {}, and neither paru nor yay is installed", setting, reason),
+    });
+    (helper, warning)
+}
+
+/// Pick `configured` if it is usable, otherwise the first installed of
+/// paru and yay, along with why `configured` was passed over
+fn choose_aur_helper(
+    configured: Option<&str>,
+    installed: impl Fn(&str) -> bool,
+) -> (Option<String>, Option<String>) {
+    let rejected = match configured {
+        Some(helper) if !SUPPORTED_HELPERS.contains(&helper) => {
+            Some(format!("{} isn't supported (only paru and yay are)", helper))
+        }
+        Some(helper) if !installed(helper) => Some(format!("{} isn't installed", helper)),
+        Some(helper) => return (Some(helper.to_string()), None),
+        None => None,
+    };
+    let detected = SUPPORTED_HELPERS.into_iter().find(|helper| installed(helper));
+    (detected.map(str::to_string), rejected)
+}
+
+/// Check whether `helper --version` runs
+fn helper_installed(helper: &str) -> bool {
+    Command::new(helper)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Parse `pacman -Qu` / `paru -Qua` lines of the form
+/// `name oldver -> newver` (optionally followed by `[ignored]`)
+pub(crate) fn parse_update_output(output: &str, source: PackageSource) -> Vec<PackageUpdate> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let (name, old_version, arrow, new_version) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+            (arrow == "->").then(|| PackageUpdate {
+                name: name.to_string(),
+                old_version: old_version.to_string(),
+                new_version: new_version.to_string(),
+                source,
+            })
+        })
+        .collect()
+}
+
 /// Parse the output of `pacman -Ss` or `paru -Ss`
-#[allow(dead_code)]
-fn parse_pacman_search_output(output: &str, source: PackageSource) -> Vec<Package> {
+pub(crate) fn parse_pacman_search_output(output: &str, source: PackageSource) -> Vec<Package> {
     let mut packages = Vec::new();
     let mut lines = output.lines().peekable();
 
@@ -332,6 +626,17 @@ fn parse_pacman_search_output(output: &str, source: PackageSource) -> Vec<Packag
     packages
 }
 
+/// Dependents of each package in multi-package `pacman -Qi` output,
+/// whose blocks are separated by blank lines
+#[cfg(feature = "terraflow")]
+fn parse_required_by(output: &str) -> std::collections::HashMap<String, Vec<String>> {
+    output
+        .split("\n\n")
+        .filter_map(|block| PackageInfo::from_pacman_output(block, PackageSource::Official))
+        .filter_map(|info| Some((info.name, info.required_by.filter(|deps| !deps.is_empty())?)))
+        .collect()
+}
+
 /// Unified repository manager that can query both sources
 pub struct RepoManager {
     pub pacman: Pacman,
@@ -346,6 +651,50 @@ impl RepoManager {
         }
     }
 
+    /// Create a manager whose installs and upgrades run through `auth`
+    pub fn with_auth(auth: AuthBackend) -> Self {
+        Self {
+            pacman: Pacman { auth },
+            aur: Paru { auth },
+        }
+    }
+
+    /// Get package info from whichever repository `source` names
+    ///
+    /// Flatpaks have no `-Si` equivalent and report `PackageNotFound`.
+    pub fn get_info(&self, name: &str, source: PackageSource) -> Result<PackageInfo, RepoError> {
+        match source {
+            PackageSource::Official => self.pacman.get_info(name),
+            PackageSource::Aur => self.aur.get_info(name),
+            PackageSource::Flatpak => Err(RepoError::PackageNotFound(name.to_string())),
+        }
+    }
+
+    /// List pending updates of official packages and, when a helper is
+    /// installed, AUR packages
+    pub fn check_updates(&self) -> Result<Vec<PackageUpdate>, RepoError> {
+        let mut updates = self.pacman.check_updates()?;
+
+        if self.aur.is_available() {
+            if let Ok(aur_updates) = self.aur.check_updates() {
+                updates.extend(aur_updates);
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// Run a full system upgrade (`pacman -Syu`), then the AUR packages
+    /// too when `include_aur` is set and a helper is installed
+    pub fn upgrade_system(&self, include_aur: bool) -> Result<(), RepoError> {
+        self.pacman.upgrade()?;
+
+        if include_aur && self.aur.is_available() {
+            self.aur.upgrade()?;
+        }
+        Ok(())
+    }
+
     /// Get a list of all available packages from both sources
     #[allow(dead_code)]
     pub fn list_all(&self) -> Result<Vec<String>, RepoError> {
@@ -404,4 +753,71 @@ core/coreutils 9.4-3
         assert_eq!(packages[0].name, "neofetch");
         assert_eq!(packages[1].name, "coreutils");
     }
+
+    #[test]
+    fn test_choose_aur_helper() {
+        let only = |installed: &'static [&'static str]| move |helper: &str| installed.contains(&helper);
+
+        let chosen = |configured, installed| choose_aur_helper(configured, installed).0;
+
+        assert_eq!(chosen(None, only(&["yay", "paru"])), Some("paru".to_string()));
+        assert_eq!(chosen(Some("yay"), only(&["yay", "paru"])), Some("yay".to_string()));
+        // Unsupported or missing choices fall back to detection
+        assert_eq!(chosen(Some("aura"), only(&["aura", "yay"])), Some("yay".to_string()));
+        assert_eq!(chosen(Some("pikaur"), only(&["pikaur", "paru"])), Some("paru".to_string()));
+        assert_eq!(chosen(Some("yay"), only(&["paru"])), Some("paru".to_string()));
+        assert_eq!(chosen(None, only(&[])), None);
+
+        // and say why
+        assert_eq!(choose_aur_helper(None, only(&["paru"])).1, None);
+        assert_eq!(
+            choose_aur_helper(Some("pikaur"), only(&["pikaur", "paru"])).1.as_deref(),
+            Some("pikaur isn't supported (only paru and yay are)")
+        );
+        assert_eq!(
+            choose_aur_helper(Some("yay"), only(&[])),
+            (None, Some("yay isn't installed".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_install_args_are_interactive() {
+        assert_eq!(Pacman::install_args("neofetch"), ["pacman", "-S", "neofetch"]);
+        assert_eq!(Paru::install_args("yay-bin"), ["-S", "yay-bin"]);
+        assert!(!Pacman::remove_args("htop").contains(&"--noconfirm"));
+        assert!(!Paru::remove_args("htop").contains(&"--noconfirm"));
+    }
+
+    #[test]
+    #[cfg(feature = "terraflow")]
+    fn test_parse_required_by_blocks() {
+        let output = "\
+Name            : python
+Version         : 3.12.7-1
+Required By     : meson  python-pip
+                  python-setuptools
+
+Name            : htop
+Version         : 3.3.0-1
+Required By     : None
+
+";
+        let required_by = parse_required_by(output);
+        assert_eq!(required_by.len(), 1);
+        assert_eq!(required_by["python"], vec!["meson", "python-pip", "python-setuptools"]);
+    }
+
+    #[test]
+    fn test_parse_update_output() {
+        let output = "linux 6.7.1.arch1-1 -> 6.7.2.arch1-1
+firefox 121.0-1 -> 122.0-1 [ignored]
+:: warning line
+";
+        let updates = parse_update_output(output, PackageSource::Official);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].name, "linux");
+        assert_eq!(updates[0].old_version, "6.7.1.arch1-1");
+        assert_eq!(updates[0].new_version, "6.7.2.arch1-1");
+        assert_eq!(updates[1].to_string(), "firefox 121.0-1 -> 122.0-1");
+    }
 }
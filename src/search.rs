@@ -0,0 +1,269 @@
+//! Terra Store v1.0 - Background Search
+//!
+//! Runs package searches on worker threads so typing never waits on a scan.
+//! Every new query bumps a generation counter: an in-flight scan notices the
+//! change and aborts, and any result that still arrives late is discarded.
+//!
+//! Typing is made cheap by narrowing rather than debouncing: when a query
+//! extends the last completed one (same options, same database) and that
+//! search returned fewer matches than its limit, the new scan only visits
+//! those earlier matches. Every term match is monotone (a longer term can
+//! only match a subset), so the narrowed scan finds exactly what a full
+//! scan would. A truncated earlier result set forces a full scan, and so
+//! does a regex query, whose matches aren't monotone.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::database::{is_regex_query, PackageDatabase, SearchOptions};
+
+/// A finished search
+#[derive(Debug)]
+pub struct SearchResponse {
+    /// Generation the search was started with
+    pub generation: u64,
+    /// Matching indices into the database
+    pub results: Vec<usize>,
+    /// Total matches before the result limit was applied
+    pub total: usize,
+    /// Time spent scanning
+    pub elapsed: Duration,
+    /// Number of packages visited by the scan, for tests to check that
+    /// an extended query only revisits the previous matches
+    #[cfg(test)]
+    pub scanned: usize,
+}
+
+/// Names a search is restricted to (the installed packages)
+pub type NameFilter = Arc<HashSet<String>>;
+
+/// A search whose result set can seed the next one
+struct SearchInput {
+    database: Arc<PackageDatabase>,
+    query: String,
+    options: SearchOptions,
+    only: Option<NameFilter>,
+}
+
+impl SearchInput {
+    /// Check whether every match of `next` is among this search's matches
+    fn covers(&self, next: &SearchInput) -> bool {
+        let same_filter = match (&self.only, &next.only) {
+            (None, None) => true,
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        };
+        Arc::ptr_eq(&self.database, &next.database)
+            && self.options == next.options
+            && same_filter
+            && next.query.starts_with(self.query.as_str())
+            // Extending a pattern can widen its matches (`/lib` -> `/lib|gtk`)
+            && !is_regex_query(&next.query)
+    }
+}
+
+/// Dispatches searches to worker threads and collects the latest result
+pub struct SearchWorker {
+    /// Generation of the most recent request
+    generation: Arc<AtomicU64>,
+    /// Whether the most recent request is still running
+    in_flight: bool,
+    /// Input of the most recent request
+    pending: Option<SearchInput>,
+    /// Last completed search and its complete (untruncated) results
+    last: Option<(SearchInput, Arc<Vec<usize>>)>,
+    tx: Sender<SearchResponse>,
+    rx: Receiver<SearchResponse>,
+}
+
+impl SearchWorker {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            generation: Arc::new(AtomicU64::new(0)),
+            in_flight: false,
+            pending: None,
+            last: None,
+            tx,
+            rx,
+        }
+    }
+
+    /// Start a search, superseding any search still in flight
+    ///
+    /// With `only`, packages whose name isn't in the set are skipped.
+    /// Narrows the last completed result set when possible (see module docs).
+    pub fn submit(
+        &mut self,
+        database: Arc<PackageDatabase>,
+        query: String,
+        options: SearchOptions,
+        only: Option<NameFilter>,
+    ) -> u64 {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.in_flight = true;
+        let current = Arc::clone(&self.generation);
+        let tx = self.tx.clone();
+
+        let input = SearchInput {
+            database: Arc::clone(&database),
+            query: query.clone(),
+            options,
+            only: only.clone(),
+        };
+        let candidates = self
+            .last
+            .as_ref()
+            .filter(|(last, _)| last.covers(&input))
+            .map(|(_, results)| Arc::clone(results));
+        self.pending = Some(input);
+
+        thread::spawn(move || {
+            let start = Instant::now();
+            let superseded = || current.load(Ordering::SeqCst) != generation;
+            #[cfg(test)]
+            let scanned = candidates.as_ref().map_or(database.len(), |c| c.len());
+            let keep = |name: &str| only.as_ref().is_none_or(|names| names.contains(name));
+
+            if let Some(results) = database.search_cancellable(
+                &query,
+                &options,
+                candidates.as_deref().map(Vec::as_slice),
+                keep,
+                superseded,
+            ) {
+                let _ = tx.send(SearchResponse {
+                    generation,
+                    results: results.indices,
+                    total: results.total,
+                    elapsed: start.elapsed(),
+                    #[cfg(test)]
+                    scanned,
+                });
+            }
+        });
+
+        generation
+    }
+
+    /// Abort the search in flight without starting a new one
+    pub fn cancel(&mut self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.in_flight = false;
+    }
+
+    /// Take the result of the latest search if it has finished
+    ///
+    /// Results of superseded searches are drained and dropped.
+    pub fn poll(&mut self) -> Option<SearchResponse> {
+        let latest = self.generation.load(Ordering::SeqCst);
+        let mut found = None;
+
+        while let Ok(response) = self.rx.try_recv() {
+            if response.generation == latest {
+                found = Some(response);
+            }
+        }
+
+        if let Some(response) = &found {
+            self.in_flight = false;
+            // Only an untruncated result set is complete enough to narrow
+            self.last = self
+                .pending
+                .take()
+                .filter(|_| response.total == response.results.len())
+                .map(|input| (input, Arc::new(response.results.clone())));
+        }
+        found
+    }
+
+    /// Check whether the latest search is still running
+    pub fn is_pending(&self) -> bool {
+        self.in_flight
+    }
+}
+
+impl Default for SearchWorker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::PackageSource;
+
+    fn wait_for(worker: &mut SearchWorker) -> SearchResponse {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(response) = worker.poll() {
+                return response;
+            }
+            assert!(Instant::now() < deadline, "search timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn options(limit: usize) -> SearchOptions {
+        SearchOptions {
+            source_filter: None,
+            limit,
+            descriptions: false,
+        }
+    }
+
+    #[test]
+    fn test_superseded_search_is_dropped() {
+        let names: Vec<String> = (0..50_000).map(|i| format!("package-{}", i)).collect();
+        let db = Arc::new(PackageDatabase::from_names(&names, PackageSource::Official));
+        let mut worker = SearchWorker::new();
+
+        let first = worker.submit(Arc::clone(&db), "package".to_string(), options(500), None);
+        let second = worker.submit(Arc::clone(&db), "package-4999".to_string(), options(500), None);
+        assert!(second > first);
+
+        let response = wait_for(&mut worker);
+        assert!(!worker.is_pending());
+        assert_eq!(response.generation, second);
+        assert_eq!(db.get_name(response.results[0]), Some("package-4999"));
+
+        // A late result from the first search must never surface
+        thread::sleep(Duration::from_millis(20));
+        assert!(worker.poll().is_none());
+    }
+
+    #[test]
+    fn test_extended_query_narrows_previous_results() {
+        let mut names: Vec<String> = (0..10).map(|i| format!("neo-{}", i)).collect();
+        names.extend((0..990).map(|i| format!("other-{}", i)));
+        let db = Arc::new(PackageDatabase::from_names(&names, PackageSource::Official));
+        let mut worker = SearchWorker::new();
+
+        // The first search has nothing to narrow
+        worker.submit(Arc::clone(&db), "ne".to_string(), options(500), None);
+        let first = wait_for(&mut worker);
+        assert_eq!(first.scanned, 1000);
+        assert_eq!(first.results.len(), 10);
+
+        // Extending the query only revisits the previous matches
+        worker.submit(Arc::clone(&db), "neo-".to_string(), options(500), None);
+        let second = wait_for(&mut worker);
+        assert_eq!(second.scanned, 10);
+        assert_eq!(second.results, first.results);
+
+        // A query that doesn't extend the last one scans everything again
+        worker.submit(Arc::clone(&db), "other-9".to_string(), options(500), None);
+        assert_eq!(wait_for(&mut worker).scanned, 1000);
+
+        // So does one whose previous result set was truncated
+        worker.submit(Arc::clone(&db), "o".to_string(), options(5), None);
+        wait_for(&mut worker);
+        worker.submit(Arc::clone(&db), "ot".to_string(), options(5), None);
+        assert_eq!(wait_for(&mut worker).scanned, 1000);
+    }
+}
@@ -0,0 +1,102 @@
+//! Terra Store v1.0 - Session State
+//!
+//! Remembers the last query, source filter and mode across restarts.
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::database::write_atomically;
+use crate::paths;
+use crate::ui::{AppMode, SourceFilter};
+
+/// UI state saved on quit and restored on the next start
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Search query
+    pub query: String,
+    /// Repository source filter (`None` keeps the configured default)
+    pub source_filter: Option<SourceFilter>,
+    /// Active mode
+    #[serde(deserialize_with = "mode_or_search")]
+    pub mode: AppMode,
+}
+
+/// Deserialize a saved mode, falling back to Search for one this build
+/// doesn't have (e.g. Audit without the `terraflow` feature) rather than
+/// losing the rest of the session
+fn mode_or_search<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AppMode, D::Error> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(AppMode::deserialize(value).unwrap_or(AppMode::Search))
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            query: String::new(),
+            source_filter: None,
+            mode: AppMode::Search,
+        }
+    }
+}
+
+impl Session {
+    /// Get the session file path
+    fn path() -> Option<PathBuf> {
+        paths::data_file("session.json")
+    }
+
+    /// Load the last session, or a fresh one if there is none
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| Self::parse(&contents))
+            .unwrap_or_default()
+    }
+
+    /// Parse session JSON (missing fields take their defaults)
+    fn parse(contents: &str) -> Option<Self> {
+        serde_json::from_str(contents).ok()
+    }
+
+    /// Save the session to disk
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = match Self::path() {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        write_atomically(&path, |writer| Ok(serde_json::to_writer_pretty(writer, self)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_session() {
+        let session = Session {
+            query: "neovim".to_string(),
+            source_filter: Some(SourceFilter::Aur),
+            mode: AppMode::Universal,
+        };
+        let json = serde_json::to_string(&session).unwrap();
+        assert_eq!(Session::parse(&json), Some(session));
+
+        // Partial and unreadable files fall back to defaults
+        let partial = Session::parse(r#"{"query": "htop"}"#).unwrap();
+        assert_eq!(partial.query, "htop");
+        assert_eq!(partial.mode, AppMode::Search);
+        assert_eq!(partial.source_filter, None);
+        assert_eq!(Session::parse("not json"), None);
+
+        // An unknown mode keeps the rest of the session
+        let unknown = Session::parse(r#"{"query": "htop", "source_filter": "aur", "mode": "bogus"}"#).unwrap();
+        assert_eq!(unknown.query, "htop");
+        assert_eq!(unknown.source_filter, Some(SourceFilter::Aur));
+        assert_eq!(unknown.mode, AppMode::Search);
+    }
+}
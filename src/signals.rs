@@ -0,0 +1,69 @@
+//! Terra Store v1.0 - Signal Handling
+//!
+//! SIGINT and SIGTERM only set flags, which the main loop polls so that
+//! quitting always goes through the normal cleanup: terminal restored,
+//! workers joined, sudo keep-alive stopped. The handlers are removed
+//! again when `Signals` is dropped.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::SigId;
+
+/// Signals received since they were last checked
+pub struct Signals {
+    interrupt: Arc<AtomicBool>,
+    terminate: Arc<AtomicBool>,
+    ids: Vec<SigId>,
+}
+
+impl Signals {
+    /// Start catching SIGINT and SIGTERM
+    pub fn install() -> io::Result<Self> {
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let terminate = Arc::new(AtomicBool::new(false));
+        let ids = vec![
+            signal_hook::flag::register(SIGINT, Arc::clone(&interrupt))?,
+            signal_hook::flag::register(SIGTERM, Arc::clone(&terminate))?,
+        ];
+        Ok(Self { interrupt, terminate, ids })
+    }
+
+    /// Whether SIGINT arrived since the last call
+    ///
+    /// The TUI's raw mode turns Ctrl+C into a key press, so this is a
+    /// `kill -INT`, or Ctrl+C while pacman or the password prompt has
+    /// the terminal.
+    pub fn take_interrupt(&self) -> bool {
+        self.interrupt.swap(false, Ordering::SeqCst)
+    }
+
+    /// Whether SIGTERM has arrived
+    pub fn terminated(&self) -> bool {
+        self.terminate.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Signals {
+    fn drop(&mut self) {
+        for id in self.ids.drain(..) {
+            signal_hook::low_level::unregister(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signals_set_flags() {
+        let signals = Signals::install().unwrap();
+        signal_hook::low_level::raise(SIGINT).unwrap();
+        assert!(signals.take_interrupt());
+        assert!(!signals.take_interrupt());
+        assert!(!signals.terminated());
+    }
+}
@@ -7,16 +7,28 @@
 //!
 //! Set `TERRA_PACKAGES_DIR` environment variable to specify your packages directory,
 //! or place package lists in one of the auto-detected locations.
+//!
+//! Package lists hold one package per line. `#` starts a comment, and a
+//! `source:` prefix (`aur:`, `official:`, `flatpak:`) overrides the source
+//! implied by the file name. `.toml` files list packages in `official`,
+//! `aur` and `flatpak` arrays instead.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::database::write_atomically;
+use crate::flatpak::FlatpakDatabase;
 use crate::package::PackageSource;
+use crate::repos::Pacman;
 
 /// Result of auditing packages against config
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct AuditResult {
     /// Packages in config but not installed
     pub missing: Vec<PackageEntry>,
@@ -29,17 +41,87 @@ pub struct AuditResult {
 }
 
 /// A package entry from config files
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct PackageEntry {
     pub name: String,
     pub source: PackageSource,
+    /// Package list file the entry came from
     pub file: String,
 }
 
+/// What syncing the system to the package lists would change
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncPlan {
+    /// Listed packages that aren't installed
+    pub install: Vec<PackageEntry>,
+    /// Explicitly installed packages in no list (only removed on request)
+    pub remove: Vec<Removal>,
+}
+
+/// An installed package the sync plan would remove
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Removal {
+    pub name: String,
+    /// AUR for foreign packages (`pacman -Qm`), otherwise official
+    pub source: PackageSource,
+    /// Installed packages that depend on it, which removing it would break
+    pub required_by: Vec<String>,
+}
+
+impl SyncPlan {
+    /// Plan the changes an audit calls for, looking up which extra
+    /// packages are foreign and which other packages need
+    pub fn from_audit(result: &AuditResult) -> Self {
+        let foreign = Pacman::foreign_packages();
+        let required_by = Pacman::required_by_all(&result.extra);
+        Self::build(result, &foreign, &required_by)
+    }
+
+    fn build(
+        result: &AuditResult,
+        foreign: &HashSet<String>,
+        required_by: &HashMap<String, Vec<String>>,
+    ) -> Self {
+        let remove = result
+            .extra
+            .iter()
+            .map(|name| Removal {
+                name: name.clone(),
+                source: if foreign.contains(name) { PackageSource::Aur } else { PackageSource::Official },
+                required_by: required_by.get(name).cloned().unwrap_or_default(),
+            })
+            .collect();
+
+        Self {
+            install: result.missing.clone(),
+            remove,
+        }
+    }
+
+    /// Number of removals that other installed packages depend on
+    pub fn risky_removals(&self) -> usize {
+        self.remove.iter().filter(|removal| !removal.required_by.is_empty()).count()
+    }
+}
+
+/// Package list extensions read unless configured otherwise
+const DEFAULT_EXTENSIONS: [&str; 1] = ["txt"];
+
+/// A `.toml` package list
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PackageToml {
+    official: Vec<String>,
+    aur: Vec<String>,
+    flatpak: Vec<String>,
+}
+
 /// TerraFlow configuration manager
 pub struct TerraFlow {
     /// Path to the dotfiles packages directory
     packages_dir: PathBuf,
+    /// Extensions of one-package-per-line files (`""` for none)
+    extensions: Vec<String>,
 }
 
 impl TerraFlow {
@@ -47,45 +129,33 @@ impl TerraFlow {
     pub fn new(packages_dir: impl Into<PathBuf>) -> Self {
         Self {
             packages_dir: packages_dir.into(),
+            extensions: DEFAULT_EXTENSIONS.map(String::from).to_vec(),
         }
     }
 
+    /// Read line-based package lists with these extensions (`""` for
+    /// files without one) instead of just `.txt`
+    pub fn with_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
     /// Auto-detect packages directory.
     ///
     /// Checks in order:
-    /// 1. `TERRA_PACKAGES_DIR` environment variable
-    /// 2. `~/.config/terra-store/packages` (XDG-compliant)
-    /// 3. Common dotfiles locations
-    pub fn auto_detect() -> Option<Self> {
-        // 1. Check environment variable first (highest priority)
-        if let Ok(path_str) = std::env::var("TERRA_PACKAGES_DIR") {
-            let path = PathBuf::from(path_str);
-            if path.is_dir() {
-                return Some(Self::new(path));
-            }
-        }
-
-        let home = dirs::home_dir()?;
-
-        // 2. Check standard locations in order of preference
-        let candidates = [
-            // XDG-compliant location
-            home.join(".config/terra-store/packages"),
-            // Common dotfiles locations
-            home.join("TerraFlow-Dotfiles/packages"),
-            home.join(".dotfiles/packages"),
-            home.join("dotfiles/packages"),
-            // Legacy location
-            home.join(".config/terraflow/packages"),
-        ];
-
-        for path in candidates {
-            if path.is_dir() {
-                return Some(Self::new(path));
-            }
-        }
+    /// 1. `configured` (`terraflow_dir` in config.toml)
+    /// 2. `TERRAFLOW_DIR`, then `TERRA_PACKAGES_DIR` environment variables
+    /// 3. `~/.config/terra-store/packages` (XDG-compliant)
+    /// 4. Common dotfiles locations
+    pub fn auto_detect(configured: Option<&Path>) -> Option<Self> {
+        let env = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+        detect_dir(configured, env, dirs::home_dir()).map(Self::new)
+    }
 
-        None
+    /// Auto-detect the packages directory and list extensions from `config`
+    pub fn from_config(config: &Config) -> Option<Self> {
+        Self::auto_detect(config.terraflow_dir.as_deref())
+            .map(|tf| tf.with_extensions(config.terraflow_extensions.clone()))
     }
 
     /// Load all package entries from config files
@@ -96,37 +166,54 @@ impl TerraFlow {
             return packages;
         }
 
-        // Read all .txt files in the packages directory
-        if let Ok(entries) = fs::read_dir(&self.packages_dir) {
-            for entry in entries.filter_map(|e| e.ok()) {
-                let path = entry.path();
-                if path.extension().map(|e| e == "txt").unwrap_or(false) {
-                    let source = Self::detect_source(&path);
-                    let file_name = path.file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or("unknown")
-                        .to_string();
-
-                    if let Ok(contents) = fs::read_to_string(&path) {
-                        for line in contents.lines() {
-                            let name = line.trim();
-                            if !name.is_empty() && !name.starts_with('#') && name != "." {
-                                packages.push(PackageEntry {
-                                    name: name.to_string(),
-                                    source,
-                                    file: file_name.clone(),
-                                });
-                            }
-                        }
-                    }
-                }
+        // Names pacman's sync databases know, to tell official packages
+        // from AUR ones regardless of which file lists them
+        let sync = SyncDatabases::read();
+        let sync_names = sync.as_ref().map(|sync| &sync.names);
+
+        // Read the package lists in the packages directory, in name order
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&self.packages_dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_file()).collect(),
+            Err(_) => return packages,
+        };
+        paths.sort();
+
+        for path in paths {
+            let file_name = path.file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("unknown")
+                .to_string();
+            // Dotfiles like `.gitkeep` are never package lists
+            if file_name.starts_with('.') {
+                continue;
+            }
+            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            let is_toml = extension == "toml";
+            if !is_toml && !self.extensions.iter().any(|e| e == extension) {
+                continue;
+            }
+            let Ok(contents) = fs::read_to_string(&path) else {
+                continue;
+            };
+
+            if is_toml {
+                packages.extend(parse_package_toml(&contents, &file_name));
+            } else {
+                let fallback = Self::detect_source(&path);
+                let source = |name: &str| source_for(name, sync_names, fallback);
+                packages.extend(parse_package_list(&contents, source, &file_name));
             }
         }
 
-        packages
+        // Installed packages are checked by name, so a group such as
+        // `gnome` stands for its members
+        match &sync {
+            Some(sync) => expand_groups(packages, &sync.groups),
+            None => packages,
+        }
     }
 
-    /// Detect package source from filename
+    /// Guess package source from filename, for when pacman can't be asked
     fn detect_source(path: &Path) -> PackageSource {
         let name = path.file_stem()
             .and_then(|n| n.to_str())
@@ -167,18 +254,29 @@ impl TerraFlow {
             .map(|p| p.name.clone())
             .collect();
 
+        // `flatpak:` entries are checked against flatpak's own list
+        let mut flatpak = FlatpakDatabase::new();
+        if config_packages.iter().any(|p| p.source == PackageSource::Flatpak) {
+            flatpak.refresh_installed();
+        }
+
         let mut missing = Vec::new();
         for pkg in &config_packages {
-            if !installed.contains(&pkg.name) {
+            let is_installed = match pkg.source {
+                PackageSource::Flatpak => flatpak.is_installed(&pkg.name),
+                _ => installed.contains(&pkg.name),
+            };
+            if !is_installed {
                 missing.push(pkg.clone());
             }
         }
 
         // Packages installed but not in config (informational)
-        let extra: Vec<String> = installed.iter()
+        let mut extra: Vec<String> = installed.iter()
             .filter(|p| !config_names.contains(*p))
             .cloned()
             .collect();
+        extra.sort();
 
         AuditResult {
             missing,
@@ -188,17 +286,17 @@ impl TerraFlow {
         }
     }
 
-    /// Export currently installed packages to a file
-    #[allow(dead_code)]
-    pub fn export_installed(&self, output_path: &Path) -> std::io::Result<usize> {
-        let installed = Self::get_installed_packages();
-        let mut sorted: Vec<_> = installed.into_iter().collect();
-        sorted.sort();
-
-        let content = sorted.join("\n");
-        fs::write(output_path, content)?;
+    /// Audit, then plan the installs and removals that would sync the
+    /// system to the package lists
+    pub fn plan(&self) -> SyncPlan {
+        SyncPlan::from_audit(&self.audit())
+    }
 
-        Ok(sorted.len())
+    /// Export explicitly installed packages into `official.txt` and
+    /// `aur.txt` in `output_dir`, so the lists audit back cleanly
+    #[allow(dead_code)]
+    pub fn export_installed(&self, output_dir: &Path) -> std::io::Result<ExportCounts> {
+        write_export(output_dir, &Self::get_installed_packages(), &Pacman::foreign_packages())
     }
 
     /// Get the packages directory path
@@ -208,6 +306,221 @@ impl TerraFlow {
     }
 }
 
+/// Number of packages written to each file by `export_installed`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportCounts {
+    pub official: usize,
+    pub aur: usize,
+}
+
+/// Write `installed` to `official.txt` and `aur.txt` in `dir`, sorted,
+/// with the `foreign` ones in `aur.txt`
+///
+/// Each file is written to a temp file and renamed into place.
+fn write_export(
+    dir: &Path,
+    installed: &HashSet<String>,
+    foreign: &HashSet<String>,
+) -> std::io::Result<ExportCounts> {
+    let (mut aur, mut official): (Vec<&String>, Vec<&String>) =
+        installed.iter().partition(|name| foreign.contains(*name));
+    official.sort();
+    aur.sort();
+
+    fs::create_dir_all(dir)?;
+    for (file, names) in [("official.txt", &official), ("aur.txt", &aur)] {
+        write_atomically(&dir.join(file), |writer| {
+            names.iter().try_for_each(|name| writeln!(writer, "{name}"))
+        })?;
+    }
+
+    Ok(ExportCounts { official: official.len(), aur: aur.len() })
+}
+
+/// Environment variables naming the packages directory, in priority order
+const DIR_ENV_VARS: [&str; 2] = ["TERRAFLOW_DIR", "TERRA_PACKAGES_DIR"];
+
+/// First existing directory of `configured`, the `DIR_ENV_VARS` (looked
+/// up with `env`) and the usual locations under `home`
+///
+/// A leading `~/` in `configured` stands for `home`.
+fn detect_dir(
+    configured: Option<&Path>,
+    env: impl Fn(&str) -> Option<PathBuf>,
+    home: Option<PathBuf>,
+) -> Option<PathBuf> {
+    let configured = configured.map(|path| match (path.strip_prefix("~"), &home) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    });
+
+    let mut candidates: Vec<PathBuf> = configured
+        .into_iter()
+        .chain(DIR_ENV_VARS.iter().filter_map(|name| env(name)))
+        .collect();
+    if let Some(home) = home {
+        candidates.extend([
+            // XDG-compliant location
+            home.join(".config/terra-store/packages"),
+            // Common dotfiles locations
+            home.join("TerraFlow-Dotfiles/packages"),
+            home.join(".dotfiles/packages"),
+            home.join("dotfiles/packages"),
+            // Legacy location
+            home.join(".config/terraflow/packages"),
+        ]);
+    }
+
+    candidates.into_iter().find(|path| path.is_dir())
+}
+
+/// What pacman's sync databases list
+struct SyncDatabases {
+    /// Names of every package and group
+    names: HashSet<String>,
+    /// Members of each group that isn't also a package name
+    groups: HashMap<String, Vec<String>>,
+}
+
+impl SyncDatabases {
+    /// Ask pacman, returning `None` if it can't be queried
+    fn read() -> Option<Self> {
+        let packages = Command::new("pacman").args(["-Slq"]).output().ok()?;
+        if !packages.status.success() {
+            return None;
+        }
+        let mut names: HashSet<String> = String::from_utf8_lossy(&packages.stdout)
+            .lines()
+            .map(String::from)
+            .collect();
+
+        // Lists may name groups such as `gnome`
+        let mut groups = match Command::new("pacman").args(["-Sg"]).output() {
+            Ok(output) => parse_groups(&String::from_utf8_lossy(&output.stdout)),
+            Err(_) => HashMap::new(),
+        };
+        groups.retain(|group, _| !names.contains(group));
+        names.extend(groups.keys().cloned());
+        (!names.is_empty()).then_some(Self { names, groups })
+    }
+}
+
+/// Parse `pacman -Sg` output, one `group member` pair per line
+fn parse_groups(output: &str) -> HashMap<String, Vec<String>> {
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    for line in output.lines() {
+        if let Some((group, member)) = line.split_once(char::is_whitespace) {
+            groups.entry(group.to_string()).or_default().push(member.trim().to_string());
+        }
+    }
+    groups
+}
+
+/// Replace official entries naming a group with one entry per member
+///
+/// Members listed on their own, or by an earlier group, aren't repeated.
+fn expand_groups(entries: Vec<PackageEntry>, groups: &HashMap<String, Vec<String>>) -> Vec<PackageEntry> {
+    let group_of = |entry: &PackageEntry| {
+        groups.get(&entry.name).filter(|_| entry.source == PackageSource::Official)
+    };
+    let listed: HashSet<String> = entries
+        .iter()
+        .filter(|entry| group_of(entry).is_none())
+        .map(|entry| entry.name.clone())
+        .collect();
+
+    let mut added = HashSet::new();
+    let mut expanded = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let Some(members) = group_of(&entry) else {
+            expanded.push(entry);
+            continue;
+        };
+        for member in members {
+            if !listed.contains(member) && added.insert(member.clone()) {
+                expanded.push(PackageEntry {
+                    name: member.clone(),
+                    source: PackageSource::Official,
+                    file: entry.file.clone(),
+                });
+            }
+        }
+    }
+    expanded
+}
+
+/// Source of `name`: official if the sync databases know it, otherwise
+/// AUR, or `fallback` when they couldn't be read
+fn source_for(name: &str, sync: Option<&HashSet<String>>, fallback: PackageSource) -> PackageSource {
+    match sync {
+        Some(sync) if sync.contains(name) => PackageSource::Official,
+        Some(_) => PackageSource::Aur,
+        None => fallback,
+    }
+}
+
+/// Parse the lines of a package list file
+///
+/// Everything from a `#` on is a comment, and blank lines (and the
+/// literal `.`) are skipped. A `source:` prefix such as `aur:paru-bin`
+/// sets the source of its line; other lines get `source(name)`.
+fn parse_package_list(
+    contents: &str,
+    source: impl Fn(&str) -> PackageSource,
+    file: &str,
+) -> Vec<PackageEntry> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() || line == "." {
+                return None;
+            }
+
+            let (source, name) = match line.split_once(':') {
+                Some((prefix, name)) => (parse_source_prefix(prefix)?, name.trim()),
+                None => (source(line), line),
+            };
+            (!name.is_empty()).then(|| PackageEntry {
+                name: name.to_string(),
+                source,
+                file: file.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parse a `.toml` package list, whose arrays name each package's source
+///
+/// Unknown keys are ignored; a file that isn't valid TOML lists nothing.
+fn parse_package_toml(contents: &str, file: &str) -> Vec<PackageEntry> {
+    let lists: PackageToml = toml::from_str(contents).unwrap_or_default();
+    [
+        (lists.official, PackageSource::Official),
+        (lists.aur, PackageSource::Aur),
+        (lists.flatpak, PackageSource::Flatpak),
+    ]
+    .into_iter()
+    .flat_map(|(names, source)| names.into_iter().map(move |name| (name, source)))
+    .filter(|(name, _)| !name.trim().is_empty())
+    .map(|(name, source)| PackageEntry {
+        name: name.trim().to_string(),
+        source,
+        file: file.to_string(),
+    })
+    .collect()
+}
+
+/// Source named by a `source:` line prefix
+fn parse_source_prefix(prefix: &str) -> Option<PackageSource> {
+    match prefix.trim().to_ascii_lowercase().as_str() {
+        "aur" => Some(PackageSource::Aur),
+        "official" | "repo" | "pacman" => Some(PackageSource::Official),
+        "flatpak" => Some(PackageSource::Flatpak),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -223,4 +536,202 @@ mod tests {
             PackageSource::Official
         );
     }
+
+    #[test]
+    fn test_source_from_sync_databases() {
+        let sync: HashSet<String> = ["git", "gnome"].map(String::from).into();
+        let source = |name: &str| source_for(name, Some(&sync), PackageSource::Official);
+        assert_eq!(source("git"), PackageSource::Official);
+        assert_eq!(source("gnome"), PackageSource::Official);
+        assert_eq!(source("paru-bin"), PackageSource::Aur);
+
+        // Without pacman, the file name decides
+        assert_eq!(source_for("paru-bin", None, PackageSource::Official), PackageSource::Official);
+
+        // An explicit prefix beats the lookup
+        let entries = parse_package_list("official:paru-bin\nparu-bin\n", source, "aur.txt");
+        assert_eq!(names(&entries), vec![("paru-bin", PackageSource::Official), ("paru-bin", PackageSource::Aur)]);
+    }
+
+    #[test]
+    fn test_parse_package_toml() {
+        let contents = r#"
+            # Workstation
+            official = ["git", "neovim"]
+            aur = ["paru-bin", " "]
+            flatpak = ["org.gimp.GIMP"]
+            description = "ignored"
+        "#;
+        let entries = parse_package_toml(contents, "packages.toml");
+        assert_eq!(
+            names(&entries),
+            vec![
+                ("git", PackageSource::Official),
+                ("neovim", PackageSource::Official),
+                ("paru-bin", PackageSource::Aur),
+                ("org.gimp.GIMP", PackageSource::Flatpak),
+            ]
+        );
+        assert_eq!(entries[0].file, "packages.toml");
+
+        assert!(parse_package_toml("official = [", "broken.toml").is_empty());
+    }
+
+    #[test]
+    fn test_load_dispatches_by_extension() {
+        let dir = std::env::temp_dir().join(format!("terra-packages-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("base.txt"), "aur:one\n").unwrap();
+        fs::write(dir.join("extra.list"), "aur:two\n").unwrap();
+        fs::write(dir.join("Packages"), "aur:three\n").unwrap();
+        fs::write(dir.join("more.toml"), "aur = [\"four\"]\n").unwrap();
+        fs::write(dir.join(".hidden"), "aur:five\n").unwrap();
+
+        let load = |tf: &TerraFlow| -> Vec<String> {
+            tf.load_config_packages().into_iter().map(|entry| entry.name).collect()
+        };
+        assert_eq!(load(&TerraFlow::new(&dir)), vec!["one", "four"]);
+        let all = TerraFlow::new(&dir).with_extensions(["txt", "list", ""].map(String::from).to_vec());
+        assert_eq!(load(&all), vec!["three", "one", "two", "four"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_round_trips_through_load() {
+        let dir = std::env::temp_dir().join(format!("terra-export-{}", std::process::id()));
+        let set = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<HashSet<_>>();
+        let installed = set(&["vim", "yay-bin", "git", "paru"]);
+        let foreign = set(&["yay-bin", "paru", "not-explicit"]);
+
+        let counts = write_export(&dir, &installed, &foreign).unwrap();
+        assert_eq!(counts, ExportCounts { official: 2, aur: 2 });
+        assert_eq!(fs::read_to_string(dir.join("official.txt")).unwrap(), "git\nvim\n");
+        assert_eq!(fs::read_to_string(dir.join("aur.txt")).unwrap(), "paru\nyay-bin\n");
+        assert!(!dir.join("aur.txt.tmp").exists());
+
+        // With no sync databases to ask, the file names decide the source
+        let entries: Vec<(String, PackageSource)> = TerraFlow::new(&dir)
+            .load_config_packages()
+            .into_iter()
+            .map(|entry| (entry.name, entry.source))
+            .collect();
+        assert!(entries.contains(&("paru".to_string(), PackageSource::Aur)));
+        assert_eq!(entries.len(), 4);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_detect_dir_order() {
+        let root = std::env::temp_dir().join(format!("terra-detect-{}", std::process::id()));
+        let (configured, from_env, home) = (root.join("configured"), root.join("env"), root.join("home"));
+        let dotfiles = home.join(".dotfiles/packages");
+        for dir in [&configured, &from_env, &dotfiles] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let env = |name: &str| (name == "TERRAFLOW_DIR").then(|| from_env.clone());
+        let no_env = |_: &str| None;
+
+        assert_eq!(detect_dir(Some(&configured), env, Some(home.clone())), Some(configured.clone()));
+        assert_eq!(detect_dir(Some(Path::new("~/configured")), no_env, Some(root.clone())), Some(configured.clone()));
+        // A configured directory that doesn't exist is passed over
+        assert_eq!(detect_dir(Some(&root.join("missing")), env, Some(home.clone())), Some(from_env.clone()));
+        assert_eq!(detect_dir(None, no_env, Some(home.clone())), Some(dotfiles));
+        assert_eq!(detect_dir(None, no_env, None), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_audit_result_json() {
+        let result = AuditResult {
+            missing: vec![PackageEntry {
+                name: "paru".to_string(),
+                source: PackageSource::Aur,
+                file: "aur.txt".to_string(),
+            }],
+            extra: vec!["htop".to_string()],
+            config_count: 3,
+            installed_count: 4,
+        };
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            r#"{"missing":[{"name":"paru","source":"Aur","file":"aur.txt"}],"extra":["htop"],"config_count":3,"installed_count":4}"#
+        );
+    }
+
+    #[test]
+    fn test_sync_plan() {
+        let result = AuditResult {
+            missing: vec![PackageEntry {
+                name: "neovim".to_string(),
+                source: PackageSource::Official,
+                file: "base.txt".to_string(),
+            }],
+            extra: vec!["htop".to_string(), "paru-bin".to_string(), "python".to_string()],
+            ..AuditResult::default()
+        };
+        let foreign: HashSet<String> = ["paru-bin".to_string()].into();
+        let required_by: HashMap<String, Vec<String>> = [("python".to_string(), vec!["meson".to_string()])].into();
+
+        let plan = SyncPlan::build(&result, &foreign, &required_by);
+        assert_eq!(plan.install, result.missing);
+        let sources: Vec<PackageSource> = plan.remove.iter().map(|removal| removal.source).collect();
+        assert_eq!(sources, vec![PackageSource::Official, PackageSource::Aur, PackageSource::Official]);
+        assert_eq!(plan.remove[2].required_by, vec!["meson"]);
+        assert_eq!(plan.risky_removals(), 1);
+    }
+
+    #[test]
+    fn test_groups_expand_to_members() {
+        let groups = parse_groups("gnome gdm\ngnome nautilus\nxorg xorg-server\n");
+        assert_eq!(groups["gnome"], vec!["gdm", "nautilus"]);
+
+        let contents = "gnome\nnautilus\naur:xorg\nxorg\ngnome\n";
+        let entries = parse_package_list(contents, |_| PackageSource::Official, "desktop.txt");
+        let entries = expand_groups(entries, &groups);
+        // The group's members are audited instead, each once
+        assert_eq!(
+            names(&entries),
+            vec![
+                ("gdm", PackageSource::Official),
+                ("nautilus", PackageSource::Official),
+                ("xorg", PackageSource::Aur),
+                ("xorg-server", PackageSource::Official),
+            ]
+        );
+        assert_eq!(entries[0].file, "desktop.txt");
+    }
+
+    fn names(entries: &[PackageEntry]) -> Vec<(&str, PackageSource)> {
+        entries.iter().map(|entry| (entry.name.as_str(), entry.source)).collect()
+    }
+
+    #[test]
+    fn test_parse_comments_and_blank_lines() {
+        let contents = "# Editors\nneovim # editor\n\n   \n\t\n.\n  htop  \n#git\n";
+        let entries = parse_package_list(contents, |_| PackageSource::Official, "base.txt");
+        assert_eq!(
+            names(&entries),
+            vec![("neovim", PackageSource::Official), ("htop", PackageSource::Official)]
+        );
+        assert_eq!(entries[0].file, "base.txt");
+    }
+
+    #[test]
+    fn test_parse_source_prefix() {
+        let contents = "aur:paru-bin\nAUR: yay-bin # fallback\nofficial:git\nflatpak:org.gimp.GIMP\nfirefox\naur:\nbogus:pkg\n";
+        let entries = parse_package_list(contents, |_| PackageSource::Aur, "aur.txt");
+        assert_eq!(
+            names(&entries),
+            vec![
+                ("paru-bin", PackageSource::Aur),
+                ("yay-bin", PackageSource::Aur),
+                ("git", PackageSource::Official),
+                ("org.gimp.GIMP", PackageSource::Flatpak),
+                ("firefox", PackageSource::Aur),
+            ]
+        );
+    }
 }
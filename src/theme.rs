@@ -1,13 +1,20 @@
 //! Terra Store v1.0 - Pywal Theme Integration
 //!
 //! Loads color schemes from ~/.cache/wal/colors.json for dynamic theming.
+//! Colors can also be set in the `[theme]` table of config.toml.
+//! Terminals without truecolor get the nearest 256- or 16-color match.
 
+use std::env;
 use std::fs;
 use std::path::PathBuf;
 
 use ratatui::style::Color;
 use serde::Deserialize;
 
+/// Minimum contrast ratio of muted text and borders against the background
+/// (WCAG's threshold for large text and UI components)
+const MIN_MUTED_CONTRAST: f64 = 3.0;
+
 /// Pywal color scheme
 #[derive(Debug, Clone, Deserialize)]
 #[allow(dead_code)]
@@ -46,6 +53,64 @@ pub struct ColorPalette {
     pub color15: String,
 }
 
+/// Colors a terminal can display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorLevel {
+    /// 24-bit RGB
+    TrueColor,
+    /// The xterm 256-color palette
+    Ansi256,
+    /// The 16 basic ANSI colors
+    Ansi16,
+}
+
+impl ColorLevel {
+    /// Detect the terminal's color support from `$COLORTERM` and `$TERM`
+    pub fn detect() -> Self {
+        Self::from_env(env::var("COLORTERM").ok().as_deref(), env::var("TERM").ok().as_deref())
+    }
+
+    fn from_env(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        if matches!(colorterm, Some("truecolor" | "24bit")) {
+            return ColorLevel::TrueColor;
+        }
+
+        match term {
+            // Unknown: keep the colors as they are
+            None | Some("") => ColorLevel::TrueColor,
+            // COLORTERM doesn't survive SSH, so recognize truecolor
+            // terminals by name too
+            Some(term)
+                if ["direct", "kitty", "alacritty", "wezterm", "foot"]
+                    .iter()
+                    .any(|name| term.contains(name)) =>
+            {
+                ColorLevel::TrueColor
+            }
+            Some(term) if term.contains("256") => ColorLevel::Ansi256,
+            Some(_) => ColorLevel::Ansi16,
+        }
+    }
+}
+
+/// Hex colors from the `[theme]` config table, one per `Theme` field
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeOverrides {
+    /// Apply these on top of Pywal instead of only without it
+    pub override_pywal: bool,
+    pub bg: Option<String>,
+    pub fg: Option<String>,
+    pub accent: Option<String>,
+    pub secondary: Option<String>,
+    pub success: Option<String>,
+    pub error: Option<String>,
+    pub warning: Option<String>,
+    pub muted: Option<String>,
+    pub highlight_bg: Option<String>,
+    pub border: Option<String>,
+}
+
 /// Application theme derived from Pywal or defaults
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
@@ -81,13 +146,69 @@ impl Default for Theme {
 }
 
 impl Theme {
+    /// Names of the built-in themes, in cycling order
+    pub const NAMES: [&'static str; 4] = ["dark", "light", "gruvbox", "nord"];
+
+    /// Built-in theme called `name`
+    pub fn named(name: &str) -> Option<Self> {
+        let theme = match name {
+            "dark" => Self::default(),
+            "light" => Self {
+                bg: Color::Rgb(250, 250, 250),           // #fafafa
+                fg: Color::Rgb(56, 58, 66),              // #383a42
+                accent: Color::Rgb(80, 161, 79),         // #50a14f
+                secondary: Color::Rgb(64, 120, 242),     // #4078f2
+                success: Color::Rgb(80, 161, 79),        // #50a14f
+                error: Color::Rgb(228, 86, 73),          // #e45649
+                warning: Color::Rgb(193, 132, 1),        // #c18401
+                muted: Color::Rgb(160, 161, 167),        // #a0a1a7
+                highlight_bg: Color::Rgb(229, 229, 230), // #e5e5e6
+                border: Color::Rgb(208, 208, 208),       // #d0d0d0
+            },
+            "gruvbox" => Self {
+                bg: Color::Rgb(40, 40, 40),           // #282828
+                fg: Color::Rgb(235, 219, 178),        // #ebdbb2
+                accent: Color::Rgb(184, 187, 38),     // #b8bb26
+                secondary: Color::Rgb(254, 128, 25),  // #fe8019
+                success: Color::Rgb(184, 187, 38),    // #b8bb26
+                error: Color::Rgb(251, 73, 52),       // #fb4934
+                warning: Color::Rgb(250, 189, 47),    // #fabd2f
+                muted: Color::Rgb(146, 131, 116),     // #928374
+                highlight_bg: Color::Rgb(60, 56, 54), // #3c3836
+                border: Color::Rgb(80, 73, 69),       // #504945
+            },
+            "nord" => Self {
+                bg: Color::Rgb(46, 52, 64),           // #2e3440
+                fg: Color::Rgb(216, 222, 233),        // #d8dee9
+                accent: Color::Rgb(136, 192, 208),    // #88c0d0
+                secondary: Color::Rgb(129, 161, 193), // #81a1c1
+                success: Color::Rgb(163, 190, 140),   // #a3be8c
+                error: Color::Rgb(191, 97, 106),      // #bf616a
+                warning: Color::Rgb(235, 203, 139),   // #ebcb8b
+                muted: Color::Rgb(76, 86, 106),       // #4c566a
+                highlight_bg: Color::Rgb(59, 66, 82), // #3b4252
+                border: Color::Rgb(67, 76, 94),       // #434c5e
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
     /// Load theme from Pywal colors.json
     pub fn from_pywal() -> Option<Self> {
         let path = pywal_colors_path()?;
         let contents = fs::read_to_string(path).ok()?;
         let pywal: PywalColors = serde_json::from_str(&contents).ok()?;
+        Self::from_pywal_colors(&pywal)
+    }
 
-        Some(Self {
+    /// Map a Pywal scheme onto theme colors
+    ///
+    /// color8 is near the background on light schemes, so there muted
+    /// text and borders are pulled toward the foreground until they stay
+    /// readable. Dark schemes keep color8 as it is.
+    fn from_pywal_colors(pywal: &PywalColors) -> Option<Self> {
+        let mut theme = Self {
             bg: parse_hex_color(&pywal.special.background)?,
             fg: parse_hex_color(&pywal.special.foreground)?,
             accent: parse_hex_color(&pywal.colors.color2)?,     // Usually green
@@ -98,12 +219,65 @@ impl Theme {
             muted: parse_hex_color(&pywal.colors.color8)?,      // Bright black
             highlight_bg: parse_hex_color(&pywal.colors.color0)?, // Black variant
             border: parse_hex_color(&pywal.colors.color8)?,     // Bright black
-        })
+        };
+
+        if relative_luminance(theme.bg) > 0.5 {
+            theme.muted = ensure_contrast(theme.muted, theme.bg, theme.fg);
+            theme.border = ensure_contrast(theme.border, theme.bg, theme.fg);
+        }
+        Some(theme)
+    }
+
+    /// Load the Pywal theme, or the defaults with `overrides` applied,
+    /// in colors the terminal can show
+    ///
+    /// With `override_pywal` set, the overrides apply to Pywal too.
+    pub fn load(overrides: &ThemeOverrides) -> Self {
+        let theme = match Self::from_pywal() {
+            Some(theme) if !overrides.override_pywal => theme,
+            Some(theme) => theme.with_overrides(overrides),
+            None => Self::default().with_overrides(overrides),
+        };
+        theme.quantized(ColorLevel::detect())
+    }
+
+    /// Map every RGB color to the nearest one available at `level`
+    pub fn quantized(self, level: ColorLevel) -> Self {
+        let q = |color| quantize(color, level);
+        Self {
+            bg: q(self.bg),
+            fg: q(self.fg),
+            accent: q(self.accent),
+            secondary: q(self.secondary),
+            success: q(self.success),
+            error: q(self.error),
+            warning: q(self.warning),
+            muted: q(self.muted),
+            highlight_bg: q(self.highlight_bg),
+            border: q(self.border),
+        }
     }
 
-    /// Load Pywal theme or fall back to defaults
-    pub fn load() -> Self {
-        Self::from_pywal().unwrap_or_default()
+    /// Replace every color `overrides` sets; invalid hex values are ignored
+    pub fn with_overrides(mut self, overrides: &ThemeOverrides) -> Self {
+        let fields = [
+            (&mut self.bg, &overrides.bg),
+            (&mut self.fg, &overrides.fg),
+            (&mut self.accent, &overrides.accent),
+            (&mut self.secondary, &overrides.secondary),
+            (&mut self.success, &overrides.success),
+            (&mut self.error, &overrides.error),
+            (&mut self.warning, &overrides.warning),
+            (&mut self.muted, &overrides.muted),
+            (&mut self.highlight_bg, &overrides.highlight_bg),
+            (&mut self.border, &overrides.border),
+        ];
+        for (color, hex) in fields {
+            if let Some(parsed) = hex.as_deref().and_then(parse_hex_color) {
+                *color = parsed;
+            }
+        }
+        self
     }
 }
 
@@ -118,10 +292,117 @@ fn pywal_colors_path() -> Option<PathBuf> {
     }
 }
 
+/// Standard xterm values of the 16 ANSI colors
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Channel values of the xterm 6x6x6 color cube (indices 16-231)
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Nearest color to `color` available at `level`
+fn quantize(color: Color, level: ColorLevel) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+
+    match level {
+        ColorLevel::TrueColor => color,
+        ColorLevel::Ansi256 => {
+            // Best cube entry, channel by channel
+            let cube_index = |v: u8| match v {
+                0..=47 => 0,
+                48..=114 => 1,
+                _ => (v as usize - 35) / 40,
+            };
+            let (ri, gi, bi) = (cube_index(r), cube_index(g), cube_index(b));
+            let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+            // Best entry of the 24-step grayscale ramp (8, 18, ..., 238)
+            let average = (r as usize + g as usize + b as usize) / 3;
+            let gray_index = (average.saturating_sub(3) / 10).min(23);
+            let gray = (8 + 10 * gray_index) as u8;
+
+            if distance((r, g, b), (gray, gray, gray)) < distance((r, g, b), cube) {
+                Color::Indexed(232 + gray_index as u8)
+            } else {
+                Color::Indexed((16 + 36 * ri + 6 * gi + bi) as u8)
+            }
+        }
+        ColorLevel::Ansi16 => ANSI16
+            .iter()
+            .min_by_key(|(_, rgb)| distance((r, g, b), *rgb))
+            .map(|(named, _)| *named)
+            .unwrap_or(color),
+    }
+}
+
+/// Squared distance between two RGB colors
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// Blend `color` toward `fg` until it reaches `MIN_MUTED_CONTRAST`
+/// against `bg` (or becomes `fg`)
+fn ensure_contrast(color: Color, bg: Color, fg: Color) -> Color {
+    let (Color::Rgb(r, g, b), Color::Rgb(tr, tg, tb)) = (color, fg) else {
+        return color;
+    };
+
+    let mix = |from: u8, to: u8, t: f64| (from as f64 + (to as f64 - from as f64) * t).round() as u8;
+    (0..=10)
+        .map(|step| {
+            let t = step as f64 / 10.0;
+            Color::Rgb(mix(r, tr, t), mix(g, tg, t), mix(b, tb, t))
+        })
+        .find(|&candidate| contrast_ratio(candidate, bg) >= MIN_MUTED_CONTRAST)
+        .unwrap_or(fg)
+}
+
+/// WCAG contrast ratio between two colors, from 1 (none) to 21
+fn contrast_ratio(a: Color, b: Color) -> f64 {
+    let (la, lb) = (relative_luminance(a), relative_luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+/// WCAG relative luminance of an RGB color (0 for black, 1 for white)
+fn relative_luminance(color: Color) -> f64 {
+    let Color::Rgb(r, g, b) = color else {
+        return 0.0;
+    };
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+}
+
 /// Parse a hex color string like "#1f2428" to ratatui Color
 fn parse_hex_color(hex: &str) -> Option<Color> {
     let hex = hex.trim_start_matches('#');
-    if hex.len() != 6 {
+    // Checking the digits first also keeps the slicing below on char
+    // boundaries
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
         return None;
     }
 
@@ -141,6 +422,88 @@ mod tests {
         assert_eq!(parse_hex_color("#ff0000"), Some(Color::Rgb(255, 0, 0)));
         assert_eq!(parse_hex_color("#00ff00"), Some(Color::Rgb(0, 255, 0)));
         assert_eq!(parse_hex_color("1f2428"), Some(Color::Rgb(31, 36, 40)));
+        assert_eq!(parse_hex_color("#€1234"), None);
+        assert_eq!(parse_hex_color("+f0000"), None);
+    }
+
+    #[test]
+    fn test_light_pywal_muted_contrast() {
+        let json = r##"{
+            "wallpaper": null,
+            "special": {"background": "#f5f5f5", "foreground": "#2a2a2a", "cursor": "#2a2a2a"},
+            "colors": {
+                "color0": "#f5f5f5", "color1": "#c0392b", "color2": "#27ae60", "color3": "#d68910",
+                "color4": "#2e86c1", "color5": "#8e44ad", "color6": "#17a589", "color7": "#2a2a2a",
+                "color8": "#e0e0e0", "color9": "#c0392b", "color10": "#27ae60", "color11": "#d68910",
+                "color12": "#2e86c1", "color13": "#8e44ad", "color14": "#17a589", "color15": "#2a2a2a"
+            }
+        }"##;
+        let pywal: PywalColors = serde_json::from_str(json).unwrap();
+        let theme = Theme::from_pywal_colors(&pywal).unwrap();
+
+        // color8 alone is nearly invisible on this background
+        assert!(contrast_ratio(Color::Rgb(224, 224, 224), theme.bg) < 1.5);
+        assert!(contrast_ratio(theme.muted, theme.bg) >= MIN_MUTED_CONTRAST);
+        assert!(contrast_ratio(theme.border, theme.bg) >= MIN_MUTED_CONTRAST);
+        // and is still dimmer than the foreground
+        assert_ne!(theme.muted, theme.fg);
+    }
+
+    #[test]
+    fn test_dark_pywal_keeps_color8() {
+        let json = r##"{
+            "wallpaper": null,
+            "special": {"background": "#1d1f21", "foreground": "#c5c8c6", "cursor": "#c5c8c6"},
+            "colors": {
+                "color0": "#282a2e", "color1": "#a54242", "color2": "#8c9440", "color3": "#de935f",
+                "color4": "#5f819d", "color5": "#85678f", "color6": "#5e8d87", "color7": "#707880",
+                "color8": "#373b41", "color9": "#cc6666", "color10": "#b5bd68", "color11": "#f0c674",
+                "color12": "#81a2be", "color13": "#b294bb", "color14": "#8abeb7", "color15": "#c5c8c6"
+            }
+        }"##;
+        let pywal: PywalColors = serde_json::from_str(json).unwrap();
+        let theme = Theme::from_pywal_colors(&pywal).unwrap();
+
+        // Low contrast, but dark schemes use color8 for subtle borders
+        assert!(contrast_ratio(Color::Rgb(55, 59, 65), theme.bg) < MIN_MUTED_CONTRAST);
+        assert_eq!(theme.muted, Color::Rgb(55, 59, 65));
+        assert_eq!(theme.border, Color::Rgb(55, 59, 65));
+    }
+
+    #[test]
+    fn test_quantize() {
+        let red = Color::Rgb(255, 0, 0);
+        assert_eq!(quantize(red, ColorLevel::TrueColor), red);
+        assert_eq!(quantize(red, ColorLevel::Ansi256), Color::Indexed(196));
+        assert_eq!(quantize(Color::Rgb(128, 128, 128), ColorLevel::Ansi256), Color::Indexed(244));
+        assert_eq!(quantize(red, ColorLevel::Ansi16), Color::LightRed);
+        assert_eq!(quantize(Color::Rgb(31, 36, 40), ColorLevel::Ansi16), Color::Black);
+
+        assert_eq!(ColorLevel::from_env(Some("truecolor"), Some("xterm")), ColorLevel::TrueColor);
+        assert_eq!(ColorLevel::from_env(None, Some("xterm-256color")), ColorLevel::Ansi256);
+        assert_eq!(ColorLevel::from_env(None, Some("linux")), ColorLevel::Ansi16);
+    }
+
+    #[test]
+    fn test_named_themes() {
+        for name in Theme::NAMES {
+            assert!(Theme::named(name).is_some(), "missing preset {}", name);
+        }
+        assert_eq!(Theme::named("nord").unwrap().bg, Color::Rgb(46, 52, 64));
+        assert!(Theme::named("solarized").is_none());
+    }
+
+    #[test]
+    fn test_theme_overrides() {
+        let overrides = ThemeOverrides {
+            bg: Some("#000000".to_string()),
+            accent: Some("not a color".to_string()),
+            ..ThemeOverrides::default()
+        };
+        let theme = Theme::default().with_overrides(&overrides);
+        assert_eq!(theme.bg, Color::Rgb(0, 0, 0));
+        // Invalid values keep the previous color
+        assert_eq!(theme.accent, Theme::default().accent);
     }
 
     #[test]
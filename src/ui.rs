@@ -3,86 +3,347 @@
 //! Split-pane TUI with instant search powered by Arena-based indexing.
 //! Includes History, Audit (with TerraFlow feature), and Universal (Flatpak) modes.
 
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{self, Stdout};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    cursor::Show,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Position, Rect},
     prelude::CrosstermBackend,
-    style::{Modifier, Style},
+    style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
     Frame, Terminal,
 };
+use serde::{Deserialize, Serialize};
 
-use crate::database::PackageDatabase;
-use crate::flatpak::FlatpakDatabase;
-use crate::history::History;
-use crate::package::PackageSource;
-use crate::repos::RepoManager;
+use crate::auth::AuthBackend;
+use crate::aur::{self, AurInfo};
+use crate::clipboard;
+use crate::config::{Config, InstalledAction, SearchMode};
+use crate::database::{is_regex_query, regex_error, Highlighter, PackageDatabase, SearchOptions};
+use crate::deps::{self, DepNode};
+use crate::favorites::Favorites;
+use crate::flatpak::{FlatpakApp, FlatpakDatabase, InstallScope};
+use crate::history::{current_timestamp, iso_date, relative_time, Action, History, InstallRecord, StatusFilter};
+use crate::keymap::{KeyAction, Keymap};
+use crate::package::{find_conflicts, format_count, format_size, Conflict, PackageInfo, PackageSource, PackageUpdate};
+use crate::repos::{aur_helper, Pacman, Paru, RepoError, RepoManager};
+use crate::search::{NameFilter, SearchWorker};
+use crate::session::Session;
 #[cfg(feature = "terraflow")]
-use crate::terraflow::{AuditResult, TerraFlow};
-use crate::theme::Theme;
-
-/// Maximum results to display
-const MAX_DISPLAY_RESULTS: usize = 500;
+use crate::terraflow::{AuditResult, SyncPlan, TerraFlow};
+use crate::theme::{ColorLevel, Theme};
 
 /// Application mode
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AppMode {
     Search,
     Universal,  // Flatpak search
     History,
+    /// Starred packages
+    Favorites,
     #[cfg(feature = "terraflow")]
     Audit,
 }
 
+impl AppMode {
+    /// Whether letter keys type into the query (or History's filter)
+    /// in this mode
+    ///
+    /// Modes without a text input use them for vim-style navigation
+    /// instead.
+    pub fn takes_text(self) -> bool {
+        matches!(self, AppMode::Search | AppMode::Universal | AppMode::History)
+    }
+
+    /// The mode after this one, wrapping around
+    pub fn next(self) -> Self {
+        match self {
+            AppMode::Search => AppMode::Universal,
+            AppMode::Universal => AppMode::History,
+            AppMode::History => AppMode::Favorites,
+            #[cfg(feature = "terraflow")]
+            AppMode::Favorites => AppMode::Audit,
+            #[cfg(not(feature = "terraflow"))]
+            AppMode::Favorites => AppMode::Search,
+            #[cfg(feature = "terraflow")]
+            AppMode::Audit => AppMode::Search,
+        }
+    }
+}
+
+/// Frames of the busy spinner
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Main loop ticks per spinner frame (the loop runs at roughly 60Hz)
+const TICKS_PER_SPINNER_FRAME: usize = 5;
+
+/// How long the selection must stay put before its details are fetched
+const DETAIL_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Whether the TUI owns the terminal (raw mode, alternate screen)
+static TERMINAL_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Most status messages the notifications pane keeps
+const NOTIFICATION_LIMIT: usize = 50;
+
+/// Longest gap between two clicks on a row that still counts as a double-click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Action the main loop runs outside the TUI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PendingAction {
+    Install { name: String, source: PackageSource },
+    InstallFlatpak { app_id: String, name: String, remote: String },
+    Remove { name: String, source: PackageSource },
+    RemoveFlatpak { app_id: String, name: String },
+    /// Install several packages one after another
+    InstallBatch { packages: Vec<(String, PackageSource)> },
+    /// Apply a TerraFlow sync plan: install, then remove
+    #[cfg(feature = "terraflow")]
+    Sync {
+        install: Vec<(String, PackageSource)>,
+        remove: Vec<(String, PackageSource)>,
+    },
+    /// Full system upgrade
+    Upgrade,
+}
+
+impl PendingAction {
+    /// Whether the action runs pacman or an AUR helper and so needs sudo
+    ///
+    /// Flatpak installs and removals go through Flatpak's own
+    /// authorization instead.
+    pub fn needs_root(&self) -> bool {
+        match self {
+            PendingAction::Install { source, .. } | PendingAction::Remove { source, .. } => {
+                *source != PackageSource::Flatpak
+            }
+            PendingAction::InstallFlatpak { .. } | PendingAction::RemoveFlatpak { .. } => false,
+            PendingAction::InstallBatch { packages } => {
+                packages.iter().any(|(_, source)| *source != PackageSource::Flatpak)
+            }
+            #[cfg(feature = "terraflow")]
+            PendingAction::Sync { install, remove } => {
+                install.iter().chain(remove).any(|(_, source)| *source != PackageSource::Flatpak)
+            }
+            PendingAction::Upgrade => true,
+        }
+    }
+}
+
+/// Entries of the action menu shown for installed packages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    Reinstall,
+    Remove,
+    Details,
+}
+
+impl MenuAction {
+    pub const ALL: [MenuAction; 3] = [MenuAction::Reinstall, MenuAction::Remove, MenuAction::Details];
+
+    pub fn label(&self) -> &str {
+        match self {
+            MenuAction::Reinstall => "Reinstall",
+            MenuAction::Remove => "Remove",
+            MenuAction::Details => "Details",
+        }
+    }
+}
+
+/// Popup drawn over the main view, capturing input while open
+#[derive(Debug, Clone)]
+pub enum Overlay {
+    /// Action menu for an already-installed package
+    Actions {
+        name: String,
+        source: PackageSource,
+        selected: usize,
+    },
+    /// Read-only package information, scrolled down `scroll` lines
+    Info { title: String, text: String, scroll: u16 },
+    /// Confirmation before quitting with background work still running
+    ConfirmQuit { running: String },
+    /// Confirmation before installing a single package
+    ConfirmInstall {
+        name: String,
+        source: PackageSource,
+        /// Queued once confirmed
+        action: PendingAction,
+    },
+    /// Confirmation before installing a batch of packages
+    ConfirmBatch {
+        packages: Vec<(String, PackageSource)>,
+        /// Summed download size of the packages whose info was available
+        download_size: u64,
+        /// Summed installed size of the packages whose info was available
+        installed_size: u64,
+        /// Packages whose info isn't in (still loading, or the lookup
+        /// failed), so neither their sizes nor their conflicts are known
+        unchecked: usize,
+        /// Conflicts among the batch or with installed packages
+        conflicts: Vec<Conflict>,
+    },
+    /// TerraFlow sync plan awaiting confirmation
+    #[cfg(feature = "terraflow")]
+    SyncPlan {
+        plan: SyncPlan,
+        /// Also remove the extra packages (opt-in)
+        remove: bool,
+        scroll: u16,
+    },
+}
+
 /// Application state
 pub struct App {
     /// Current mode
     pub mode: AppMode,
     /// Current search query
     pub query: String,
+    /// `query` prepared for highlighting matches in the results
+    highlighter: Highlighter,
+    /// Byte offset of the cursor in the active input (`query`, or
+    /// `history_filter` in History mode)
+    cursor: usize,
+    /// Query edited since the last search (on-Enter search mode)
+    pub query_dirty: bool,
     /// Search result indices into the database
     pub results: Vec<usize>,
+    /// VCS variants folded into a release result, keyed by its index
+    pub vcs_variants: HashMap<usize, Vec<usize>>,
+    /// Database indices marked for batch install
+    pub selected_set: HashSet<usize>,
     /// Current selection index
     pub selected: usize,
     /// List widget state
     list_state: ListState,
+    /// Rows of the list drawn last frame, inside its border
+    list_area: Rect,
+    /// List row shown at the top of `list_area`
+    list_offset: usize,
+    /// When and on which entry the last mouse click landed
+    last_click: Option<(Instant, usize)>,
     /// Current repository source filter
     pub source_filter: SourceFilter,
+    /// Only list installed packages and Flatpaks
+    pub installed_only: bool,
+    /// Copy of `installed` shared with the search worker
+    installed_snapshot: Option<NameFilter>,
     /// UI theme
     pub theme: Theme,
-    /// Arena-based package database
-    pub database: PackageDatabase,
+    /// Built-in theme picked with `cycle_theme` (`None` for the loaded one)
+    pub theme_name: Option<&'static str>,
+    /// Arena-based package database (shared with search workers)
+    pub database: Arc<PackageDatabase>,
+    /// Database rebuild running in the background, and when it started
+    rebuild: Option<(Receiver<PackageDatabase>, Instant)>,
+    /// Set to make a running rebuild skip writing the cache
+    rebuild_cancel: Arc<AtomicBool>,
+    /// Rebuild and Flatpak loader threads, joined by `shutdown`
+    workers: Vec<JoinHandle<()>>,
+    /// Background search dispatcher
+    search_worker: SearchWorker,
     /// Repository manager
     pub repo_manager: RepoManager,
+    /// User configuration
+    pub config: Config,
+    /// Names of locally installed packages
+    pub installed: HashSet<String>,
+    /// Open popup, if any
+    pub overlay: Option<Overlay>,
+    /// Action queued for the main loop
+    pub pending_action: Option<PendingAction>,
     /// Installation history
     pub history: History,
+    /// Name filter typed in History mode
+    pub history_filter: String,
+    /// Which outcomes History mode lists
+    pub history_status: StatusFilter,
+    /// Starred packages
+    pub favorites: Favorites,
+    /// Database indices of the starred packages Favorites mode lists
+    favorite_results: Vec<usize>,
+    /// Fetched package details by name (`None` if the lookup failed)
+    details: HashMap<String, Option<PackageInfo>>,
+    /// Package whose details are wanted, and when it was first selected
+    detail_request: Option<(String, Instant)>,
+    /// Package whose details are being fetched
+    detail_in_flight: Option<String>,
+    /// Package whose details the open info popup is waiting for
+    details_popup: Option<String>,
+    /// Marked packages whose details were requested for the size totals
+    size_requests: HashSet<String>,
+    detail_tx: Sender<(String, Option<PackageInfo>)>,
+    detail_rx: Receiver<(String, Option<PackageInfo>)>,
+    /// AUR RPC data by name, kept for the session (`None` if the package
+    /// isn't in the AUR or the lookup failed)
+    aur_info: HashMap<String, Option<AurInfo>>,
+    /// Names whose AUR RPC lookup is running
+    aur_in_flight: HashSet<String>,
+    /// Results on screen, and since when, so scrolling settles before
+    /// they're looked up
+    aur_window: (Vec<usize>, Instant),
+    /// A lookup failed (offline, most likely); no more are made
+    aur_unreachable: bool,
+    aur_tx: Sender<(Vec<String>, Option<Vec<AurInfo>>)>,
+    aur_rx: Receiver<(Vec<String>, Option<Vec<AurInfo>>)>,
     /// TerraFlow config (if detected)
     #[cfg(feature = "terraflow")]
     pub terraflow: Option<TerraFlow>,
     /// Audit results (cached)
     #[cfg(feature = "terraflow")]
     pub audit_result: Option<AuditResult>,
+    /// Audit list on screen
+    #[cfg(feature = "terraflow")]
+    pub audit_view: AuditView,
     /// Flatpak database (lazy loaded)
     pub flatpak: FlatpakDatabase,
+    /// Flatpak database being loaded in the background
+    flatpak_loader: Option<Receiver<(FlatpakDatabase, Result<(), String>)>>,
     /// Flatpak search results
     pub flatpak_results: Vec<usize>,
+    /// Pending package updates, once the background check has finished
+    pub updates: Option<Vec<PackageUpdate>>,
+    /// Update check running in the background
+    update_check: Option<Receiver<Vec<PackageUpdate>>>,
+    /// Dependency tree being built for the popup
+    deps_loader: Option<Receiver<DepNode>>,
+    /// PKGBUILD being fetched for the preview popup, by package name
+    pkgbuild_loader: Option<Receiver<(String, Result<String, String>)>>,
+    /// Whether Flatpak search also matches summaries
+    pub flatpak_summary: bool,
     /// Status message
     pub status: String,
+    /// Notable status messages with their unix timestamps, newest first
+    notifications: VecDeque<(u64, String)>,
+    /// Main loop tick count, drives the spinner animation
+    spinner_tick: usize,
+    /// Key bindings for the remappable actions
+    pub keymap: Keymap,
     /// Should quit
     pub should_quit: bool,
     /// Is loading
     pub is_loading: bool,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SourceFilter {
     All,
     Official,
@@ -106,7 +367,7 @@ impl SourceFilter {
         }
     }
 
-    pub fn to_package_source(&self) -> Option<PackageSource> {
+    pub fn to_package_source(self) -> Option<PackageSource> {
         match self {
             SourceFilter::All => None,
             SourceFilter::Official => Some(PackageSource::Official),
@@ -115,29 +376,116 @@ impl SourceFilter {
     }
 }
 
+/// Which TerraFlow audit list Audit mode shows
+#[cfg(feature = "terraflow")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuditView {
+    /// In a package list but not installed
+    #[default]
+    Missing,
+    /// Explicitly installed but in no package list
+    Extra,
+}
+
+#[cfg(feature = "terraflow")]
+impl AuditView {
+    pub fn toggle(self) -> Self {
+        match self {
+            AuditView::Missing => AuditView::Extra,
+            AuditView::Extra => AuditView::Missing,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AuditView::Missing => "MISSING",
+            AuditView::Extra => "EXTRA",
+        }
+    }
+}
+
 impl App {
-    pub fn new() -> Self {
-        let theme = Theme::load();
-        let repo_manager = RepoManager::new();
+    /// Create app state for the TUI from the loaded config, with the
+    /// user's key bindings
+    pub fn new(config: Config, keymap: Keymap) -> Self {
+        let mut app = Self::with_config(config);
+        app.keymap = keymap;
+        app
+    }
+
+    /// Create app state with an explicit configuration
+    pub fn with_config(config: Config) -> Self {
+        let theme = Theme::load(&config.theme);
+        let flatpak_summary = config.flatpak_search_summary;
+        let repo_manager = RepoManager::with_auth(AuthBackend::resolve(config.auth_backend));
+        let (detail_tx, detail_rx) = mpsc::channel();
+        let (aur_tx, aur_rx) = mpsc::channel();
 
         let mut app = Self {
             mode: AppMode::Search,
             query: String::new(),
+            highlighter: Highlighter::default(),
+            cursor: 0,
+            query_dirty: false,
             results: Vec::new(),
+            vcs_variants: HashMap::new(),
+            selected_set: HashSet::new(),
             selected: 0,
             list_state: ListState::default(),
-            source_filter: SourceFilter::All,
+            list_area: Rect::default(),
+            list_offset: 0,
+            last_click: None,
+            source_filter: config.default_source,
+            installed_only: false,
+            installed_snapshot: None,
             theme,
-            database: PackageDatabase::new(),
+            theme_name: None,
+            database: Arc::new(PackageDatabase::new()),
+            rebuild: None,
+            rebuild_cancel: Arc::new(AtomicBool::new(false)),
+            workers: Vec::new(),
+            search_worker: SearchWorker::new(),
             repo_manager,
+            config,
+            installed: HashSet::new(),
+            overlay: None,
+            pending_action: None,
             history: History::default(),
+            history_filter: String::new(),
+            history_status: StatusFilter::default(),
+            favorites: Favorites::default(),
+            favorite_results: Vec::new(),
+            details: HashMap::new(),
+            detail_request: None,
+            detail_in_flight: None,
+            details_popup: None,
+            size_requests: HashSet::new(),
+            detail_tx,
+            detail_rx,
+            aur_info: HashMap::new(),
+            aur_in_flight: HashSet::new(),
+            aur_window: (Vec::new(), Instant::now()),
+            aur_unreachable: false,
+            aur_tx,
+            aur_rx,
             #[cfg(feature = "terraflow")]
             terraflow: None,
             #[cfg(feature = "terraflow")]
             audit_result: None,
+            #[cfg(feature = "terraflow")]
+            audit_view: AuditView::default(),
             flatpak: FlatpakDatabase::new(),
+            flatpak_loader: None,
+            updates: None,
+            update_check: None,
+            deps_loader: None,
+            pkgbuild_loader: None,
             flatpak_results: Vec::new(),
+            flatpak_summary,
             status: String::from("Loading package database..."),
+            notifications: VecDeque::new(),
+            spinner_tick: 0,
+            keymap: Keymap::default(),
             should_quit: false,
             is_loading: true,
         };
@@ -146,10 +494,10 @@ impl App {
         app
     }
 
-    /// Load the package database
-    pub fn load_database(&mut self) {
-        let start = Instant::now();
-        self.database = PackageDatabase::load_or_build();
+    /// Install a freshly loaded database and leave the loading state
+    pub fn finish_loading(&mut self, database: PackageDatabase, elapsed: Duration) {
+        self.set_database(database);
+        self.installed = Pacman::installed_packages();
 
         let stats = &self.database.stats;
         let source = if stats.was_cached { "cache" } else { "pacman" };
@@ -157,813 +505,4475 @@ impl App {
         self.status = format!(
             "Loaded {} pkgs in {}ms ({})",
             stats.official_count + stats.aur_count,
-            start.elapsed().as_millis(),
+            elapsed.as_millis(),
             source
         );
         self.is_loading = false;
+        self.start_update_check();
     }
 
-    /// Perform instant search
-    pub fn search(&mut self) {
-        if self.query.is_empty() {
-            self.results.clear();
-            self.status = format!("{} packages indexed", self.database.len());
+    /// Look for pending updates on a worker thread; `poll_updates` picks
+    /// up the result
+    pub fn start_update_check(&mut self) {
+        if self.update_check.is_some() {
             return;
         }
 
-        if self.query.len() < 2 {
-            self.results.clear();
-            self.status = String::from("Type at least 2 chars...");
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let updates = RepoManager::new().check_updates().unwrap_or_default();
+            let _ = tx.send(updates);
+        });
+        self.update_check = Some(rx);
+    }
+
+    /// Store the result of a finished update check, if any
+    pub fn poll_updates(&mut self) {
+        let Some(rx) = &self.update_check else {
             return;
+        };
+
+        match rx.try_recv() {
+            Ok(updates) => {
+                self.update_check = None;
+                self.updates = Some(updates);
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.update_check = None,
         }
+    }
 
-        let start = Instant::now();
-        self.results = self.database.search(
-            &self.query,
-            self.source_filter.to_package_source(),
-            MAX_DISPLAY_RESULTS,
-        );
-        let elapsed_us = start.elapsed().as_micros();
+    /// Build the selected package's dependency tree in the background
+    pub fn show_dependencies(&mut self) {
+        let Some((name, source)) = self.selected_package() else {
+            return;
+        };
+        if self.deps_loader.is_some() {
+            return;
+        }
 
-        self.status = format!("Found {} in {}µs", self.results.len(), elapsed_us);
-        self.selected = 0;
-        self.list_state.select(Some(0));
+        let name = name.to_string();
+        self.status = format!("Resolving dependencies of {}...", name);
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(deps::dependency_tree(&name, source));
+        });
+        self.deps_loader = Some(rx);
     }
 
-    /// Run TerraFlow audit
-    #[cfg(feature = "terraflow")]
-    pub fn run_audit(&mut self) {
-        if let Some(ref tf) = self.terraflow {
-            self.status = String::from("Running audit...");
-            self.audit_result = Some(tf.audit());
-            if let Some(ref result) = self.audit_result {
-                self.status = format!(
-                    "Audit: {} missing, {} extra",
-                    result.missing.len(),
-                    result.extra.len()
-                );
-            }
-        } else {
-            self.status = String::from("TerraFlow not configured");
+    /// Fetch the selected AUR package's PKGBUILD in the background, to
+    /// read before installing
+    ///
+    /// The AUR helper prints it; failing that (with `aur-rpc`), it's
+    /// downloaded from the package's AUR git repository.
+    pub fn show_pkgbuild(&mut self) {
+        let Some((name, source)) = self.selected_package() else {
+            return;
+        };
+        if source != PackageSource::Aur {
+            self.status = format!("{} isn't from the AUR, so it has no PKGBUILD to review", name);
+            return;
         }
+        if self.pkgbuild_loader.is_some() {
+            return;
+        }
+
+        let name = name.to_string();
+        self.status = format!("Fetching the PKGBUILD of {}...", name);
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let pkgbuild = Paru::pkgbuild(&name).or_else(|helper_error| {
+                aur::fetch_pkgbuild(&name).map_err(|e| if aur::ENABLED { e.to_string() } else { helper_error.to_string() })
+            });
+            let _ = tx.send((name, pkgbuild));
+        });
+        self.pkgbuild_loader = Some(rx);
     }
 
-    /// Switch to a different mode
-    pub fn set_mode(&mut self, mode: AppMode) {
-        self.mode = mode;
-        self.selected = 0;
-        self.list_state.select(Some(0));
+    /// Show a fetched PKGBUILD, if any
+    pub fn poll_pkgbuild(&mut self) {
+        let Some(rx) = &self.pkgbuild_loader else {
+            return;
+        };
 
-        match mode {
-            AppMode::Search => {
-                self.status = format!("{} packages indexed", self.database.len());
-            }
-            AppMode::Universal => {
-                self.load_flatpak();
+        match rx.try_recv() {
+            Ok((name, Ok(pkgbuild))) => {
+                self.pkgbuild_loader = None;
+                self.status = format!("PKGBUILD of {} ({} lines)", name, pkgbuild.lines().count());
+                self.overlay = Some(Overlay::Info {
+                    title: format!("PKGBUILD of {}", name),
+                    text: pkgbuild,
+                    scroll: 0,
+                });
             }
-            AppMode::History => {
-                self.status = format!(
-                    "History: {} success, {} failed",
-                    self.history.success_count(),
-                    self.history.failure_count()
-                );
+            Ok((name, Err(e))) => {
+                self.pkgbuild_loader = None;
+                self.notify(format!("✗ Couldn't fetch the PKGBUILD of {}: {}", name, e));
             }
-            #[cfg(feature = "terraflow")]
-            AppMode::Audit => {
-                self.run_audit();
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.pkgbuild_loader = None,
+        }
+    }
+
+    /// Show a finished dependency tree, if any
+    pub fn poll_deps(&mut self) {
+        let Some(rx) = &self.deps_loader else {
+            return;
+        };
+
+        match rx.try_recv() {
+            Ok(tree) => {
+                self.deps_loader = None;
+                self.status = format!("{} pulls in {} packages", tree.name, tree.dependency_count());
+                self.overlay = Some(Overlay::Info {
+                    title: format!("Dependencies of {}", tree.name),
+                    text: tree.render(),
+                    scroll: 0,
+                });
             }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.deps_loader = None,
         }
     }
 
-    /// Load Flatpak database on demand (lazy)
-    pub fn load_flatpak(&mut self) {
-        if !FlatpakDatabase::is_available() {
-            self.status = String::from("Flatpak not installed");
+    /// List the pending updates in a popup
+    pub fn show_updates(&mut self) {
+        let Some(updates) = &self.updates else {
+            self.status = String::from("Checking for updates...");
+            return;
+        };
+        if updates.is_empty() {
+            self.status = String::from("✓ System is up to date");
             return;
         }
 
-        if !self.flatpak.is_loaded() {
-            self.status = String::from("Loading Flatpak database...");
-            if let Err(e) = self.flatpak.load() {
-                self.status = format!("Flatpak error: {}", e);
-                return;
-            }
+        let text = updates
+            .iter()
+            .map(|update| format!("[{}] {}", update.source, update))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.overlay = Some(Overlay::Info {
+            title: format!("Updates ({})", updates.len()),
+            text,
+            scroll: 0,
+        });
+    }
+
+    /// Snapshot the state worth restoring on the next start
+    pub fn session(&self) -> Session {
+        Session {
+            query: self.query.clone(),
+            source_filter: Some(self.source_filter),
+            mode: self.mode,
         }
+    }
 
-        let stats = &self.flatpak.stats;
-        self.status = format!(
-            "Flatpak: {} apps in {}ms ({})",
-            stats.app_count, stats.load_time_ms, stats.source
-        );
+    /// Restore a saved session, re-running its search so results show
+    /// up straight away
+    pub fn restore_session(&mut self, session: Session) {
+        self.query = session.query;
+        self.cursor = self.query.len();
+        if let Some(source_filter) = session.source_filter {
+            self.source_filter = source_filter;
+        }
+
+        if session.mode != AppMode::Search {
+            self.set_mode(session.mode);
+        } else if !self.query.is_empty() {
+            self.search();
+        } else {
+            self.show_recent();
+        }
     }
 
-    /// Search Flatpaks
-    pub fn search_flatpak(&mut self) {
+    /// Swap in a new database, dropping everything indexed into the old one
+    ///
+    /// Result indices are only meaningful for the database they came from,
+    /// so results and selection are reset and any search in flight against
+    /// the old database is cancelled before the swap.
+    pub fn set_database(&mut self, database: PackageDatabase) {
+        self.search_worker.cancel();
+        self.results.clear();
+        self.vcs_variants.clear();
+        self.selected_set.clear();
+        self.selected = 0;
+        self.list_state.select(Some(0));
+        self.database = Arc::new(database);
+        self.list_favorites();
+    }
+
+    /// Perform instant search
+    ///
+    /// The scan runs on a worker thread; results are picked up by
+    /// `poll_search`. Starting a new search cancels the previous one.
+    pub fn search(&mut self) {
+        self.query_dirty = false;
+
+        if self.query.is_empty() {
+            self.search_worker.cancel();
+            if self.installed_only {
+                self.list_installed();
+            } else {
+                self.show_recent();
+                self.status = format!("{} packages indexed", self.database.len());
+            }
+            return;
+        }
+
         if self.query.len() < 2 {
-            self.flatpak_results.clear();
+            self.search_worker.cancel();
+            self.results.clear();
             self.status = String::from("Type at least 2 chars...");
             return;
         }
 
-        let start = Instant::now();
-        // Store indices for the results
-        self.flatpak_results = (0..self.flatpak.len())
-            .filter(|&idx| {
-                let apps = self.flatpak.search(&self.query, MAX_DISPLAY_RESULTS);
-                apps.iter().enumerate().any(|(i, _)| i == idx)
-            })
-            .take(MAX_DISPLAY_RESULTS)
-            .collect();
-        let elapsed_us = start.elapsed().as_micros();
+        let options = SearchOptions {
+            source_filter: self.source_filter.to_package_source(),
+            limit: self.config.max_results,
+            descriptions: self.config.search_descriptions,
+        };
+        let only = self.installed_filter();
+        self.search_worker.submit(Arc::clone(&self.database), self.query.clone(), options, only);
+    }
 
-        self.status = format!("Found {} Flatpaks in {}µs", self.flatpak_results.len(), elapsed_us);
+    /// The installed set to restrict searches to, if the filter is on
+    ///
+    /// The snapshot is reused while `installed` is unchanged, so extended
+    /// queries can still narrow the previous results.
+    fn installed_filter(&mut self) -> Option<NameFilter> {
+        if !self.installed_only {
+            return None;
+        }
+        match &self.installed_snapshot {
+            Some(snapshot) if **snapshot == self.installed => {}
+            _ => self.installed_snapshot = Some(Arc::new(self.installed.clone())),
+        }
+        self.installed_snapshot.clone()
+    }
+
+    /// List the recently installed packages while Search has no query
+    ///
+    /// Records whose package is no longer indexed (or is filtered out by
+    /// the source filter) are left out.
+    pub fn show_recent(&mut self) {
+        if self.mode != AppMode::Search || !self.query.is_empty() || self.installed_only {
+            return;
+        }
+        let source_filter = self.source_filter.to_package_source();
+        // Flatpaks aren't in the package database
+        let wanted: Vec<(&str, PackageSource)> = self
+            .history
+            .recent_installs()
+            .filter(|record| record.source != PackageSource::Flatpak)
+            .filter(|record| source_filter.is_none_or(|filter| record.source == filter))
+            .map(|record| (record.name.as_str(), record.source))
+            .collect();
+        let mut results = self.database.find_all(&wanted);
+        results.truncate(self.config.recent_installs);
+        self.results = results;
+        self.vcs_variants.clear();
         self.selected = 0;
         self.list_state.select(Some(0));
     }
 
-    // Navigation methods
-    pub fn select_previous(&mut self) {
-        let len = match self.mode {
-            AppMode::Search => self.results.len(),
-            AppMode::Universal => self.flatpak.search(&self.query, MAX_DISPLAY_RESULTS).len(),
-            AppMode::History => self.history.records.len(),
-            #[cfg(feature = "terraflow")]
-            AppMode::Audit => self.audit_result.as_ref().map(|r| r.missing.len()).unwrap_or(0),
-        };
-        if len == 0 {
+    /// Move starred packages to the top of the results, keeping the
+    /// search's order within both groups
+    fn favorites_first(&mut self) {
+        if self.favorites.is_empty() {
             return;
         }
-        self.selected = self.selected.saturating_sub(1);
-        self.list_state.select(Some(self.selected));
+        let (database, favorites) = (&self.database, &self.favorites);
+        self.results
+            .sort_by_key(|&idx| !database.get_name(idx).is_some_and(|name| favorites.contains(name)));
     }
 
-    pub fn select_next(&mut self) {
-        let len = match self.mode {
-            AppMode::Search => self.results.len(),
-            AppMode::Universal => self.flatpak.search(&self.query, MAX_DISPLAY_RESULTS).len(),
-            AppMode::History => self.history.records.len(),
-            #[cfg(feature = "terraflow")]
-            AppMode::Audit => self.audit_result.as_ref().map(|r| r.missing.len()).unwrap_or(0),
+    /// Find the starred packages in the database, sorted by name
+    fn list_favorites(&mut self) {
+        let favorites = &self.favorites;
+        self.favorite_results = if favorites.is_empty() {
+            Vec::new()
+        } else {
+            self.database.list_filtered(None, usize::MAX, |name| favorites.contains(name)).indices
         };
-        if len == 0 {
+    }
+
+    /// Star or unstar the selected package
+    pub fn toggle_favorite(&mut self) {
+        let Some((name, _)) = self.selected_package() else {
             return;
+        };
+        let name = name.to_string();
+        self.status = if self.favorites.toggle(&name) {
+            format!("★ Added {} to favorites", name)
+        } else {
+            format!("Removed {} from favorites", name)
+        };
+        if let Err(e) = self.favorites.save() {
+            self.notify(format!("⚠ Favorites not saved: {}", e));
         }
-        self.selected = (self.selected + 1).min(len.saturating_sub(1));
-        self.list_state.select(Some(self.selected));
-    }
 
-    pub fn page_up(&mut self) {
-        self.selected = self.selected.saturating_sub(10);
-        self.list_state.select(Some(self.selected));
+        self.list_favorites();
+        if self.mode == AppMode::Favorites {
+            self.selected = self.selected.min(self.favorite_results.len().saturating_sub(1));
+            self.list_state.select(Some(self.selected));
+        }
     }
 
-    pub fn page_down(&mut self) {
-        let len = match self.mode {
-            AppMode::Search => self.results.len(),
-            AppMode::Universal => self.flatpak.search(&self.query, MAX_DISPLAY_RESULTS).len(),
-            AppMode::History => self.history.records.len(),
-            #[cfg(feature = "terraflow")]
-            AppMode::Audit => self.audit_result.as_ref().map(|r| r.missing.len()).unwrap_or(0),
+    /// Show every installed package, for browsing without a query
+    fn list_installed(&mut self) {
+        let installed = &self.installed;
+        let listed = self.database.list_filtered(
+            self.source_filter.to_package_source(),
+            self.config.max_results,
+            |name| installed.contains(name),
+        );
+        self.status = if listed.total > listed.indices.len() {
+            format!("Showing {} of {} installed packages", listed.indices.len(), listed.total)
+        } else {
+            format!("{} installed packages", listed.total)
         };
-        self.selected = (self.selected + 10).min(len.saturating_sub(1));
-        self.list_state.select(Some(self.selected));
+        self.results = listed.indices;
+        self.vcs_variants.clear();
+        self.selected = 0;
+        self.list_state.select(Some(0));
     }
 
-    pub fn selected_package(&self) -> Option<(&str, PackageSource)> {
-        if self.mode != AppMode::Search {
-            return None;
+    /// Toggle listing only installed packages (Search) or Flatpaks
+    /// (Universal)
+    pub fn toggle_installed_only(&mut self) {
+        self.installed_only = !self.installed_only;
+        match self.mode {
+            AppMode::Search => self.search(),
+            AppMode::Universal => self.search_flatpak(),
+            _ => {}
         }
-        let idx = *self.results.get(self.selected)?;
-        let name = self.database.get_name(idx)?;
-        let source = self.database.get_source(idx)?;
-        Some((name, source))
     }
 
-    pub fn toggle_source(&mut self) {
-        self.source_filter = self.source_filter.next();
-        if self.mode == AppMode::Search {
-            self.search();
+    /// React to an edit of the Search mode query
+    ///
+    /// Instant mode searches right away; on-Enter mode just marks the
+    /// query dirty until Enter is pressed.
+    pub fn query_changed(&mut self) {
+        match self.config.search_mode {
+            SearchMode::Instant => self.search(),
+            SearchMode::OnEnter => {
+                self.query_dirty = true;
+                self.status = String::from("Press Enter to search");
+            }
         }
     }
 
-    pub fn refresh_database(&mut self) {
-        self.is_loading = true;
-        self.status = String::from("Refreshing...");
-        let _ = PackageDatabase::invalidate_cache();
-        self.load_database();
-        self.search();
+    /// Handle Enter in Search mode: run a pending search, else act on
+    /// the selected package
+    pub fn submit(&mut self) {
+        if self.query_dirty {
+            self.search();
+        } else {
+            self.activate_selected();
+        }
     }
-}
 
-impl Default for App {
-    fn default() -> Self {
-        Self::new()
+    /// Apply the results of the latest finished search, if any
+    pub fn poll_search(&mut self) {
+        let Some(response) = self.search_worker.poll() else {
+            return;
+        };
+
+        let shown = response.results.len();
+        if self.config.collapse_vcs {
+            (self.results, self.vcs_variants) = self.database.collapse_vcs(&response.results);
+        } else {
+            self.results = response.results;
+            self.vcs_variants.clear();
+        }
+        self.favorites_first();
+        self.status = if let Some(error) = regex_error(&self.query) {
+            format!("✗ Invalid regex ({}), matched literally", error)
+        } else if response.total > shown {
+            format!(
+                "Showing {} of {} in {}µs - refine your query",
+                format_count(shown),
+                format_count(response.total),
+                response.elapsed.as_micros()
+            )
+        } else {
+            format!(
+                "Found {} in {}µs",
+                self.results.len(),
+                response.elapsed.as_micros()
+            )
+        };
+        self.selected = 0;
+        self.list_state.select(Some(0));
     }
-}
 
-/// Initialize terminal
-pub fn init_terminal() -> io::Result<Terminal<CrosstermBackend<Stdout>>> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    Terminal::new(CrosstermBackend::new(stdout))
-}
+    /// Fetch details for the selected package once the selection settles
+    ///
+    /// Called every frame. The lookup runs on a worker thread and its
+    /// result is cached, so revisiting a package is instant.
+    pub fn poll_details(&mut self) {
+        let mut received = false;
+        while let Ok((name, info)) = self.detail_rx.try_recv() {
+            if self.detail_in_flight.as_deref() == Some(name.as_str()) {
+                self.detail_in_flight = None;
+            }
+            if self.details_popup.as_deref() == Some(name.as_str()) {
+                self.details_popup = None;
+                if let Some(Overlay::Info { title, text, .. }) = &mut self.overlay {
+                    if *title == name {
+                        *text = match &info {
+                            Some(info) => info.to_display_string(),
+                            None => format!("✗ Couldn't look up {}", name),
+                        };
+                    }
+                }
+            }
+            self.details.insert(name, info);
+            received = true;
+        }
+        // An open batch confirmation picks up the sizes and conflicts
+        if let (true, Some(Overlay::ConfirmBatch { packages, .. })) = (received, &self.overlay) {
+            self.overlay = Some(self.batch_overlay(packages.clone()));
+        }
+        self.request_marked_details();
 
-/// Restore terminal
-pub fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> io::Result<()> {
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()
-}
+        let Some((name, source)) = self.selected_package() else {
+            self.detail_request = None;
+            return;
+        };
+        if self.details.contains_key(name) {
+            return;
+        }
 
-/// Main rendering function
-pub fn draw(frame: &mut Frame, app: &mut App) {
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(3),
-            Constraint::Min(10),
-            Constraint::Length(3),
-        ])
-        .split(frame.area());
+        let since = match &self.detail_request {
+            Some((requested, since)) if requested == name => *since,
+            _ => {
+                self.detail_request = Some((name.to_string(), Instant::now()));
+                return;
+            }
+        };
+        if since.elapsed() < DETAIL_DEBOUNCE || self.detail_in_flight.is_some() {
+            return;
+        }
 
-    draw_header(frame, chunks[0], app);
+        let name = name.to_string();
+        let installed = self.is_installed(&name);
+        self.detail_in_flight = Some(name.clone());
+        let tx = self.detail_tx.clone();
+        thread::spawn(move || {
+            let info = fetch_info(&RepoManager::new(), &name, source, installed);
+            let _ = tx.send((name, info.ok()));
+        });
+    }
 
-    let content_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
-        .split(chunks[1]);
+    /// Look up AUR packages on the AUR RPC: the AUR results on screen
+    /// once scrolling settles, and the selected package once its details
+    /// are in
+    ///
+    /// Called every frame. Answers are cached for the session. After a
+    /// failed lookup (most likely offline) no more are made, and AUR
+    /// entries just go without the extra data.
+    pub fn poll_aur(&mut self) {
+        while let Ok((names, found)) = self.aur_rx.try_recv() {
+            self.aur_unreachable |= found.is_none();
+            let mut found = found.unwrap_or_default();
+            for name in names {
+                let info = found.iter().position(|info| info.name == name).map(|i| found.swap_remove(i));
+                self.aur_in_flight.remove(&name);
+                self.aur_info.insert(name, info);
+            }
+        }
+        if !aur::ENABLED || self.aur_unreachable {
+            return;
+        }
 
-    match app.mode {
-        AppMode::Search => {
-            draw_package_list(frame, content_chunks[0], app);
-            draw_preview(frame, content_chunks[1], app);
+        let window = self.visible_results().to_vec();
+        if window != self.aur_window.0 {
+            self.aur_window = (window, Instant::now());
+        } else if self.aur_window.1.elapsed() >= DETAIL_DEBOUNCE {
+            let names = self.aur_window.0.iter().filter_map(|&idx| self.aur_name(idx)).collect();
+            self.request_aur_info(names);
         }
-        AppMode::Universal => {
-            draw_flatpak_list(frame, content_chunks[0], app);
-            draw_flatpak_preview(frame, content_chunks[1], app);
+
+        if !self.details_loading() {
+            if let Some(name) = self.selected_index().and_then(|idx| self.aur_name(idx)) {
+                self.request_aur_info(vec![name]);
+            }
         }
-        AppMode::History => {
-            draw_history_list(frame, content_chunks[0], app);
-            draw_history_detail(frame, content_chunks[1], app);
+    }
+
+    /// Search results in the rows drawn last frame
+    fn visible_results(&self) -> &[usize] {
+        if self.mode != AppMode::Search {
+            return &[];
         }
-        #[cfg(feature = "terraflow")]
-        AppMode::Audit => {
-            draw_audit_list(frame, content_chunks[0], app);
-            draw_audit_detail(frame, content_chunks[1], app);
+        let start = self.list_offset.min(self.results.len());
+        let end = (self.list_offset + usize::from(self.list_area.height)).min(self.results.len());
+        &self.results[start..end]
+    }
+
+    /// Name of the package at `idx` if it's from the AUR
+    fn aur_name(&self, idx: usize) -> Option<String> {
+        match self.database.get_source(idx)? {
+            PackageSource::Aur => self.database.get_name(idx).map(String::from),
+            _ => None,
         }
     }
 
-    draw_footer(frame, chunks[2], app);
-}
+    /// Look up the `names` not cached or on their way yet, in one request
+    fn request_aur_info(&mut self, mut names: Vec<String>) {
+        names.retain(|name| !self.aur_info.contains_key(name) && !self.aur_in_flight.contains(name));
+        if names.is_empty() {
+            return;
+        }
 
-fn draw_header(frame: &mut Frame, area: Rect, app: &App) {
-    let theme = &app.theme;
+        self.aur_in_flight.extend(names.iter().cloned());
+        let tx = self.aur_tx.clone();
+        thread::spawn(move || {
+            let found = aur::fetch_info(&names).ok();
+            let _ = tx.send((names, found));
+        });
+    }
 
-    let mode_label = match app.mode {
-        AppMode::Search => format!("SEARCH | {}", app.source_filter.label()),
-        AppMode::Universal => "UNIVERSAL (Flatpak)".to_string(),
-        AppMode::History => "HISTORY".to_string(),
-        #[cfg(feature = "terraflow")]
-        AppMode::Audit => "AUDIT".to_string(),
-    };
+    /// AUR RPC data of the selected package: `None` while it isn't known
+    /// yet, `Some(None)` if it's unavailable
+    pub fn selected_aur_info(&self) -> Option<Option<&AurInfo>> {
+        let (name, _) = self.selected_package()?;
+        self.aur_info.get(name).map(Option::as_ref)
+    }
 
-    let search_block = Block::default()
-        .title(Span::styled(
-            format!(" 🔍 TERRA STORE | {} ", mode_label),
-            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
-        ))
-        .borders(Borders::ALL)
-        .border_style(Style::default().fg(theme.border));
+    /// Fetch details of marked packages that aren't cached yet, for the
+    /// footer's size totals
+    fn request_marked_details(&mut self) {
+        let missing: Vec<(String, PackageSource)> = self
+            .selected_set
+            .iter()
+            .filter_map(|&idx| Some((self.database.get_name(idx)?, self.database.get_source(idx)?)))
+            .filter(|(name, _)| !self.details.contains_key(*name) && !self.size_requests.contains(*name))
+            .map(|(name, source)| (name.to_string(), source))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
 
-    let content = if app.mode == AppMode::Search {
-        let search_text = if app.query.is_empty() {
-            Span::styled("Type to search...", Style::default().fg(theme.muted))
-        } else {
-            Span::styled(&app.query, Style::default().fg(theme.fg))
-        };
+        self.size_requests.extend(missing.iter().map(|(name, _)| name.clone()));
+        let tx = self.detail_tx.clone();
+        thread::spawn(move || {
+            let repos = RepoManager::new();
+            for (name, source) in missing {
+                let info = repos.get_info(&name, source);
+                if tx.send((name, info.ok())).is_err() {
+                    break;
+                }
+            }
+        });
+    }
 
-        Line::from(vec![
-            Span::styled("> ", Style::default().fg(theme.accent)),
-            search_text,
-            Span::styled("█", Style::default().fg(theme.accent)),
-        ])
-    } else {
-        Line::from(vec![
-            Span::styled("Press ", Style::default().fg(theme.muted)),
-            Span::styled("1", Style::default().fg(theme.accent)),
-            Span::styled(" Search  ", Style::default().fg(theme.muted)),
-            Span::styled("2", Style::default().fg(theme.accent)),
-            Span::styled(" History  ", Style::default().fg(theme.muted)),
-            Span::styled("3", Style::default().fg(theme.accent)),
-            Span::styled(" Audit", Style::default().fg(theme.muted)),
-        ])
-    };
+    /// Summed download and installed sizes of the marked packages, and
+    /// how many of them have no size (not fetched yet, or unavailable)
+    pub fn marked_sizes(&self) -> (u64, u64, usize) {
+        let mut totals = (0, 0, 0);
+        for &idx in &self.selected_set {
+            let info = self.database.get_name(idx).and_then(|name| self.details.get(name)?.as_ref());
+            match info {
+                Some(info) => {
+                    totals.0 += info.download_size;
+                    totals.1 += info.installed_size;
+                }
+                None => totals.2 += 1,
+            }
+        }
+        totals
+    }
 
-    let paragraph = Paragraph::new(content).block(search_block);
-    frame.render_widget(paragraph, area);
-}
+    /// Get the fetched details of the selected package, if available
+    pub fn selected_info(&self) -> Option<&PackageInfo> {
+        let (name, _) = self.selected_package()?;
+        self.details.get(name)?.as_ref()
+    }
 
-fn draw_package_list(frame: &mut Frame, area: Rect, app: &mut App) {
-    let theme = &app.theme;
-    let visible_height = area.height.saturating_sub(2) as usize;
-    let scroll_offset = app.selected.saturating_sub(visible_height / 2);
-    let end_idx = (scroll_offset + visible_height).min(app.results.len());
+    /// Check whether the selected package's details are still on the way
+    pub fn details_loading(&self) -> bool {
+        self.selected_package()
+            .is_some_and(|(name, _)| !self.details.contains_key(name))
+    }
 
-    let items: Vec<ListItem> = app.results[scroll_offset..end_idx]
-        .iter()
-        .enumerate()
-        .filter_map(|(i, &pkg_idx)| {
-            let name = app.database.get_name(pkg_idx)?;
-            let source = app.database.get_source(pkg_idx)?;
-            let actual_idx = scroll_offset + i;
+    /// Clear the query and results, returning to the stats view
+    pub fn clear_search(&mut self) {
+        self.query.clear();
+        self.cursor = 0;
+        self.vcs_variants.clear();
+        self.selected = 0;
+        self.list_state.select(Some(0));
+        self.search();
+    }
 
-            let source_tag = match source {
-                PackageSource::Official => Span::styled("[OFF]", Style::default().fg(theme.accent)),
-                PackageSource::Aur => Span::styled("[AUR]", Style::default().fg(theme.secondary)),
-            };
+    /// Text edited by typing in the current mode
+    fn input(&self) -> &String {
+        match self.mode {
+            AppMode::History => &self.history_filter,
+            _ => &self.query,
+        }
+    }
 
-            let style = if actual_idx == app.selected {
-                Style::default().bg(theme.highlight_bg).fg(theme.fg).add_modifier(Modifier::BOLD)
-            } else {
-                Style::default().fg(theme.fg)
-            };
+    fn input_mut(&mut self) -> &mut String {
+        match self.mode {
+            AppMode::History => &mut self.history_filter,
+            _ => &mut self.query,
+        }
+    }
 
-            Some(ListItem::new(Line::from(vec![
-                source_tag,
-                Span::raw(" "),
-                Span::styled(name, style),
-            ])))
-        })
-        .collect();
+    /// Re-run the current mode's search after its input was edited
+    fn query_edited(&mut self) {
+        match self.mode {
+            AppMode::Search => self.query_changed(),
+            AppMode::Universal => self.search_flatpak(),
+            AppMode::History => self.history_filter_changed(),
+            AppMode::Favorites => {}
+            #[cfg(feature = "terraflow")]
+            AppMode::Audit => {}
+        }
+    }
 
-    let title = if app.is_loading {
-        " Loading... ".to_string()
-    } else {
-        format!(" Results ({}) ", app.results.len())
-    };
+    /// Cursor position, clamped to a char boundary of the active input
+    pub fn cursor(&self) -> usize {
+        let input = self.input();
+        let mut cursor = self.cursor.min(input.len());
+        while !input.is_char_boundary(cursor) {
+            cursor -= 1;
+        }
+        cursor
+    }
 
-    let list = List::new(items)
-        .block(Block::default().title(title).borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
-        .highlight_style(Style::default().bg(theme.highlight_bg).add_modifier(Modifier::BOLD))
-        .highlight_symbol("➜ ");
+    /// Type a character at the cursor
+    pub fn insert_char(&mut self, c: char) {
+        let cursor = self.cursor();
+        self.input_mut().insert(cursor, c);
+        self.cursor = cursor + c.len_utf8();
+        self.query_edited();
+    }
 
-    let mut adjusted_state = ListState::default();
-    if app.selected >= scroll_offset && app.selected < end_idx {
-        adjusted_state.select(Some(app.selected - scroll_offset));
+    /// Delete the character before the cursor
+    pub fn backspace(&mut self) {
+        let cursor = self.cursor();
+        let Some(c) = self.input()[..cursor].chars().next_back() else {
+            return;
+        };
+        self.cursor = cursor - c.len_utf8();
+        let at = self.cursor;
+        self.input_mut().remove(at);
+        self.query_edited();
     }
 
-    frame.render_stateful_widget(list, area, &mut adjusted_state);
-}
+    /// Move the cursor one character left
+    pub fn cursor_left(&mut self) {
+        let cursor = self.cursor();
+        self.cursor = self.input()[..cursor].chars().next_back().map_or(0, |c| cursor - c.len_utf8());
+    }
 
-fn draw_preview(frame: &mut Frame, area: Rect, app: &App) {
-    let theme = &app.theme;
+    /// Move the cursor one character right
+    pub fn cursor_right(&mut self) {
+        let cursor = self.cursor();
+        self.cursor = self.input()[cursor..].chars().next().map_or(cursor, |c| cursor + c.len_utf8());
+    }
 
-    let content = if let Some((name, source)) = app.selected_package() {
-        vec![
-            Line::from(vec![
-                Span::styled("📦 ", Style::default()),
-                Span::styled(name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
-            ]),
-            Line::from(""),
-            Line::from(vec![
-                Span::styled("Source: ", Style::default().fg(theme.muted)),
-                match source {
-                    PackageSource::Official => Span::styled("Official", Style::default().fg(theme.accent)),
-                    PackageSource::Aur => Span::styled("AUR", Style::default().fg(theme.secondary)),
-                },
-            ]),
-            Line::from(""),
-            Line::from(Span::styled("Press Enter to install", Style::default().fg(theme.muted))),
-        ]
-    } else {
-        let stats = &app.database.stats;
-        vec![
-            Line::from(Span::styled("Database Stats", Style::default().fg(theme.fg).add_modifier(Modifier::BOLD))),
-            Line::from(""),
-            Line::from(vec![
-                Span::styled("Official: ", Style::default().fg(theme.muted)),
-                Span::styled(format!("{}", stats.official_count), Style::default().fg(theme.fg)),
-            ]),
-            Line::from(vec![
-                Span::styled("AUR: ", Style::default().fg(theme.muted)),
-                Span::styled(format!("{}", stats.aur_count), Style::default().fg(theme.fg)),
-            ]),
-            Line::from(vec![
-                Span::styled("Arena: ", Style::default().fg(theme.muted)),
-                Span::styled(format!("{:.2} MB", stats.arena_bytes as f64 / 1_000_000.0), Style::default().fg(theme.fg)),
-            ]),
-        ]
-    };
+    /// Move the cursor to the start (`at_end` false) or end of the input
+    pub fn cursor_to(&mut self, at_end: bool) {
+        self.cursor = if at_end { self.input().len() } else { 0 };
+    }
 
-    let preview = Paragraph::new(content)
-        .block(Block::default().title(" Details ").borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
-        .wrap(Wrap { trim: true });
+    /// Delete the whole input (`Ctrl+U`)
+    pub fn clear_query(&mut self) {
+        self.input_mut().clear();
+        self.cursor = 0;
+        self.query_edited();
+    }
 
-    frame.render_widget(preview, area);
-}
+    /// Delete the word before the cursor, along with any whitespace
+    /// between them (`Ctrl+W`)
+    pub fn delete_word(&mut self) {
+        let cursor = self.cursor();
+        let start = self.input()[..cursor].trim_end().trim_end_matches(|c: char| !c.is_whitespace()).len();
+        self.input_mut().replace_range(start..cursor, "");
+        self.cursor = start;
+        self.query_edited();
+    }
 
-fn draw_flatpak_list(frame: &mut Frame, area: Rect, app: &mut App) {
-    let theme = &app.theme;
+    /// Indices into `history.records` of the entries History mode lists
+    ///
+    /// `selected` indexes this list in History mode, for navigation,
+    /// drawing and the detail pane alike.
+    pub fn history_view(&self) -> Vec<usize> {
+        self.history.matching(&self.history_filter, self.history_status)
+    }
 
-    let results = app.flatpak.search(&app.query, MAX_DISPLAY_RESULTS);
-    let visible_height = area.height.saturating_sub(2) as usize;
-    let scroll_offset = app.selected.saturating_sub(visible_height / 2);
-    let end_idx = (scroll_offset + visible_height).min(results.len());
+    /// The history record selected in History mode
+    pub fn selected_history_record(&self) -> Option<&InstallRecord> {
+        let idx = *self.history_view().get(self.selected)?;
+        self.history.records.get(idx)
+    }
 
-    let items: Vec<ListItem> = if results.is_empty() && !app.flatpak.is_loaded() {
-        vec![ListItem::new(Line::from(Span::styled(
-            "Press F2 to load Flatpaks",
-            Style::default().fg(theme.muted),
-        )))]
-    } else {
-        results[scroll_offset..end_idx]
-            .iter()
-            .enumerate()
-            .map(|(i, flatpak)| {
-                let actual_idx = scroll_offset + i;
-                let style = if actual_idx == app.selected {
-                    Style::default().bg(theme.highlight_bg).fg(theme.fg).add_modifier(Modifier::BOLD)
-                } else {
-                    Style::default().fg(theme.fg)
-                };
+    /// Cycle History mode between all, successful and failed records
+    pub fn cycle_history_status(&mut self) {
+        self.history_status = self.history_status.next();
+        self.history_filter_changed();
+    }
 
-                ListItem::new(Line::from(vec![
-                    Span::styled("[FPK]", Style::default().fg(theme.secondary)),
-                    Span::raw(" "),
-                    Span::styled(&flatpak.name, style),
-                ]))
-            })
-            .collect()
-    };
+    /// Back to the top of the History list after its filter changed
+    fn history_filter_changed(&mut self) {
+        self.selected = 0;
+        self.list_state.select(Some(0));
+        self.status = format!(
+            "History: {} of {} records shown ({})",
+            self.history_view().len(),
+            self.history.records.len(),
+            self.history_status.label().to_lowercase()
+        );
+    }
 
-    let title = format!(" Flatpak ({}) ", app.flatpak.len());
+    /// Show the VCS variants folded into the selected result
+    pub fn expand_vcs(&mut self) {
+        let Some(&idx) = self.results.get(self.selected) else {
+            return;
+        };
 
-    let list = List::new(items)
-        .block(Block::default().title(title).borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
-        .highlight_style(Style::default().bg(theme.highlight_bg).add_modifier(Modifier::BOLD))
-        .highlight_symbol("➜ ");
+        match self.vcs_variants.remove(&idx) {
+            Some(variants) => {
+                let at = self.selected + 1;
+                self.results.splice(at..at, variants);
+            }
+            None => self.status = String::from("No VCS variants for this package"),
+        }
+    }
 
-    let mut adjusted_state = ListState::default();
-    if app.selected >= scroll_offset && app.selected < end_idx {
-        adjusted_state.select(Some(app.selected - scroll_offset));
+    /// Check whether a search is still running
+    pub fn is_searching(&self) -> bool {
+        self.search_worker.is_pending()
     }
 
-    frame.render_stateful_widget(list, area, &mut adjusted_state);
-}
+    /// Run TerraFlow audit
+    #[cfg(feature = "terraflow")]
+    pub fn run_audit(&mut self) {
+        if let Some(ref tf) = self.terraflow {
+            self.status = String::from("Running audit...");
+            self.audit_result = Some(tf.audit());
+            if let Some(ref result) = self.audit_result {
+                self.status = format!(
+                    "Audit: {} missing, {} extra",
+                    result.missing.len(),
+                    result.extra.len()
+                );
+            }
+        } else {
+            self.status = String::from("TerraFlow not configured");
+        }
+    }
 
-fn draw_flatpak_preview(frame: &mut Frame, area: Rect, app: &App) {
-    let theme = &app.theme;
+    /// Switch Audit mode between the missing and extra lists
+    #[cfg(feature = "terraflow")]
+    pub fn toggle_audit_view(&mut self) {
+        self.audit_view = self.audit_view.toggle();
+        self.selected = 0;
+        self.list_state.select(Some(0));
+    }
 
-    let results = app.flatpak.search(&app.query, MAX_DISPLAY_RESULTS);
-    let content = if let Some(flatpak) = results.get(app.selected) {
-        vec![
-            Line::from(vec![
-                Span::styled("📦 ", Style::default()),
-                Span::styled(&flatpak.name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
-            ]),
-            Line::from(""),
-            Line::from(vec![
-                Span::styled("ID: ", Style::default().fg(theme.muted)),
-                Span::styled(&flatpak.id, Style::default().fg(theme.fg)),
-            ]),
-            Line::from(""),
-            Line::from(Span::styled(&flatpak.summary, Style::default().fg(theme.fg))),
-            Line::from(""),
-            Line::from(Span::styled("Press Enter to install (flatpak)", Style::default().fg(theme.muted))),
-        ]
-    } else {
-        let stats = &app.flatpak.stats;
-        if app.flatpak.is_loaded() {
-            vec![
-                Line::from(Span::styled("Flatpak Database", Style::default().fg(theme.fg).add_modifier(Modifier::BOLD))),
-                Line::from(""),
-                Line::from(vec![
-                    Span::styled("Apps: ", Style::default().fg(theme.muted)),
-                    Span::styled(format!("{}", stats.app_count), Style::default().fg(theme.fg)),
-                ]),
-                Line::from(vec![
-                    Span::styled("Source: ", Style::default().fg(theme.muted)),
-                    Span::styled(&stats.source, Style::default().fg(theme.fg)),
-                ]),
-                Line::from(vec![
-                    Span::styled("Load time: ", Style::default().fg(theme.muted)),
-                    Span::styled(format!("{}ms", stats.load_time_ms), Style::default().fg(theme.fg)),
-                ]),
-            ]
+    /// Switch to a different mode
+    pub fn set_mode(&mut self, mode: AppMode) {
+        self.mode = mode;
+        self.selected = 0;
+        self.list_state.select(Some(0));
+        self.cursor_to(true);
+
+        match mode {
+            AppMode::Search => {
+                self.status = format!("{} packages indexed", self.database.len());
+            }
+            AppMode::Universal => {
+                self.load_flatpak();
+                if self.flatpak.is_loaded() && !self.query.is_empty() {
+                    self.search_flatpak();
+                }
+            }
+            AppMode::History => {
+                self.status = format!(
+                    "History: {} success, {} failed",
+                    self.history.success_count(),
+                    self.history.failure_count()
+                );
+            }
+            AppMode::Favorites => {
+                self.list_favorites();
+                // A favorite can be listed once per repository it's in
+                let found: HashSet<&str> = self
+                    .favorite_results
+                    .iter()
+                    .filter_map(|&idx| self.database.get_name(idx))
+                    .collect();
+                let missing = self.favorites.len() - found.len();
+                self.status = if self.favorites.is_empty() {
+                    String::from("No favorites yet - star packages with Ctrl+F")
+                } else if missing > 0 {
+                    format!("{} favorites ({} not in the database)", self.favorites.len(), missing)
+                } else {
+                    format!("{} favorites", self.favorites.len())
+                };
+            }
+            #[cfg(feature = "terraflow")]
+            AppMode::Audit => {
+                self.run_audit();
+            }
+        }
+    }
+
+    /// Load Flatpak database on demand (lazy)
+    ///
+    /// The first load parses AppStream data on a worker thread;
+    /// `poll_flatpak` picks it up.
+    pub fn load_flatpak(&mut self) {
+        if !FlatpakDatabase::is_available() {
+            self.status = String::from("Flatpak not installed");
+            return;
+        }
+
+        if self.flatpak.is_loaded() {
+            self.show_flatpak_stats();
+            return;
+        }
+
+        self.status = String::from("Loading Flatpak database...");
+        if self.flatpak_loader.is_some() {
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        self.spawn_worker(move || {
+            let mut flatpak = FlatpakDatabase::new();
+            let result = flatpak.load();
+            let _ = tx.send((flatpak, result));
+        });
+        self.flatpak_loader = Some(rx);
+    }
+
+    /// Install a Flatpak database finished loading in the background
+    pub fn poll_flatpak(&mut self) {
+        let Some(rx) = &self.flatpak_loader else {
+            return;
+        };
+
+        match rx.try_recv() {
+            Ok((flatpak, result)) => {
+                self.flatpak_loader = None;
+                match result {
+                    Ok(()) => {
+                        self.flatpak = flatpak;
+                        self.show_flatpak_stats();
+                        if self.mode == AppMode::Universal && !self.query.is_empty() {
+                            self.search_flatpak();
+                        }
+                    }
+                    Err(e) => self.notify(format!("Flatpak error: {}", e)),
+                }
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                self.flatpak_loader = None;
+                self.notify("Flatpak error: loader crashed");
+            }
+        }
+    }
+
+    fn show_flatpak_stats(&mut self) {
+        let stats = &self.flatpak.stats;
+        self.status = format!(
+            "Flatpak: {} apps in {}ms ({})",
+            stats.app_count, stats.load_time_ms, stats.source
+        );
+    }
+
+    /// Search Flatpaks
+    pub fn search_flatpak(&mut self) {
+        if self.query.len() < 2 && !(self.installed_only && self.query.is_empty()) {
+            self.flatpak_results.clear();
+            self.status = String::from("Type at least 2 chars...");
+            return;
+        }
+
+        let start = Instant::now();
+        self.flatpak_results = self.flatpak.search(
+            &self.query,
+            self.config.max_results,
+            self.flatpak_summary,
+            self.installed_only,
+        );
+        let elapsed_us = start.elapsed().as_micros();
+
+        self.status = format!("Found {} Flatpaks in {}µs", self.flatpak_results.len(), elapsed_us);
+        self.selected = 0;
+        self.list_state.select(Some(0));
+    }
+
+    // Navigation methods
+
+    /// Number of entries in the current mode's list
+    fn list_len(&self) -> usize {
+        match self.mode {
+            AppMode::Search => self.results.len(),
+            AppMode::Universal => self.flatpak_results.len(),
+            AppMode::History => self.history_view().len(),
+            AppMode::Favorites => self.favorite_results.len(),
+            #[cfg(feature = "terraflow")]
+            AppMode::Audit => self.audit_result.as_ref().map_or(0, |r| match self.audit_view {
+                AuditView::Missing => r.missing.len(),
+                AuditView::Extra => r.extra.len(),
+            }),
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.step_selection(false, self.config.wrap_navigation);
+    }
+
+    pub fn select_next(&mut self) {
+        self.step_selection(true, self.config.wrap_navigation);
+    }
+
+    /// Move the selection one entry, wrapping around the ends if `wrap`
+    /// is set and stopping at them otherwise
+    fn step_selection(&mut self, forward: bool, wrap: bool) {
+        let len = self.list_len();
+        if len == 0 {
+            return;
+        }
+        let last = len - 1;
+        self.selected = match (forward, wrap) {
+            (true, true) if self.selected >= last => 0,
+            (false, true) if self.selected == 0 => last,
+            (true, _) => (self.selected + 1).min(last),
+            (false, _) => self.selected.saturating_sub(1),
+        };
+        self.list_state.select(Some(self.selected));
+    }
+
+    pub fn page_up(&mut self) {
+        self.selected = self.selected.saturating_sub(10);
+        self.list_state.select(Some(self.selected));
+    }
+
+    pub fn page_down(&mut self) {
+        let len = self.list_len();
+        self.selected = (self.selected + 10).min(len.saturating_sub(1));
+        self.list_state.select(Some(self.selected));
+    }
+
+    /// Jump to the top of the list
+    pub fn select_first(&mut self) {
+        self.selected = 0;
+        self.list_state.select(Some(self.selected));
+    }
+
+    /// Jump to the bottom of the list
+    pub fn select_last(&mut self) {
+        self.selected = self.list_len().saturating_sub(1);
+        self.list_state.select(Some(self.selected));
+    }
+
+    /// Entry of the current list under a screen position, if any
+    fn entry_at(&self, column: u16, row: u16) -> Option<usize> {
+        if !self.list_area.contains(Position::new(column, row)) {
+            return None;
+        }
+        let line = self.list_offset + usize::from(row - self.list_area.y);
+        match self.mode {
+            // Day separators take up rows of their own
+            AppMode::History => {
+                let view = self.history_view();
+                let records = view.iter().map(|&idx| &self.history.records[idx]);
+                match history_rows(records).get(line)? {
+                    HistoryRow::Record(i) => Some(*i),
+                    HistoryRow::Day(_) => None,
+                }
+            }
+            _ => (line < self.list_len()).then_some(line),
+        }
+    }
+
+    /// Select the clicked entry; a double-click in Search mode activates it
+    /// like Enter
+    pub fn click(&mut self, column: u16, row: u16) {
+        let Some(index) = self.entry_at(column, row) else {
+            return;
+        };
+        let now = Instant::now();
+        let double = matches!(self.last_click, Some((at, last))
+            if last == index && now.duration_since(at) <= DOUBLE_CLICK);
+
+        self.selected = index;
+        self.list_state.select(Some(index));
+        if double && matches!(self.mode, AppMode::Search | AppMode::Favorites) {
+            self.last_click = None;
+            self.activate_selected();
         } else {
-            vec![
-                Line::from(Span::styled("Flatpak not loaded", Style::default().fg(theme.muted))),
-                Line::from(""),
-                Line::from(Span::styled("Type to search Flatpaks", Style::default().fg(theme.fg))),
-            ]
+            self.last_click = Some((now, index));
         }
-    };
+    }
+
+    pub fn selected_package(&self) -> Option<(&str, PackageSource)> {
+        let idx = self.selected_index()?;
+        let name = self.database.get_name(idx)?;
+        let source = self.database.get_source(idx)?;
+        Some((name, source))
+    }
+
+    /// Get the database index of the selected search result (or
+    /// favorite)
+    fn selected_index(&self) -> Option<usize> {
+        let list = match self.mode {
+            AppMode::Search => &self.results,
+            AppMode::Favorites => &self.favorite_results,
+            _ => return None,
+        };
+        let idx = *list.get(self.selected)?;
+        (idx < self.database.len()).then_some(idx)
+    }
+
+    /// Get the selected Flatpak in Universal mode
+    pub fn selected_flatpak(&self) -> Option<&FlatpakApp> {
+        if self.mode != AppMode::Universal {
+            return None;
+        }
+        self.flatpak.get(*self.flatpak_results.get(self.selected)?)
+    }
+
+    /// Name of the selected entry in any mode, as the package manager
+    /// takes it (Flatpaks by app id)
+    pub fn selected_name(&self) -> Option<String> {
+        match self.mode {
+            AppMode::Search | AppMode::Favorites => self.selected_package().map(|(name, _)| name.to_string()),
+            AppMode::Universal => self.selected_flatpak().map(|flatpak| flatpak.id.clone()),
+            AppMode::History => self.selected_history_record().map(|record| record.name.clone()),
+            #[cfg(feature = "terraflow")]
+            AppMode::Audit => {
+                let result = self.audit_result.as_ref()?;
+                match self.audit_view {
+                    AuditView::Missing => result.missing.get(self.selected).map(|pkg| pkg.name.clone()),
+                    AuditView::Extra => result.extra.get(self.selected).cloned(),
+                }
+            }
+        }
+    }
+
+    /// Copy the selected entry's name to the system clipboard
+    pub fn copy_selected_name(&mut self) {
+        let Some(name) = self.selected_name() else {
+            return;
+        };
+        self.status = match clipboard::copy(&name) {
+            Ok(_) => format!("Copied {}", name),
+            Err(e) => format!("✗ {}", e),
+        };
+    }
+
+    /// Remote to install a Flatpak from: the one listing it, else the
+    /// configured fallback
+    pub fn flatpak_remote(&self, app_id: &str) -> String {
+        self.flatpak
+            .remote_of(app_id)
+            .unwrap_or(&self.config.flatpak_remote)
+            .to_string()
+    }
+
+    /// Queue installation of the selected Flatpak, unless it is installed
+    pub fn install_selected_flatpak(&mut self) {
+        let Some(flatpak) = self.selected_flatpak() else {
+            return;
+        };
+        let name = flatpak.name.clone();
+
+        if self.flatpak.is_installed(&flatpak.id) {
+            self.status = format!("{} is already installed (Del to uninstall)", name);
+            return;
+        }
+
+        let action = PendingAction::InstallFlatpak {
+            app_id: flatpak.id.clone(),
+            name: name.clone(),
+            remote: self.flatpak_remote(&flatpak.id),
+        };
+        self.queue_install(name, PackageSource::Flatpak, action);
+    }
+
+    /// Install the package of the selected History record again
+    ///
+    /// Retries failed installs, and brings back removed packages. The
+    /// record's source must still be usable: AUR records need an AUR
+    /// helper and Flatpak records need Flatpak.
+    pub fn reinstall_from_history(&mut self) {
+        let Some(record) = self.selected_history_record() else {
+            return;
+        };
+        if record.action == Action::Upgrade {
+            self.status = String::from("Press F9 to upgrade the system");
+            return;
+        }
+        let (name, source) = (record.name.clone(), record.source);
+
+        let action = match source {
+            PackageSource::Aur if aur_helper().is_none() => {
+                self.notify(format!("✗ Can't reinstall {}: {}", name, RepoError::AurHelperNotFound));
+                return;
+            }
+            PackageSource::Flatpak if !FlatpakDatabase::is_available() => {
+                self.notify(format!("✗ Can't reinstall {}: Flatpak not installed", name));
+                return;
+            }
+            PackageSource::Flatpak => PendingAction::InstallFlatpak {
+                remote: self.flatpak_remote(&name),
+                app_id: name.clone(),
+                name: name.clone(),
+            },
+            _ => PendingAction::Install {
+                name: name.clone(),
+                source,
+            },
+        };
+        self.queue_install(name, source, action);
+    }
+
+    /// Get the indexed description of the selected search result
+    pub fn selected_description(&self) -> Option<&str> {
+        self.selected_index()
+            .and_then(|idx| self.database.get_description(idx))
+            .filter(|desc| !desc.is_empty())
+    }
+
+    /// Check whether a package is installed locally
+    pub fn is_installed(&self, name: &str) -> bool {
+        self.installed.contains(name)
+    }
+
+    /// Handle Enter on the selected search result
+    ///
+    /// Installed packages open the action menu unless the config asks
+    /// for a plain reinstall.
+    pub fn activate_selected(&mut self) {
+        let Some((name, source)) = self.selected_package() else {
+            return;
+        };
+        let name = name.to_string();
+
+        if self.is_installed(&name) && self.config.enter_on_installed == InstalledAction::Menu {
+            self.overlay = Some(Overlay::Actions {
+                name,
+                source,
+                selected: 0,
+            });
+        } else {
+            let action = PendingAction::Install {
+                name: name.clone(),
+                source,
+            };
+            self.queue_install(name, source, action);
+        }
+    }
+
+    /// Queue a single install, asking first unless confirmation is off
+    fn queue_install(&mut self, name: String, source: PackageSource, action: PendingAction) {
+        if self.config.confirm_install {
+            self.overlay = Some(Overlay::ConfirmInstall { name, source, action });
+        } else {
+            self.pending_action = Some(action);
+        }
+    }
+
+    /// Accept the install confirmation and queue the install
+    pub fn confirm_install(&mut self) {
+        if let Some(Overlay::ConfirmInstall { action, .. }) = self.overlay.take() {
+            self.pending_action = Some(action);
+        }
+    }
+
+    /// Queue removal of the selected package, if it is installed
+    pub fn remove_selected(&mut self) {
+        let Some((name, source)) = self.selected_package() else {
+            return;
+        };
+
+        if self.is_installed(name) {
+            self.pending_action = Some(PendingAction::Remove {
+                name: name.to_string(),
+                source,
+            });
+        } else {
+            self.status = format!("{} is not installed", name);
+        }
+    }
+
+    /// Queue removal of the selected Flatpak, if it is installed
+    pub fn remove_selected_flatpak(&mut self) {
+        let Some(flatpak) = self.selected_flatpak() else {
+            return;
+        };
+
+        if self.flatpak.is_installed(&flatpak.id) {
+            self.pending_action = Some(PendingAction::RemoveFlatpak {
+                app_id: flatpak.id.clone(),
+                name: flatpak.name.clone(),
+            });
+        } else {
+            self.status = format!("{} is not installed", flatpak.name);
+        }
+    }
+
+    /// Mark every displayed result for batch install, up to `max_batch`
+    ///
+    /// If every displayed result is already marked, unmark them instead.
+    pub fn mark_all(&mut self) {
+        if self.results.iter().all(|idx| self.selected_set.contains(idx)) {
+            for idx in &self.results {
+                self.selected_set.remove(idx);
+            }
+            self.status = format!("{} packages marked", self.selected_set.len());
+            return;
+        }
+
+        let room = self.config.max_batch.saturating_sub(self.selected_set.len());
+        let unmarked: Vec<usize> = self
+            .results
+            .iter()
+            .copied()
+            .filter(|idx| !self.selected_set.contains(idx))
+            .collect();
+        let added = unmarked.len().min(room);
+        self.selected_set.extend(&unmarked[..added]);
+
+        self.status = if added < unmarked.len() {
+            format!(
+                "⚠ Marked {} of {} results (batch limit {})",
+                added,
+                unmarked.len(),
+                self.config.max_batch
+            )
+        } else {
+            format!("{} packages marked", self.selected_set.len())
+        };
+    }
+
+    /// Mark or unmark the selected result and move on to the next one
+    pub fn toggle_mark(&mut self) {
+        let Some(idx) = self.selected_index() else {
+            return;
+        };
+
+        if !self.selected_set.remove(&idx) {
+            if self.selected_set.len() >= self.config.max_batch {
+                self.notify(format!("⚠ Batch limit reached ({})", self.config.max_batch));
+                return;
+            }
+            self.selected_set.insert(idx);
+        }
+        self.status = format!("{} packages marked", self.selected_set.len());
+        self.select_next();
+    }
+
+    /// Install every marked package, confirming first for large batches
+    /// or when the packages conflict
+    pub fn install_marked(&mut self) {
+        let mut packages: Vec<(String, PackageSource)> = self
+            .selected_set
+            .iter()
+            .filter_map(|&idx| {
+                let name = self.database.get_name(idx)?;
+                Some((name.to_string(), self.database.get_source(idx)?))
+            })
+            .collect();
+        packages.sort_by(|a, b| a.0.cmp(&b.0));
+
+        if packages.is_empty() {
+            self.status = String::from("No packages marked (Ctrl+A marks all results)");
+            return;
+        }
+
+        // Only details already fetched are used; the rest keep loading in
+        // the background and fill in the popup as they arrive
+        self.request_marked_details();
+        let overlay = self.batch_overlay(packages);
+
+        // A package whose info isn't in might conflict, so only a fully
+        // checked small batch skips the popup
+        let checked_and_clear = matches!(
+            &overlay,
+            Overlay::ConfirmBatch { packages, unchecked: 0, conflicts, .. }
+                if packages.len() < self.config.batch_confirm_threshold && conflicts.is_empty()
+        );
+        match overlay {
+            Overlay::ConfirmBatch { packages, .. } if checked_and_clear => {
+                self.selected_set.clear();
+                self.pending_action = Some(PendingAction::InstallBatch { packages });
+            }
+            overlay => self.overlay = Some(overlay),
+        }
+    }
+
+    /// Batch confirmation for `packages`, with sizes and conflicts from
+    /// the details fetched so far
+    fn batch_overlay(&self, packages: Vec<(String, PackageSource)>) -> Overlay {
+        let infos: Vec<PackageInfo> = packages
+            .iter()
+            .filter_map(|(name, _)| self.details.get(name)?.clone())
+            .collect();
+        Overlay::ConfirmBatch {
+            download_size: infos.iter().map(|info| info.download_size).sum(),
+            installed_size: infos.iter().map(|info| info.installed_size).sum(),
+            unchecked: packages.len() - infos.len(),
+            conflicts: find_conflicts(&infos, &self.installed),
+            packages,
+        }
+    }
+
+    /// Install every package the audit found missing, each from the
+    /// source its package list names
+    ///
+    /// Always asks first. Details aren't looked up, since reprovisioning
+    /// can mean hundreds of `-Si` calls; only ones already cached count.
+    #[cfg(feature = "terraflow")]
+    pub fn install_missing(&mut self) {
+        let packages: Vec<(String, PackageSource)> = self
+            .audit_result
+            .iter()
+            .flat_map(|result| &result.missing)
+            .map(|pkg| (pkg.name.clone(), pkg.source))
+            .collect();
+
+        if packages.is_empty() {
+            self.status = String::from("Nothing missing to install");
+            return;
+        }
+
+        self.overlay = Some(self.batch_overlay(packages));
+    }
+
+    /// Show what syncing the system to the TerraFlow package lists
+    /// would install, and (on request) remove
+    #[cfg(feature = "terraflow")]
+    pub fn show_sync_plan(&mut self) {
+        let Some(ref tf) = self.terraflow else {
+            self.status = String::from("TerraFlow not configured");
+            return;
+        };
+        let plan = tf.plan();
+
+        if plan.install.is_empty() && plan.remove.is_empty() {
+            self.status = String::from("✓ System matches the package lists");
+            return;
+        }
+        self.overlay = Some(Overlay::SyncPlan {
+            plan,
+            remove: false,
+            scroll: 0,
+        });
+    }
+
+    /// Accept the sync plan and queue it
+    #[cfg(feature = "terraflow")]
+    pub fn confirm_sync(&mut self) {
+        let Some(Overlay::SyncPlan { plan, remove, .. }) = self.overlay.take() else {
+            return;
+        };
+        let install: Vec<_> = plan.install.into_iter().map(|pkg| (pkg.name, pkg.source)).collect();
+        let remove: Vec<_> = match remove {
+            true => plan.remove.into_iter().map(|removal| (removal.name, removal.source)).collect(),
+            false => Vec::new(),
+        };
+
+        if install.is_empty() && remove.is_empty() {
+            self.status = String::from("Nothing to do (r includes removals)");
+            return;
+        }
+        self.pending_action = Some(PendingAction::Sync { install, remove });
+    }
+
+    /// Accept the batch confirmation and queue the install
+    pub fn confirm_batch(&mut self) {
+        if let Some(Overlay::ConfirmBatch { packages, .. }) = self.overlay.take() {
+            self.selected_set.clear();
+            self.pending_action = Some(PendingAction::InstallBatch { packages });
+        }
+    }
+
+    /// Run the highlighted entry of the action menu
+    pub fn choose_menu_action(&mut self) {
+        let Some(Overlay::Actions {
+            name,
+            source,
+            selected,
+        }) = self.overlay.take()
+        else {
+            return;
+        };
+
+        match MenuAction::ALL[selected] {
+            MenuAction::Reinstall => {
+                self.pending_action = Some(PendingAction::Install { name, source });
+            }
+            MenuAction::Remove => {
+                self.pending_action = Some(PendingAction::Remove { name, source });
+            }
+            MenuAction::Details => self.show_details(&name, source),
+        }
+    }
+
+    /// Show package info in a popup
+    ///
+    /// Info that isn't cached is fetched in the background (AUR lookups
+    /// go over the network); the popup says it's loading until then.
+    pub fn show_details(&mut self, name: &str, source: PackageSource) {
+        let text = match self.details.get(name) {
+            Some(Some(info)) => info.to_display_string(),
+            _ => String::from("Loading details…"),
+        };
+        self.overlay = Some(Overlay::Info {
+            title: name.to_string(),
+            text,
+            scroll: 0,
+        });
+        if matches!(self.details.get(name), Some(Some(_))) {
+            return;
+        }
+
+        self.details_popup = Some(name.to_string());
+        // The preview may already be fetching it; a failed lookup is retried
+        if self.detail_in_flight.as_deref() != Some(name) {
+            self.details.remove(name);
+            let (name, installed) = (name.to_string(), self.is_installed(name));
+            let tx = self.detail_tx.clone();
+            thread::spawn(move || {
+                let info = fetch_info(&RepoManager::new(), &name, source, installed);
+                let _ = tx.send((name, info.ok()));
+            });
+        }
+    }
+
+    /// Show the selected package's full info in a popup, reusing the
+    /// preview's details when they have already been fetched
+    pub fn show_selected_info(&mut self) {
+        if let Some(info) = self.selected_info() {
+            self.overlay = Some(Overlay::Info {
+                title: info.name.clone(),
+                text: info.to_display_string(),
+                scroll: 0,
+            });
+        } else if let Some((name, source)) = self.selected_package() {
+            let name = name.to_string();
+            self.show_details(&name, source);
+        }
+    }
+
+    /// Toggle whether Flatpak search matches summaries too
+    pub fn toggle_flatpak_summary(&mut self) {
+        self.flatpak_summary = !self.flatpak_summary;
+        self.search_flatpak();
+    }
+
+    /// Switch to the next built-in theme
+    pub fn cycle_theme(&mut self) {
+        let next = match self.theme_name.and_then(|name| Theme::NAMES.iter().position(|&n| n == name)) {
+            Some(i) => (i + 1) % Theme::NAMES.len(),
+            None => 0,
+        };
+        let name = Theme::NAMES[next];
+
+        if let Some(theme) = Theme::named(name) {
+            self.theme = theme.quantized(ColorLevel::detect());
+            self.theme_name = Some(name);
+            self.status = format!("Theme: {}", name);
+        }
+    }
+
+    pub fn toggle_source(&mut self) {
+        self.source_filter = self.source_filter.next();
+        if self.mode == AppMode::Search {
+            self.search();
+        }
+    }
+
+    /// Rebuild the database from pacman/paru in the background
+    pub fn refresh_database(&mut self) {
+        self.start_rebuild(|cancel| {
+            let _ = PackageDatabase::invalidate_cache();
+            PackageDatabase::load_or_build_cancellable(cancel)
+        });
+    }
+
+    /// Run `build` on a worker thread; `poll_rebuild` swaps the result in
+    ///
+    /// The current database stays searchable meanwhile. Only one rebuild
+    /// runs at a time, so overlapping refreshes can't race. `build` gets
+    /// the flag `shutdown` sets to skip writing the cache.
+    fn start_rebuild(&mut self, build: impl FnOnce(&AtomicBool) -> PackageDatabase + Send + 'static) {
+        if self.rebuild.is_some() {
+            self.status = String::from("Rebuild already in progress");
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::clone(&self.rebuild_cancel);
+        self.spawn_worker(move || {
+            let _ = tx.send(build(&cancel));
+        });
+        self.rebuild = Some((rx, Instant::now()));
+        self.status = String::from("Rebuilding package database...");
+    }
+
+    /// Swap in a finished background rebuild
+    pub fn poll_rebuild(&mut self) {
+        let Some((rx, started)) = &self.rebuild else {
+            return;
+        };
+
+        match rx.try_recv() {
+            Ok(database) => {
+                let elapsed = started.elapsed();
+                self.rebuild = None;
+                self.finish_loading(database, elapsed);
+                // Re-run the query against the new database
+                if !self.query.is_empty() {
+                    self.search();
+                }
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                self.rebuild = None;
+                self.notify("✗ Database rebuild failed");
+            }
+        }
+    }
+
+    /// Check whether a background rebuild is running
+    pub fn is_rebuilding(&self) -> bool {
+        self.rebuild.is_some()
+    }
+
+    /// Check whether any long operation is running
+    pub fn is_busy(&self) -> bool {
+        self.is_loading || self.is_rebuilding() || self.flatpak_loader.is_some()
+    }
+
+    /// Run a long operation on a thread `shutdown` waits for
+    fn spawn_worker(&mut self, work: impl FnOnce() + Send + 'static) {
+        self.workers.retain(|worker| !worker.is_finished());
+        self.workers.push(thread::spawn(work));
+    }
+
+    /// Quit, asking first if a rebuild or Flatpak load is still running
+    pub fn request_quit(&mut self) {
+        let mut running = Vec::new();
+        if self.is_rebuilding() {
+            running.push("database rebuild");
+        }
+        if self.flatpak_loader.is_some() {
+            running.push("Flatpak loading");
+        }
+
+        if running.is_empty() {
+            self.should_quit = true;
+        } else {
+            self.overlay = Some(Overlay::ConfirmQuit { running: running.join(", ") });
+        }
+    }
+
+    /// Stop background work before exiting: a running rebuild skips
+    /// writing the cache, and every worker thread is joined
+    pub fn shutdown(&mut self) {
+        self.rebuild_cancel.store(true, Ordering::SeqCst);
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+
+    /// Show `message` in the status line and keep it for the
+    /// notifications pane, for results that shouldn't scroll by unseen
+    pub fn notify(&mut self, message: impl Into<String>) {
+        self.status = message.into();
+        if self.notifications.len() == NOTIFICATION_LIMIT {
+            self.notifications.pop_back();
+        }
+        self.notifications.push_front((current_timestamp(), self.status.clone()));
+    }
+
+    /// Save new history records, reporting a failure (e.g. a read-only
+    /// data directory) instead of dropping it
+    pub fn flush_history(&mut self) {
+        if let Err(e) = self.history.flush() {
+            self.notify(format!("⚠ History not saved: {}", e));
+        }
+    }
+
+    /// Open the recent notifications, newest first
+    pub fn show_notifications(&mut self) {
+        let text = if self.notifications.is_empty() {
+            String::from("No notifications yet")
+        } else {
+            self.notifications
+                .iter()
+                .map(|(at, message)| format!("{:>12}  {}", relative_time(*at), message))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        self.overlay = Some(Overlay::Info {
+            title: format!("Notifications ({})", self.notifications.len()),
+            text,
+            scroll: 0,
+        });
+    }
+
+    /// Advance the animation by one main loop iteration
+    pub fn tick(&mut self) {
+        self.spinner_tick = self.spinner_tick.wrapping_add(1);
+    }
+
+    /// Current spinner frame, if a long operation is running
+    pub fn spinner(&self) -> Option<&'static str> {
+        if !self.is_busy() {
+            return None;
+        }
+        let frame = self.spinner_tick / TICKS_PER_SPINNER_FRAME;
+        Some(SPINNER_FRAMES[frame % SPINNER_FRAMES.len()])
+    }
+}
+
+/// Look up a package's info, plus what depends on it when it's installed
+fn fetch_info(repos: &RepoManager, name: &str, source: PackageSource, installed: bool) -> Result<PackageInfo, RepoError> {
+    let mut info = repos.get_info(name, source)?;
+    if installed {
+        info.required_by = Pacman::required_by(name);
+    }
+    Ok(info)
+}
+
+/// Initialize terminal
+pub fn init_terminal() -> io::Result<Terminal<CrosstermBackend<Stdout>>> {
+    enable_raw_mode()?;
+    TERMINAL_ACTIVE.store(true, Ordering::SeqCst);
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    Terminal::new(CrosstermBackend::new(stdout))
+}
+
+/// Restore terminal
+///
+/// Does nothing if the panic hook already restored it.
+pub fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> io::Result<()> {
+    if !TERMINAL_ACTIVE.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()
+}
+
+/// Restore the terminal before a panic on the main thread is reported,
+/// so the message lands in a usable shell instead of the alternate screen
+///
+/// Worker thread panics leave the TUI alone; their callers notice the
+/// dropped channel and carry on.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let on_main = thread::current().name() == Some("main");
+        if on_main && TERMINAL_ACTIVE.swap(false, Ordering::SeqCst) {
+            let _ = disable_raw_mode();
+            let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, Show);
+        }
+        default_hook(info);
+    }));
+}
+
+/// Main rendering function
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(10),
+            Constraint::Length(3),
+        ])
+        .split(frame.area());
+
+    draw_header(frame, chunks[0], app);
+
+    let content_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+        .split(chunks[1]);
+
+    match app.mode {
+        _ if app.is_loading => draw_loading(frame, chunks[1], app),
+        AppMode::Search => {
+            draw_package_list(frame, content_chunks[0], app);
+            draw_preview(frame, content_chunks[1], app);
+        }
+        AppMode::Universal => {
+            draw_flatpak_list(frame, content_chunks[0], app);
+            draw_flatpak_preview(frame, content_chunks[1], app);
+        }
+        AppMode::History => {
+            draw_history_list(frame, content_chunks[0], app);
+            draw_history_detail(frame, content_chunks[1], app);
+        }
+        AppMode::Favorites => {
+            draw_favorites_list(frame, content_chunks[0], app);
+            draw_preview(frame, content_chunks[1], app);
+        }
+        #[cfg(feature = "terraflow")]
+        AppMode::Audit => {
+            draw_audit_list(frame, content_chunks[0], app);
+            draw_audit_detail(frame, content_chunks[1], app);
+        }
+    }
+
+    draw_footer(frame, chunks[2], app);
+
+    if let Some(ref overlay) = app.overlay {
+        draw_overlay(frame, overlay, &app.theme);
+    }
+}
+
+/// Compute a rect centered in `area` taking the given percentages
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+fn draw_overlay(frame: &mut Frame, overlay: &Overlay, theme: &Theme) {
+    match overlay {
+        Overlay::Actions { name, selected, .. } => {
+            let area = centered_rect(40, 30, frame.area());
+            let items: Vec<ListItem> = MenuAction::ALL
+                .iter()
+                .map(|action| ListItem::new(Span::styled(action.label(), Style::default().fg(theme.fg))))
+                .collect();
+
+            let list = List::new(items)
+                .block(
+                    Block::default()
+                        .title(Span::styled(
+                            format!(" {} (installed) ", name),
+                            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+                        ))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.accent)),
+                )
+                .highlight_style(Style::default().bg(theme.highlight_bg).add_modifier(Modifier::BOLD))
+                .highlight_symbol("➜ ");
+
+            let mut state = ListState::default();
+            state.select(Some(*selected));
+
+            frame.render_widget(Clear, area);
+            frame.render_stateful_widget(list, area, &mut state);
+        }
+        Overlay::Info { title, text, scroll } => {
+            let area = centered_rect(70, 70, frame.area());
+            let popup = Paragraph::new(text.as_str())
+                .style(Style::default().fg(theme.fg))
+                .block(
+                    Block::default()
+                        .title(Span::styled(
+                            format!(" {} ", title),
+                            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+                        ))
+                        .title_bottom(Span::styled(" ↑↓ Scroll | Esc Close ", Style::default().fg(theme.muted)))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.accent)),
+                )
+                .wrap(Wrap { trim: false })
+                .scroll((*scroll, 0));
+
+            frame.render_widget(Clear, area);
+            frame.render_widget(popup, area);
+        }
+        Overlay::ConfirmQuit { running } => {
+            let area = centered_rect(40, 20, frame.area());
+            let popup = Paragraph::new(vec![
+                Line::from(""),
+                Line::from(Span::styled(format!("Still running: {}", running), Style::default().fg(theme.fg))),
+                Line::from(Span::styled("Quitting waits for it to finish", Style::default().fg(theme.muted))),
+            ])
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .block(
+                Block::default()
+                    .title(Span::styled(
+                        " Quit? ",
+                        Style::default().fg(theme.warning).add_modifier(Modifier::BOLD),
+                    ))
+                    .title_bottom(Span::styled(" y Quit | n Cancel ", Style::default().fg(theme.muted)))
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.warning)),
+            );
+
+            frame.render_widget(Clear, area);
+            frame.render_widget(popup, area);
+        }
+        Overlay::ConfirmInstall { name, source, .. } => {
+            let area = centered_rect(40, 20, frame.area());
+            let line = Line::from(vec![
+                source_tag(*source, theme),
+                Span::raw(" "),
+                Span::styled(name.as_str(), Style::default().fg(theme.fg).add_modifier(Modifier::BOLD)),
+            ]);
+
+            let popup = Paragraph::new(vec![Line::from(""), line])
+                .alignment(Alignment::Center)
+                .block(
+                    Block::default()
+                        .title(Span::styled(
+                            " Install? ",
+                            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+                        ))
+                        .title_bottom(Span::styled(" y Install | n Cancel ", Style::default().fg(theme.muted)))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.accent)),
+                );
+
+            frame.render_widget(Clear, area);
+            frame.render_widget(popup, area);
+        }
+        Overlay::ConfirmBatch {
+            packages,
+            download_size,
+            installed_size,
+            unchecked,
+            conflicts,
+        } => {
+            let area = centered_rect(60, 70, frame.area());
+            let unknown = if *unchecked > 0 { " +?" } else { "" };
+
+            let mut lines = vec![
+                Line::from(vec![
+                    Span::styled("Download: ", Style::default().fg(theme.muted)),
+                    Span::styled(format!("{}{}", format_size(*download_size), unknown), Style::default().fg(theme.fg)),
+                    Span::styled("  Installed: ", Style::default().fg(theme.muted)),
+                    Span::styled(format!("{}{}", format_size(*installed_size), unknown), Style::default().fg(theme.fg)),
+                ]),
+                Line::from(""),
+            ];
+            if *unchecked > 0 {
+                lines.push(Line::from(Span::styled(
+                    format!("⚠ Conflicts unchecked for {} (info still loading or unavailable)", unchecked),
+                    Style::default().fg(theme.warning),
+                )));
+                lines.push(Line::from(""));
+            }
+            if !conflicts.is_empty() {
+                lines.extend(conflicts.iter().map(|conflict| {
+                    Line::from(Span::styled(format!("⚠ {}", conflict), Style::default().fg(theme.error)))
+                }));
+                lines.push(Line::from(""));
+            }
+            lines.extend(packages.iter().map(|(name, source)| {
+                Line::from(vec![
+                    source_tag(*source, theme),
+                    Span::raw(" "),
+                    Span::styled(name.as_str(), Style::default().fg(theme.fg)),
+                ])
+            }));
+
+            let popup = Paragraph::new(lines).block(
+                Block::default()
+                    .title(Span::styled(
+                        format!(" Install {} packages? ", packages.len()),
+                        Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+                    ))
+                    .title_bottom(Span::styled(" y Install | n Cancel ", Style::default().fg(theme.muted)))
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.accent)),
+            );
+
+            frame.render_widget(Clear, area);
+            frame.render_widget(popup, area);
+        }
+        #[cfg(feature = "terraflow")]
+        Overlay::SyncPlan { plan, remove, scroll } => {
+            let area = centered_rect(70, 80, frame.area());
+            let heading = |text: String| Line::from(Span::styled(text, Style::default().fg(theme.fg).add_modifier(Modifier::BOLD)));
+
+            let mut lines = vec![heading(format!("Install ({})", plan.install.len()))];
+            lines.extend(plan.install.iter().map(|pkg| {
+                Line::from(vec![
+                    Span::raw("  "),
+                    source_tag(pkg.source, theme),
+                    Span::raw(" "),
+                    Span::styled(pkg.name.as_str(), Style::default().fg(theme.fg)),
+                    Span::styled(format!("  ({})", pkg.file), Style::default().fg(theme.muted)),
+                ])
+            }));
+            lines.push(Line::from(""));
+
+            if *remove {
+                lines.push(heading(format!("Remove ({})", plan.remove.len())));
+                let risky = plan.risky_removals();
+                if risky > 0 {
+                    lines.push(Line::from(Span::styled(
+                        format!("⚠ {} of these are needed by other packages; pacman will refuse to remove them", risky),
+                        Style::default().fg(theme.warning),
+                    )));
+                }
+                lines.extend(plan.remove.iter().map(|removal| {
+                    let mut spans = vec![
+                        Span::raw("  "),
+                        source_tag(removal.source, theme),
+                        Span::raw(" "),
+                        Span::styled(removal.name.as_str(), Style::default().fg(theme.error)),
+                    ];
+                    if !removal.required_by.is_empty() {
+                        spans.push(Span::styled(
+                            format!("  ⚠ needed by {}", removal.required_by.join(", ")),
+                            Style::default().fg(theme.warning),
+                        ));
+                    }
+                    Line::from(spans)
+                }));
+            } else {
+                lines.push(Line::from(Span::styled(
+                    format!("{} extra packages kept (r to remove them too)", plan.remove.len()),
+                    Style::default().fg(theme.muted),
+                )));
+            }
+
+            let removals = if *remove { plan.remove.len() } else { 0 };
+            let popup = Paragraph::new(lines)
+                .scroll((*scroll, 0))
+                .block(
+                    Block::default()
+                        .title(Span::styled(
+                            format!(" Sync plan: install {}, remove {} ", plan.install.len(), removals),
+                            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+                        ))
+                        .title_bottom(Span::styled(
+                            " y Apply | r Toggle removals | ↑↓ Scroll | n Cancel ",
+                            Style::default().fg(theme.muted),
+                        ))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.accent)),
+                );
+
+            frame.render_widget(Clear, area);
+            frame.render_widget(popup, area);
+        }
+    }
+}
+
+/// Color used for a package source throughout the UI
+fn source_color(source: PackageSource, theme: &Theme) -> Color {
+    match source {
+        PackageSource::Official => theme.accent,
+        PackageSource::Aur | PackageSource::Flatpak => theme.secondary,
+    }
+}
+
+/// Short `[OFF]`/`[AUR]`/`[FPK]` tag shown before package names
+fn source_tag(source: PackageSource, theme: &Theme) -> Span<'static> {
+    let tag = match source {
+        PackageSource::Official => "[OFF]",
+        PackageSource::Aur => "[AUR]",
+        PackageSource::Flatpak => "[FPK]",
+    };
+    Span::styled(tag, Style::default().fg(source_color(source, theme)))
+}
+
+fn draw_header(frame: &mut Frame, area: Rect, app: &App) {
+    let theme = &app.theme;
+
+    let mode_label = match app.mode {
+        AppMode::Search => match app.config.search_mode {
+            _ if is_regex_query(&app.query) => format!("SEARCH | {} | REGEX", app.source_filter.label()),
+            SearchMode::Instant => format!("SEARCH | {}", app.source_filter.label()),
+            SearchMode::OnEnter => format!("SEARCH | {} | ON ENTER", app.source_filter.label()),
+        },
+        AppMode::Universal => format!(
+            "UNIVERSAL (Flatpak) | {}",
+            if app.flatpak_summary { "NAME+SUMMARY" } else { "NAME" }
+        ),
+        AppMode::History => format!("HISTORY | {}", app.history_status.label()),
+        AppMode::Favorites => String::from("FAVORITES"),
+        #[cfg(feature = "terraflow")]
+        AppMode::Audit => format!("AUDIT | {}", app.audit_view.label()),
+    };
+
+    let mut search_block = Block::default()
+        .title(Span::styled(
+            format!(" 🔍 TERRA STORE | {} ", mode_label),
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        ))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.border));
+
+    if let Some(count) = app.updates.as_ref().map(Vec::len).filter(|&count| count > 0) {
+        search_block = search_block.title(
+            Line::from(Span::styled(
+                format!(" ⬆ Updates: {} (F8) ", count),
+                Style::default().fg(theme.warning).add_modifier(Modifier::BOLD),
+            ))
+            .right_aligned(),
+        );
+    }
+
+    let content = if app.mode.takes_text() {
+        let mut spans = vec![Span::styled("> ", Style::default().fg(theme.accent))];
+        let input = app.input();
+        if input.is_empty() {
+            let hint = if app.mode == AppMode::History { "Type to filter..." } else { "Type to search..." };
+            spans.push(Span::styled(hint, Style::default().fg(theme.muted)));
+            spans.push(Span::styled("█", Style::default().fg(theme.accent)));
+        } else {
+            // The cursor highlights the character it sits on, or trails
+            // the input as a block
+            let (before, rest) = input.split_at(app.cursor());
+            spans.push(Span::styled(before, Style::default().fg(theme.fg)));
+            let mut chars = rest.chars();
+            match chars.next() {
+                Some(c) => {
+                    spans.push(Span::styled(c.to_string(), Style::default().fg(theme.bg).bg(theme.accent)));
+                    spans.push(Span::styled(chars.as_str(), Style::default().fg(theme.fg)));
+                }
+                None => spans.push(Span::styled("█", Style::default().fg(theme.accent))),
+            }
+        }
+        Line::from(spans)
+    } else {
+        Line::from(vec![
+            Span::styled("Press ", Style::default().fg(theme.muted)),
+            Span::styled("1", Style::default().fg(theme.accent)),
+            Span::styled(" Search  ", Style::default().fg(theme.muted)),
+            Span::styled("2", Style::default().fg(theme.accent)),
+            Span::styled(" History  ", Style::default().fg(theme.muted)),
+            Span::styled("3", Style::default().fg(theme.accent)),
+            Span::styled(" Audit  ", Style::default().fg(theme.muted)),
+            Span::styled("4", Style::default().fg(theme.accent)),
+            Span::styled(" Favorites", Style::default().fg(theme.muted)),
+        ])
+    };
+
+    let paragraph = Paragraph::new(content).block(search_block);
+    frame.render_widget(paragraph, area);
+}
+
+fn draw_loading(frame: &mut Frame, area: Rect, app: &App) {
+    let theme = &app.theme;
+
+    let content = vec![
+        Line::from(""),
+        Line::from(Span::styled(
+            format!("{} Loading package database...", app.spinner().unwrap_or("")),
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(Span::styled(
+            "The first run queries pacman and the AUR helper, which can take a while.",
+            Style::default().fg(theme.muted),
+        )),
+        Line::from(Span::styled("Press Esc to cancel.", Style::default().fg(theme.muted))),
+    ];
+
+    let loading = Paragraph::new(content)
+        .alignment(Alignment::Center)
+        .block(Block::default().title(" Loading ").borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
+        .wrap(Wrap { trim: true });
+
+    frame.render_widget(loading, area);
+}
+
+/// Rows of a `len`-long list to draw in `height` rows, keeping the
+/// selection centred until either end of the list is in view
+fn list_window(selected: usize, len: usize, height: usize) -> std::ops::Range<usize> {
+    let start = selected
+        .saturating_sub(height / 2)
+        .min(len.saturating_sub(height));
+    start..(start + height).min(len)
+}
+
+/// Whole-row style of a manually windowed list entry
+fn row_style(selected: bool, theme: &Theme) -> Style {
+    if selected {
+        Style::default().bg(theme.highlight_bg).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default()
+    }
+}
+
+/// Highlight-symbol column for a manually windowed list
+fn row_marker(selected: bool) -> Span<'static> {
+    Span::raw(if selected { "➜ " } else { "  " })
+}
+
+fn draw_package_list(frame: &mut Frame, area: Rect, app: &mut App) {
+    if app.highlighter.query != app.query {
+        app.highlighter = Highlighter::new(&app.query);
+    }
+    let theme = &app.theme;
+    // Only the visible window is built, so ratatui gets no selection
+    // and does no scrolling of its own
+    let window = list_window(app.selected, app.results.len(), area.height.saturating_sub(2) as usize);
+    let scroll_offset = window.start;
+    // Matches are located for the visible rows only
+    let highlight = (app.config.highlight_matches && !app.query.trim().is_empty()).then_some(&app.highlighter);
+
+    let items: Vec<ListItem> = app.results[window]
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &pkg_idx)| {
+            let name = app.database.get_name(pkg_idx).unwrap_or("<invalid>");
+            let source = app.database.get_source(pkg_idx)?;
+            let actual_idx = scroll_offset + i;
+
+            let source_tag = source_tag(source, theme);
+
+            let style = if actual_idx == app.selected {
+                Style::default().bg(theme.highlight_bg).fg(theme.fg).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.fg)
+            };
+
+            let mark = if app.selected_set.contains(&pkg_idx) {
+                Span::styled("✓ ", Style::default().fg(theme.success))
+            } else {
+                Span::raw("  ")
+            };
+
+            let mut spans = vec![row_marker(actual_idx == app.selected), mark, source_tag, Span::raw(" ")];
+            match highlight {
+                Some(highlighter) => spans.extend(highlighted(name, highlighter, style, theme)),
+                None => spans.push(Span::styled(name, style)),
+            }
+            if let Some(Some(info)) = app.aur_info.get(name) {
+                spans.push(Span::styled(format!(" ▲{}", info.num_votes), Style::default().fg(theme.muted)));
+                if info.out_of_date.is_some() {
+                    spans.push(Span::styled(" ⚠ out of date", Style::default().fg(theme.warning)));
+                }
+            }
+            if app.favorites.contains(name) {
+                spans.push(Span::styled(" ★", Style::default().fg(theme.warning)));
+            }
+            if let Some(variants) = app.vcs_variants.get(&pkg_idx) {
+                spans.push(Span::styled(
+                    format!(" +{} vcs", variants.len()),
+                    Style::default().fg(theme.muted),
+                ));
+            }
+
+            Some(ListItem::new(Line::from(spans)).style(row_style(actual_idx == app.selected, theme)))
+        })
+        .collect();
+
+    let title = if app.is_loading {
+        " Loading... ".to_string()
+    } else if app.is_searching() {
+        format!(" Results ({}) | searching... ", app.results.len())
+    } else if app.query.is_empty() && !app.installed_only && !app.results.is_empty() {
+        format!(" Recently installed ({}) ", app.results.len())
+    } else {
+        format!(" Results ({}) ", app.results.len())
+    };
+
+    let list = List::new(items)
+        .block(Block::default().title(title).borders(Borders::ALL).border_style(Style::default().fg(theme.border)));
+
+    frame.render_widget(list, area);
+    track_list(app, area, scroll_offset);
+}
+
+/// Spans of `name` with the parts the query matched in the accent color
+fn highlighted<'a>(name: &'a str, highlighter: &Highlighter, style: Style, theme: &Theme) -> Vec<Span<'a>> {
+    let matched = style.fg(theme.accent).add_modifier(Modifier::BOLD);
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    for range in highlighter.ranges(name) {
+        if range.start > pos {
+            spans.push(Span::styled(&name[pos..range.start], style));
+        }
+        pos = range.end;
+        spans.push(Span::styled(&name[range], matched));
+    }
+    if pos < name.len() {
+        spans.push(Span::styled(&name[pos..], style));
+    }
+    spans
+}
+
+/// Preview lines for an AUR package's RPC data
+fn aur_lines(info: Option<Option<&AurInfo>>, theme: &Theme) -> Vec<Line<'static>> {
+    let label = |text: &'static str| Span::styled(text, Style::default().fg(theme.muted));
+    let value = |text: String| Span::styled(text, Style::default().fg(theme.fg));
+    match info {
+        Some(Some(info)) => {
+            let mut lines = vec![
+                Line::from(vec![
+                    label("Votes: "),
+                    value(format!("{} (popularity {:.2})", info.num_votes, info.popularity)),
+                ]),
+                Line::from(vec![
+                    label("Maintainer: "),
+                    match &info.maintainer {
+                        Some(maintainer) => value(maintainer.clone()),
+                        None => Span::styled("orphaned", Style::default().fg(theme.warning)),
+                    },
+                ]),
+                Line::from(vec![label("Updated: "), value(relative_time(info.last_modified))]),
+            ];
+            if let Some(flagged) = info.out_of_date {
+                lines.push(Line::from(Span::styled(
+                    format!("⚠ Flagged out of date on {}", iso_date(flagged)),
+                    Style::default().fg(theme.warning).add_modifier(Modifier::BOLD),
+                )));
+            }
+            lines
+        }
+        Some(None) => vec![Line::from(label("AUR info unavailable"))],
+        None if aur::ENABLED => vec![Line::from(label("Loading AUR info…"))],
+        None => Vec::new(),
+    }
+}
+
+fn draw_preview(frame: &mut Frame, area: Rect, app: &App) {
+    let theme = &app.theme;
+
+    let content = if let Some((name, source)) = app.selected_package() {
+        let installed = app.is_installed(name);
+        let hint = if installed && app.config.enter_on_installed == InstalledAction::Menu {
+            "Press Enter for actions, Del to remove"
+        } else if installed {
+            "Press Enter to reinstall, Del to remove"
+        } else {
+            "Press Enter to install"
+        };
+
+        let mut lines = if let Some(info) = app.selected_info() {
+            info.to_display_string()
+                .lines()
+                .map(|line| Line::from(Span::styled(line.to_string(), Style::default().fg(theme.fg))))
+                .collect()
+        } else {
+            let mut lines = vec![
+                Line::from(vec![
+                    Span::styled("📦 ", Style::default()),
+                    Span::styled(name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+                ]),
+                Line::from(""),
+            ];
+            if let Some(description) = app.selected_description() {
+                lines.push(Line::from(Span::styled(description, Style::default().fg(theme.fg))));
+                lines.push(Line::from(""));
+            }
+            if app.details_loading() {
+                lines.push(Line::from(Span::styled("Loading details…", Style::default().fg(theme.muted))));
+                lines.push(Line::from(""));
+            }
+            lines
+        };
+        lines.push(Line::from(""));
+        lines.extend([
+            Line::from(vec![
+                Span::styled("Source: ", Style::default().fg(theme.muted)),
+                Span::styled(source.to_string(), Style::default().fg(source_color(source, theme))),
+            ]),
+            Line::from(vec![
+                Span::styled("Status: ", Style::default().fg(theme.muted)),
+                if installed {
+                    Span::styled("Installed", Style::default().fg(theme.success))
+                } else {
+                    Span::styled("Not installed", Style::default().fg(theme.fg))
+                },
+            ]),
+        ]);
+        if source == PackageSource::Aur {
+            lines.extend(aur_lines(app.selected_aur_info(), theme));
+        }
+        lines.extend([Line::from(""), Line::from(Span::styled(hint, Style::default().fg(theme.muted)))]);
+        if source == PackageSource::Aur {
+            lines.push(Line::from(Span::styled(
+                "Press F6 to review the PKGBUILD",
+                Style::default().fg(theme.muted),
+            )));
+        }
+        lines
+    } else {
+        let stats = &app.database.stats;
+        // Per-source fetch times only exist for a fresh build
+        let timing = |ms: u64| {
+            if stats.was_cached {
+                String::new()
+            } else {
+                format!(" ({}ms)", ms)
+            }
+        };
+        vec![
+            Line::from(Span::styled("Database Stats", Style::default().fg(theme.fg).add_modifier(Modifier::BOLD))),
+            Line::from(""),
+            Line::from(vec![
+                Span::styled("Official: ", Style::default().fg(theme.muted)),
+                Span::styled(format!("{}", stats.official_count), Style::default().fg(theme.fg)),
+                Span::styled(timing(stats.official_ms), Style::default().fg(theme.muted)),
+            ]),
+            Line::from(vec![
+                Span::styled("AUR: ", Style::default().fg(theme.muted)),
+                Span::styled(format!("{}", stats.aur_count), Style::default().fg(theme.fg)),
+                Span::styled(timing(stats.aur_ms), Style::default().fg(theme.muted)),
+            ]),
+            Line::from(vec![
+                Span::styled("Arena: ", Style::default().fg(theme.muted)),
+                Span::styled(format!("{:.2} MB", stats.arena_bytes as f64 / 1_000_000.0), Style::default().fg(theme.fg)),
+            ]),
+        ]
+    };
+
+    let preview = Paragraph::new(content)
+        .block(Block::default().title(" Details ").borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
+        .wrap(Wrap { trim: true });
+
+    frame.render_widget(preview, area);
+}
+
+fn draw_flatpak_list(frame: &mut Frame, area: Rect, app: &mut App) {
+    let theme = &app.theme;
+
+    let results = &app.flatpak_results;
+    let window = list_window(app.selected, results.len(), area.height.saturating_sub(2) as usize);
+    let scroll_offset = window.start;
+
+    let items: Vec<ListItem> = if results.is_empty() && !app.flatpak.is_loaded() {
+        vec![ListItem::new(Line::from(Span::styled(
+            "Press F2 to load Flatpaks",
+            Style::default().fg(theme.muted),
+        )))]
+    } else {
+        results[window]
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &idx)| {
+                let flatpak = app.flatpak.get(idx)?;
+                let actual_idx = scroll_offset + i;
+                let style = if actual_idx == app.selected {
+                    Style::default().bg(theme.highlight_bg).fg(theme.fg).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(theme.fg)
+                };
+
+                let mut spans = vec![
+                    row_marker(actual_idx == app.selected),
+                    Span::styled("[FPK]", Style::default().fg(theme.secondary)),
+                    Span::raw(" "),
+                    Span::styled(&flatpak.name, style),
+                ];
+                match app.flatpak.installed_scope(&flatpak.id) {
+                    Some(InstallScope::User) => {
+                        spans.push(Span::styled(" installed (user)", Style::default().fg(theme.success)))
+                    }
+                    Some(InstallScope::System) => {
+                        spans.push(Span::styled(" installed", Style::default().fg(theme.success)))
+                    }
+                    None => {}
+                }
+
+                Some(ListItem::new(Line::from(spans)).style(row_style(actual_idx == app.selected, theme)))
+            })
+            .collect()
+    };
+
+    let title = format!(" Flatpak ({}) ", app.flatpak.len());
+
+    let list = List::new(items)
+        .block(Block::default().title(title).borders(Borders::ALL).border_style(Style::default().fg(theme.border)));
+
+    frame.render_widget(list, area);
+    track_list(app, area, scroll_offset);
+}
+
+fn draw_flatpak_preview(frame: &mut Frame, area: Rect, app: &App) {
+    let theme = &app.theme;
+
+    let content = if let Some(flatpak) = app.selected_flatpak() {
+        let installed = |label| (Span::styled(label, Style::default().fg(theme.success)), "Press Del to uninstall");
+        let (status, hint) = match app.flatpak.installed_scope(&flatpak.id) {
+            Some(InstallScope::User) => installed("Installed (user)"),
+            Some(InstallScope::System) => installed("Installed (system)"),
+            None => (
+                Span::styled("Not installed", Style::default().fg(theme.fg)),
+                "Press Enter to install (flatpak)",
+            ),
+        };
+        let field = |label, value: String| {
+            Line::from(vec![
+                Span::styled(label, Style::default().fg(theme.muted)),
+                Span::styled(value, Style::default().fg(theme.fg)),
+            ])
+        };
+
+        let mut lines = vec![
+            Line::from(vec![
+                Span::styled("📦 ", Style::default()),
+                Span::styled(&flatpak.name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+            ]),
+            Line::from(""),
+            field("ID: ", flatpak.id.clone()),
+        ];
+        if !flatpak.remote.is_empty() {
+            lines.push(field("Remote: ", flatpak.remote.clone()));
+        }
+        if !flatpak.version.is_empty() {
+            lines.push(field("Version: ", flatpak.version.clone()));
+        }
+        if !flatpak.license.is_empty() {
+            lines.push(field("License: ", flatpak.license.clone()));
+        }
+        if !flatpak.categories.is_empty() {
+            lines.push(field("Categories: ", flatpak.categories.join(", ")));
+        }
+        lines.extend([
+            Line::from(""),
+            Line::from(Span::styled(&flatpak.summary, Style::default().fg(theme.fg))),
+            Line::from(""),
+            Line::from(vec![Span::styled("Status: ", Style::default().fg(theme.muted)), status]),
+            Line::from(""),
+            Line::from(Span::styled(hint, Style::default().fg(theme.muted))),
+        ]);
+        lines
+    } else {
+        let stats = &app.flatpak.stats;
+        if app.flatpak.is_loaded() {
+            vec![
+                Line::from(Span::styled("Flatpak Database", Style::default().fg(theme.fg).add_modifier(Modifier::BOLD))),
+                Line::from(""),
+                Line::from(vec![
+                    Span::styled("Apps: ", Style::default().fg(theme.muted)),
+                    Span::styled(format!("{}", stats.app_count), Style::default().fg(theme.fg)),
+                ]),
+                Line::from(vec![
+                    Span::styled("Source: ", Style::default().fg(theme.muted)),
+                    Span::styled(&stats.source, Style::default().fg(theme.fg)),
+                ]),
+                Line::from(vec![
+                    Span::styled("Load time: ", Style::default().fg(theme.muted)),
+                    Span::styled(format!("{}ms", stats.load_time_ms), Style::default().fg(theme.fg)),
+                ]),
+            ]
+        } else {
+            vec![
+                Line::from(Span::styled("Flatpak not loaded", Style::default().fg(theme.muted))),
+                Line::from(""),
+                Line::from(Span::styled("Type to search Flatpaks", Style::default().fg(theme.fg))),
+            ]
+        }
+    };
+
+    let preview = Paragraph::new(content)
+        .block(Block::default().title(" Flatpak Details ").borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
+        .wrap(Wrap { trim: true });
+
+    frame.render_widget(preview, area);
+}
+
+/// A line of the History list: a date separator, or the record at this
+/// position of the filtered view
+#[derive(Debug, PartialEq, Eq)]
+enum HistoryRow {
+    Day(String),
+    Record(usize),
+}
+
+/// Lay out records (newest first) under a separator for each UTC day
+fn history_rows<'a>(records: impl IntoIterator<Item = &'a InstallRecord>) -> Vec<HistoryRow> {
+    let mut rows = Vec::new();
+    let mut last_day = None;
+    for (i, record) in records.into_iter().enumerate() {
+        let day = record.iso_date();
+        if last_day.as_ref() != Some(&day) {
+            rows.push(HistoryRow::Day(day.clone()));
+            last_day = Some(day);
+        }
+        rows.push(HistoryRow::Record(i));
+    }
+    rows
+}
+
+fn draw_favorites_list(frame: &mut Frame, area: Rect, app: &mut App) {
+    let theme = &app.theme;
+    let window = list_window(app.selected, app.favorite_results.len(), area.height.saturating_sub(2) as usize);
+    let scroll_offset = window.start;
+
+    let items: Vec<ListItem> = app.favorite_results[window]
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &pkg_idx)| {
+            let name = app.database.get_name(pkg_idx)?;
+            let source = app.database.get_source(pkg_idx)?;
+            let selected = scroll_offset + i == app.selected;
+            let installed = if app.is_installed(name) {
+                Span::styled("● ", Style::default().fg(theme.success))
+            } else {
+                Span::raw("  ")
+            };
+
+            let spans = vec![
+                row_marker(selected),
+                installed,
+                source_tag(source, theme),
+                Span::raw(" "),
+                Span::styled(name, Style::default().fg(theme.fg)),
+            ];
+            Some(ListItem::new(Line::from(spans)).style(row_style(selected, theme)))
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .title(format!(" ★ Favorites ({}) ", app.favorite_results.len()))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.border)),
+    );
+
+    frame.render_widget(list, area);
+    track_list(app, area, scroll_offset);
+}
+
+fn draw_history_list(frame: &mut Frame, area: Rect, app: &mut App) {
+    let theme = &app.theme;
+    let view = app.history_view();
+    let records: Vec<&InstallRecord> = view.iter().map(|&idx| &app.history.records[idx]).collect();
+    let rows = history_rows(records.iter().copied());
+
+    let items: Vec<ListItem> = rows
+        .iter()
+        .map(|row| {
+            let i = match *row {
+                HistoryRow::Day(ref day) => {
+                    return ListItem::new(Line::from(Span::styled(
+                        format!("── {} ──", day),
+                        Style::default().fg(theme.muted).add_modifier(Modifier::BOLD),
+                    )));
+                }
+                HistoryRow::Record(i) => i,
+            };
+            let record = records[i];
+            let status_icon = if record.success {
+                Span::styled("✓", Style::default().fg(theme.success))
+            } else {
+                Span::styled("✗", Style::default().fg(theme.error))
+            };
+
+            let style = if i == app.selected {
+                Style::default().bg(theme.highlight_bg).fg(theme.fg)
+            } else {
+                Style::default().fg(theme.fg)
+            };
+
+            let action_tag = match record.action {
+                Action::Install => Span::raw(""),
+                Action::Remove => Span::styled("[rm] ", Style::default().fg(theme.warning)),
+                Action::Upgrade => Span::styled("[up] ", Style::default().fg(theme.accent)),
+            };
+
+            ListItem::new(Line::from(vec![
+                status_icon,
+                Span::raw(" "),
+                action_tag,
+                Span::styled(&record.name, style),
+                Span::styled(format!(" ({})", record.formatted_time()), Style::default().fg(theme.muted)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default().title(history_title(view.len(), app.history.records.len())).borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
+        .highlight_symbol("➜ ");
+
+    // Separators shift the rows, so the widget selects the row of the
+    // selected record and only its scroll offset is kept
+    let row = rows.iter().position(|row| *row == HistoryRow::Record(app.selected));
+    let mut state = app.list_state.clone().with_selected(row);
+    frame.render_stateful_widget(list, area, &mut state);
+    *app.list_state.offset_mut() = state.offset();
+    track_list(app, area, state.offset());
+}
+
+/// Remember where a bordered list was drawn and its first shown row, so
+/// mouse clicks can be mapped back to entries
+fn track_list(app: &mut App, area: Rect, offset: usize) {
+    app.list_area = area.inner(Margin::new(1, 1));
+    app.list_offset = offset;
+}
+
+/// History list title, with the filtered count when a filter hides some
+fn history_title(shown: usize, total: usize) -> String {
+    if shown == total {
+        format!(" History ({}) ", total)
+    } else {
+        format!(" History ({}/{}) ", shown, total)
+    }
+}
+
+fn draw_history_detail(frame: &mut Frame, area: Rect, app: &App) {
+    let theme = &app.theme;
+
+    let content = if let Some(record) = app.selected_history_record() {
+        let mut lines = vec![
+            Line::from(vec![
+                Span::styled("📦 ", Style::default()),
+                Span::styled(&record.name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+            ]),
+            Line::from(""),
+            Line::from(vec![
+                Span::styled("Action: ", Style::default().fg(theme.muted)),
+                Span::styled(record.action.to_string(), Style::default().fg(theme.fg)),
+            ]),
+            Line::from(vec![
+                Span::styled("Source: ", Style::default().fg(theme.muted)),
+                Span::styled(format!("{}", record.source), Style::default().fg(theme.fg)),
+            ]),
+            Line::from(vec![
+                Span::styled("Version: ", Style::default().fg(theme.muted)),
+                match record.version {
+                    Some(ref version) => Span::styled(version, Style::default().fg(theme.fg)),
+                    None => Span::styled("unknown", Style::default().fg(theme.muted)),
+                },
+            ]),
+            Line::from(vec![
+                Span::styled("Time: ", Style::default().fg(theme.muted)),
+                Span::styled(record.iso_time(), Style::default().fg(theme.fg)),
+                Span::styled(format!(" ({})", record.formatted_time()), Style::default().fg(theme.muted)),
+            ]),
+            Line::from(vec![
+                Span::styled("Status: ", Style::default().fg(theme.muted)),
+                if record.success {
+                    Span::styled("Success", Style::default().fg(theme.success))
+                } else {
+                    Span::styled("Failed", Style::default().fg(theme.error))
+                },
+            ]),
+        ];
+
+        if let Some(ref error) = record.error {
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled("Error:", Style::default().fg(theme.error))));
+            lines.push(Line::from(Span::styled(error, Style::default().fg(theme.muted))));
+        }
+
+        lines
+    } else {
+        vec![Line::from(Span::styled("No history selected", Style::default().fg(theme.muted)))]
+    };
+
+    let preview = Paragraph::new(content)
+        .block(Block::default().title(" Details ").borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
+        .wrap(Wrap { trim: true });
+
+    frame.render_widget(preview, area);
+}
+
+#[cfg(feature = "terraflow")]
+fn draw_audit_list(frame: &mut Frame, area: Rect, app: &mut App) {
+    let theme = &app.theme;
+
+    let style = |i| {
+        if i == app.selected {
+            Style::default().bg(theme.highlight_bg).fg(theme.fg)
+        } else {
+            Style::default().fg(theme.fg)
+        }
+    };
+
+    let items: Vec<ListItem> = match (&app.audit_result, app.audit_view) {
+        (Some(result), AuditView::Missing) => result.missing.iter().enumerate().map(|(i, pkg)| {
+            ListItem::new(Line::from(vec![
+                source_tag(pkg.source, theme),
+                Span::raw(" "),
+                Span::styled(&pkg.name, style(i)),
+            ]))
+        }).collect(),
+        (Some(result), AuditView::Extra) => result.extra.iter().enumerate().map(|(i, name)| {
+            ListItem::new(Line::from(Span::styled(name, style(i))))
+        }).collect(),
+        (None, _) => vec![ListItem::new(Line::from(Span::styled("No audit data", Style::default().fg(theme.muted))))],
+    };
+
+    let title = match (&app.audit_result, app.audit_view) {
+        (Some(result), AuditView::Missing) => format!(" Missing ({}) ", result.missing.len()),
+        (Some(result), AuditView::Extra) => format!(" Extra ({}) ", result.extra.len()),
+        (None, _) => " Audit ".to_string(),
+    };
+
+    let list = List::new(items)
+        .block(Block::default().title(title).borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
+        .highlight_symbol("➜ ");
+
+    frame.render_stateful_widget(list, area, &mut app.list_state);
+    let offset = app.list_state.offset();
+    track_list(app, area, offset);
+}
+
+#[cfg(feature = "terraflow")]
+fn draw_audit_detail(frame: &mut Frame, area: Rect, app: &App) {
+    let theme = &app.theme;
+
+    let content = if let Some(ref result) = app.audit_result {
+        let extra = match app.audit_view {
+            AuditView::Extra => result.extra.get(app.selected),
+            AuditView::Missing => None,
+        };
+        let missing = match app.audit_view {
+            AuditView::Missing => result.missing.get(app.selected),
+            AuditView::Extra => None,
+        };
+
+        if let Some(name) = extra {
+            vec![
+                Line::from(vec![
+                    Span::styled("📦 ", Style::default()),
+                    Span::styled(name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+                ]),
+                Line::from(""),
+                Line::from(Span::styled(
+                    "This package is explicitly installed but in none of your package lists.",
+                    Style::default().fg(theme.warning),
+                )),
+            ]
+        } else if let Some(pkg) = missing {
+            vec![
+                Line::from(vec![
+                    Span::styled("📦 ", Style::default()),
+                    Span::styled(&pkg.name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+                ]),
+                Line::from(""),
+                Line::from(vec![
+                    Span::styled("Source: ", Style::default().fg(theme.muted)),
+                    Span::styled(format!("{}", pkg.source), Style::default().fg(theme.fg)),
+                ]),
+                Line::from(vec![
+                    Span::styled("Config: ", Style::default().fg(theme.muted)),
+                    Span::styled(&pkg.file, Style::default().fg(theme.fg)),
+                ]),
+                Line::from(""),
+                Line::from(Span::styled("This package is in your config but not installed.", Style::default().fg(theme.error))),
+            ]
+        } else {
+            vec![
+                Line::from(Span::styled("Audit Summary", Style::default().fg(theme.fg).add_modifier(Modifier::BOLD))),
+                Line::from(""),
+                Line::from(vec![
+                    Span::styled("Config packages: ", Style::default().fg(theme.muted)),
+                    Span::styled(format!("{}", result.config_count), Style::default().fg(theme.fg)),
+                ]),
+                Line::from(vec![
+                    Span::styled("Installed: ", Style::default().fg(theme.muted)),
+                    Span::styled(format!("{}", result.installed_count), Style::default().fg(theme.fg)),
+                ]),
+                Line::from(vec![
+                    Span::styled("Missing: ", Style::default().fg(theme.muted)),
+                    Span::styled(format!("{}", result.missing.len()), Style::default().fg(theme.error)),
+                ]),
+                Line::from(vec![
+                    Span::styled("Extra: ", Style::default().fg(theme.muted)),
+                    Span::styled(format!("{}", result.extra.len()), Style::default().fg(theme.warning)),
+                ]),
+            ]
+        }
+    } else {
+        vec![
+            Line::from(Span::styled("TerraFlow not configured", Style::default().fg(theme.muted))),
+            Line::from(""),
+            Line::from(Span::styled("Place package lists in:", Style::default().fg(theme.fg))),
+            Line::from(Span::styled("  ~/TerraFlow-Dotfiles/packages/", Style::default().fg(theme.accent))),
+        ]
+    };
+
+    let preview = Paragraph::new(content)
+        .block(Block::default().title(" Details ").borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
+        .wrap(Wrap { trim: true });
+
+    frame.render_widget(preview, area);
+}
+
+fn draw_footer(frame: &mut Frame, area: Rect, app: &App) {
+    let theme = &app.theme;
+    let quit = app.keymap.label(KeyAction::Quit);
+    let install = app.keymap.label(KeyAction::Install);
+    let toggle = app.keymap.label(KeyAction::ToggleSource);
+    let next_mode = app.keymap.label(KeyAction::NextMode);
+
+    let mut keybindings = match app.mode {
+        _ if app.is_loading => vec![
+            Span::styled(format!(" {}", quit), Style::default().fg(theme.accent)),
+            Span::styled(" Cancel", Style::default().fg(theme.muted)),
+        ],
+        AppMode::Search => vec![
+            Span::styled(" ↑↓", Style::default().fg(theme.accent)),
+            Span::styled(" Nav ", Style::default().fg(theme.muted)),
+            Span::styled(install.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Install ", Style::default().fg(theme.muted)),
+            Span::styled(toggle.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Source ", Style::default().fg(theme.muted)),
+            Span::styled("^L", Style::default().fg(theme.accent)),
+            Span::styled(" Clear ", Style::default().fg(theme.muted)),
+            Span::styled("Ins/^A/^X", Style::default().fg(theme.accent)),
+            Span::styled(" Mark/Install ", Style::default().fg(theme.muted)),
+            Span::styled(next_mode.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Mode ", Style::default().fg(theme.muted)),
+            Span::styled(quit.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Quit", Style::default().fg(theme.muted)),
+        ],
+        AppMode::Universal => vec![
+            Span::styled(" ↑↓", Style::default().fg(theme.accent)),
+            Span::styled(" Nav ", Style::default().fg(theme.muted)),
+            Span::styled(install.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Install ", Style::default().fg(theme.muted)),
+            Span::styled(toggle.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Summary ", Style::default().fg(theme.muted)),
+            Span::styled("F2", Style::default().fg(theme.accent)),
+            Span::styled(" Reload ", Style::default().fg(theme.muted)),
+            Span::styled(quit.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Back", Style::default().fg(theme.muted)),
+        ],
+        AppMode::History => vec![
+            Span::styled(" ↑↓", Style::default().fg(theme.accent)),
+            Span::styled(" Nav ", Style::default().fg(theme.muted)),
+            Span::styled(install.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Reinstall ", Style::default().fg(theme.muted)),
+            Span::styled(toggle.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Status ", Style::default().fg(theme.muted)),
+            Span::styled(next_mode.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Mode ", Style::default().fg(theme.muted)),
+            Span::styled(quit.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Back", Style::default().fg(theme.muted)),
+        ],
+        AppMode::Favorites => vec![
+            Span::styled(" ↑↓/jk", Style::default().fg(theme.accent)),
+            Span::styled(" Nav ", Style::default().fg(theme.muted)),
+            Span::styled(install.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Install ", Style::default().fg(theme.muted)),
+            Span::styled("f", Style::default().fg(theme.accent)),
+            Span::styled(" Unstar ", Style::default().fg(theme.muted)),
+            Span::styled("Del", Style::default().fg(theme.accent)),
+            Span::styled(" Remove ", Style::default().fg(theme.muted)),
+            Span::styled("1-4", Style::default().fg(theme.accent)),
+            Span::styled(" Mode ", Style::default().fg(theme.muted)),
+            Span::styled(quit.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Back", Style::default().fg(theme.muted)),
+        ],
+        #[cfg(feature = "terraflow")]
+        AppMode::Audit => vec![
+            Span::styled(" ↑↓/jk", Style::default().fg(theme.accent)),
+            Span::styled(" Nav ", Style::default().fg(theme.muted)),
+            Span::styled(toggle.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Missing/Extra ", Style::default().fg(theme.muted)),
+            Span::styled("^X", Style::default().fg(theme.accent)),
+            Span::styled(" Install missing ", Style::default().fg(theme.muted)),
+            Span::styled("p", Style::default().fg(theme.accent)),
+            Span::styled(" Sync plan ", Style::default().fg(theme.muted)),
+            Span::styled("1-4", Style::default().fg(theme.accent)),
+            Span::styled(" Mode ", Style::default().fg(theme.muted)),
+            Span::styled(quit.clone(), Style::default().fg(theme.accent)),
+            Span::styled(" Back", Style::default().fg(theme.muted)),
+        ],
+    };
+
+    if app.mode == AppMode::Search && !app.selected_set.is_empty() {
+        let (download_size, installed_size, unknown) = app.marked_sizes();
+        let unknown = if unknown > 0 { "+?" } else { "" };
+        keybindings.insert(
+            0,
+            Span::styled(
+                format!(
+                    " ✓ {} queued · ↓ {}{} · {}{} installed |",
+                    app.selected_set.len(),
+                    format_size(download_size),
+                    unknown,
+                    format_size(installed_size),
+                    unknown
+                ),
+                Style::default().fg(theme.success).add_modifier(Modifier::BOLD),
+            ),
+        );
+    }
+
+    if app.installed_only && matches!(app.mode, AppMode::Search | AppMode::Universal) {
+        keybindings.insert(
+            0,
+            Span::styled(" ● Installed only (^O) |", Style::default().fg(theme.warning).add_modifier(Modifier::BOLD)),
+        );
+    }
+
+    let status_style = if app.status.contains("µs") || app.status.contains("ms") {
+        Style::default().fg(theme.success)
+    } else if app.status.starts_with('✗') {
+        Style::default().fg(theme.error)
+    } else {
+        Style::default().fg(theme.muted)
+    };
+
+    let footer_block = Block::default().borders(Borders::ALL).border_style(Style::default().fg(theme.border));
+    let footer_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+        .split(footer_block.inner(area));
+
+    frame.render_widget(footer_block, area);
+    frame.render_widget(Paragraph::new(Line::from(keybindings)), footer_layout[0]);
+    let status = match app.spinner() {
+        Some(spinner) => Line::from(vec![
+            Span::styled(format!("{} ", spinner), Style::default().fg(theme.accent)),
+            Span::styled(&app.status, status_style),
+        ]),
+        None => Line::from(Span::styled(&app.status, status_style)),
+    };
+    frame.render_widget(Paragraph::new(status), footer_layout[1]);
+}
+
+/// Handle keyboard and mouse input
+pub fn handle_input(app: &mut App) -> io::Result<()> {
+    if event::poll(Duration::from_millis(16))? {
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => handle_key(app, key),
+            Event::Mouse(mouse) => handle_mouse(app, mouse),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Dispatch a mouse event: clicks select list entries, the wheel moves
+/// the selection
+pub fn handle_mouse(app: &mut App, mouse: MouseEvent) {
+    if app.is_loading || app.overlay.is_some() {
+        return;
+    }
+
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => app.click(mouse.column, mouse.row),
+        // The wheel stops at the ends even with `wrap_navigation`
+        MouseEventKind::ScrollUp => app.step_selection(false, false),
+        MouseEventKind::ScrollDown => app.step_selection(true, false),
+        _ => {}
+    }
+}
+
+/// Dispatch a single key press
+pub fn handle_key(app: &mut App, key: KeyEvent) {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    // Only the quit key is honored while the database is still loading
+    if app.is_loading {
+        if key.code == app.keymap.quit || (ctrl && key.code == KeyCode::Char('c')) {
+            app.should_quit = true;
+        }
+        return;
+    }
+
+    if app.overlay.is_some() {
+        handle_overlay_input(app, key.code);
+        return;
+    }
+
+    // A character bound to an action still types in the text modes
+    let typing = matches!(key.code, KeyCode::Char(_)) && app.mode.takes_text();
+    if !ctrl && !typing {
+        if let Some(action) = app.keymap.action(key.code) {
+            if handle_key_action(app, action) {
+                return;
+            }
+        }
+    }
+
+    match key.code {
+        // Digits are part of the query in the text modes
+        KeyCode::Char('1') if !app.mode.takes_text() => app.set_mode(AppMode::Search),
+        KeyCode::F(2) => app.set_mode(AppMode::Universal),
+        KeyCode::Char('2') if !app.mode.takes_text() => app.set_mode(AppMode::History),
+        #[cfg(feature = "terraflow")]
+        KeyCode::Char('3') if !app.mode.takes_text() => app.set_mode(AppMode::Audit),
+        KeyCode::Char('4') if !app.mode.takes_text() => app.set_mode(AppMode::Favorites),
+        KeyCode::Up => app.select_previous(),
+        KeyCode::Down => app.select_next(),
+        KeyCode::PageUp => app.page_up(),
+        KeyCode::PageDown => app.page_down(),
+        KeyCode::F(3) if app.mode == AppMode::Search => app.show_selected_info(),
+        KeyCode::F(4) if app.mode == AppMode::Search => app.show_dependencies(),
+        KeyCode::F(6) if app.mode == AppMode::Search => app.show_pkgbuild(),
+        KeyCode::F(8) => app.show_updates(),
+        KeyCode::F(9) => app.pending_action = Some(PendingAction::Upgrade),
+        // In text modes Home/End move the cursor, Ctrl+Home/End the list
+        KeyCode::Left if app.mode.takes_text() => app.cursor_left(),
+        KeyCode::Right if app.mode.takes_text() => app.cursor_right(),
+        KeyCode::Home if app.mode.takes_text() && !ctrl => app.cursor_to(false),
+        KeyCode::End if app.mode.takes_text() && !ctrl => app.cursor_to(true),
+        KeyCode::Home => app.select_first(),
+        KeyCode::End => app.select_last(),
+        KeyCode::Char('j') if !app.mode.takes_text() => app.select_next(),
+        KeyCode::Char('k') if !app.mode.takes_text() => app.select_previous(),
+        KeyCode::Char('g') if !ctrl && !app.mode.takes_text() => app.select_first(),
+        KeyCode::Char('G') if !app.mode.takes_text() => app.select_last(),
+        KeyCode::Char('t') if ctrl || !app.mode.takes_text() => app.cycle_theme(),
+        KeyCode::Char('n') if ctrl => app.show_notifications(),
+        KeyCode::Char('c') if ctrl => app.request_quit(),
+        KeyCode::Char('y') if ctrl || !app.mode.takes_text() => app.copy_selected_name(),
+        KeyCode::Char('g') if ctrl && app.mode == AppMode::Search => app.expand_vcs(),
+        KeyCode::Char('l') if ctrl && app.mode == AppMode::Search => app.clear_search(),
+        KeyCode::Char('a') if ctrl && app.mode == AppMode::Search => app.mark_all(),
+        KeyCode::Char(' ') if ctrl && app.mode == AppMode::Search => app.toggle_mark(),
+        KeyCode::Insert if app.mode == AppMode::Search => app.toggle_mark(),
+        KeyCode::Char('x') if ctrl && app.mode == AppMode::Search => app.install_marked(),
+        #[cfg(feature = "terraflow")]
+        KeyCode::Char('x') if ctrl && app.mode == AppMode::Audit => app.install_missing(),
+        #[cfg(feature = "terraflow")]
+        KeyCode::Char('p') if app.mode == AppMode::Audit => app.show_sync_plan(),
+        KeyCode::Char('o') if ctrl && matches!(app.mode, AppMode::Search | AppMode::Universal) => {
+            app.toggle_installed_only()
+        }
+        KeyCode::Char('f') if (ctrl || !app.mode.takes_text()) && matches!(app.mode, AppMode::Search | AppMode::Favorites) => {
+            app.toggle_favorite()
+        }
+        KeyCode::Delete if matches!(app.mode, AppMode::Search | AppMode::Favorites) => app.remove_selected(),
+        KeyCode::Delete if app.mode == AppMode::Universal => app.remove_selected_flatpak(),
+        KeyCode::Char('u') if ctrl && app.mode.takes_text() => app.clear_query(),
+        KeyCode::Char('w') if ctrl && app.mode.takes_text() => app.delete_word(),
+        KeyCode::Backspace if app.mode.takes_text() => app.backspace(),
+        KeyCode::Char(c) if app.mode.takes_text() => app.insert_char(c),
+        _ => {}
+    }
+}
+
+/// Run a remappable action, returning false if it doesn't apply in the
+/// current mode (so the key falls through to its regular meaning)
+fn handle_key_action(app: &mut App, action: KeyAction) -> bool {
+    match (action, app.mode) {
+        // Outside Search mode the quit key steps back to Search first
+        (KeyAction::Quit, AppMode::Search) => app.request_quit(),
+        (KeyAction::Quit, _) => app.set_mode(AppMode::Search),
+        (KeyAction::NextMode, mode) => app.set_mode(mode.next()),
+        (KeyAction::Install, AppMode::Search) => app.submit(),
+        (KeyAction::Install, AppMode::Universal) => app.install_selected_flatpak(),
+        (KeyAction::Install, AppMode::History) => app.reinstall_from_history(),
+        (KeyAction::Install, AppMode::Favorites) => app.activate_selected(),
+        (KeyAction::ToggleSource, AppMode::Search) => app.toggle_source(),
+        (KeyAction::ToggleSource, AppMode::Universal) => app.toggle_flatpak_summary(),
+        (KeyAction::ToggleSource, AppMode::History) => app.cycle_history_status(),
+        #[cfg(feature = "terraflow")]
+        (KeyAction::ToggleSource, AppMode::Audit) => app.toggle_audit_view(),
+        (KeyAction::Refresh, AppMode::Search) => app.refresh_database(),
+        _ => return false,
+    }
+    true
+}
+
+/// Handle keys while a popup is open
+fn handle_overlay_input(app: &mut App, code: KeyCode) {
+    match app.overlay {
+        Some(Overlay::Actions { ref mut selected, .. }) => match code {
+            KeyCode::Up => *selected = selected.saturating_sub(1),
+            KeyCode::Down => *selected = (*selected + 1).min(MenuAction::ALL.len() - 1),
+            KeyCode::Enter => app.choose_menu_action(),
+            KeyCode::Esc => app.overlay = None,
+            _ => {}
+        },
+        Some(Overlay::Info {
+            ref text,
+            ref mut scroll,
+            ..
+        }) => {
+            let last_line = text.lines().count().saturating_sub(1) as u16;
+            match code {
+                KeyCode::Up => *scroll = scroll.saturating_sub(1),
+                KeyCode::Down => *scroll = (*scroll + 1).min(last_line),
+                KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+                KeyCode::PageDown => *scroll = (*scroll + 10).min(last_line),
+                KeyCode::Home => *scroll = 0,
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('i') | KeyCode::F(3) => app.overlay = None,
+                _ => {}
+            }
+        }
+        Some(Overlay::ConfirmQuit { .. }) => match code {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                app.overlay = None;
+                app.should_quit = true;
+            }
+            KeyCode::Char('n') | KeyCode::Esc => app.overlay = None,
+            _ => {}
+        },
+        Some(Overlay::ConfirmInstall { .. }) => match code {
+            KeyCode::Char('y') | KeyCode::Enter => app.confirm_install(),
+            KeyCode::Char('n') | KeyCode::Esc => app.overlay = None,
+            _ => {}
+        },
+        Some(Overlay::ConfirmBatch { .. }) => match code {
+            KeyCode::Char('y') | KeyCode::Enter => app.confirm_batch(),
+            KeyCode::Char('n') | KeyCode::Esc => app.overlay = None,
+            _ => {}
+        },
+        #[cfg(feature = "terraflow")]
+        Some(Overlay::SyncPlan {
+            ref mut remove,
+            ref mut scroll,
+            ..
+        }) => match code {
+            KeyCode::Char('r') => *remove = !*remove,
+            KeyCode::Up => *scroll = scroll.saturating_sub(1),
+            KeyCode::Down => *scroll = scroll.saturating_add(1),
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+            KeyCode::PageDown => *scroll = scroll.saturating_add(10),
+            KeyCode::Char('y') | KeyCode::Enter => app.confirm_sync(),
+            KeyCode::Char('n') | KeyCode::Esc => app.overlay = None,
+            _ => {}
+        },
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_app(names: &[&str]) -> App {
+        test_app_with(names, Config::default())
+    }
+
+    fn test_app_with(names: &[&str], config: Config) -> App {
+        let mut app = App::with_config(config);
+        app.set_database(PackageDatabase::from_names(names, PackageSource::Official));
+        app.is_loading = false;
+        app
+    }
+
+    fn press(app: &mut App, code: KeyCode) {
+        handle_key(app, KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    fn type_query(app: &mut App, query: &str) {
+        for c in query.chars() {
+            press(app, KeyCode::Char(c));
+        }
+    }
+
+    /// Block until the background search settles
+    fn wait_for_search(app: &mut App) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while app.is_searching() {
+            assert!(Instant::now() < deadline, "search timed out");
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_search();
+        }
+    }
+
+    #[test]
+    fn test_instant_search_mode() {
+        let mut app = test_app(&["neofetch", "neovim", "htop"]);
+        type_query(&mut app, "neo");
+        wait_for_search(&mut app);
+        assert_eq!(app.results.len(), 2);
+    }
+
+    #[test]
+    fn test_on_enter_search_mode() {
+        let config = Config {
+            search_mode: SearchMode::OnEnter,
+            confirm_install: false,
+            ..Config::default()
+        };
+        let mut app = test_app_with(&["neofetch", "neovim", "htop"], config);
+
+        type_query(&mut app, "neo");
+        wait_for_search(&mut app);
+        assert!(app.results.is_empty());
+        assert!(app.query_dirty);
+
+        // First Enter searches instead of installing
+        press(&mut app, KeyCode::Enter);
+        wait_for_search(&mut app);
+        assert_eq!(app.results.len(), 2);
+        assert!(app.pending_action.is_none());
+
+        // Second Enter acts on the selection
+        press(&mut app, KeyCode::Enter);
+        assert!(matches!(app.pending_action, Some(PendingAction::Install { .. })));
+    }
+
+    #[test]
+    fn test_empty_query_lists_recent_installs() {
+        let mut app = test_app(&["neofetch", "neovim", "htop"]);
+        app.history.add(InstallRecord::success("htop", PackageSource::Official));
+        app.history.add(InstallRecord::success("paru", PackageSource::Aur));
+        app.history.add(InstallRecord::success("neovim", PackageSource::Official));
+        let names = |app: &App| -> Vec<String> {
+            app.results.iter().filter_map(|&idx| app.database.get_name(idx)).map(String::from).collect()
+        };
+
+        // Unindexed packages are left out
+        app.search();
+        assert_eq!(names(&app), vec!["neovim", "htop"]);
+        assert_eq!(app.selected_package().map(|(name, _)| name), Some("neovim"));
+
+        type_query(&mut app, "neo");
+        wait_for_search(&mut app);
+        assert_eq!(names(&app), vec!["neofetch", "neovim"]);
+
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('l'), KeyModifiers::CONTROL));
+        assert_eq!(names(&app), vec!["neovim", "htop"]);
+
+        app.config.recent_installs = 1;
+        app.search();
+        assert_eq!(names(&app), vec!["neovim"]);
+    }
+
+    #[test]
+    fn test_clear_search_returns_to_stats() {
+        let mut app = test_app(&["neofetch", "neovim", "htop"]);
+        type_query(&mut app, "neo");
+        wait_for_search(&mut app);
+        app.selected = 1;
+
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('l'), KeyModifiers::CONTROL));
+        assert!(app.query.is_empty());
+        assert!(app.results.is_empty());
+        assert!(!app.should_quit);
+        assert!(app.selected_package().is_none());
+        assert_eq!(app.status, "3 packages indexed");
+    }
+
+    #[test]
+    fn test_installed_only_filter() {
+        let mut app = test_app(&["neovim", "neofetch", "htop", "neomutt"]);
+        app.installed.extend(["neovim".to_string(), "htop".to_string()]);
+
+        // With no query, the filter lists everything installed
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('o'), KeyModifiers::CONTROL));
+        assert!(app.installed_only);
+        let names = |app: &App| -> Vec<String> {
+            app.results.iter().filter_map(|&idx| app.database.get_name(idx)).map(String::from).collect()
+        };
+        assert_eq!(names(&app), vec!["htop", "neovim"]);
+        assert_eq!(app.status, "2 installed packages");
+
+        type_query(&mut app, "neo");
+        wait_for_search(&mut app);
+        assert_eq!(names(&app), vec!["neovim"]);
+
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('o'), KeyModifiers::CONTROL));
+        wait_for_search(&mut app);
+        assert_eq!(names(&app).len(), 3);
+    }
+
+    #[test]
+    fn test_query_editing_keys() {
+        let mut app = test_app(&["neovim", "neovim-qt", "htop"]);
+        app.source_filter = SourceFilter::Official;
+        type_query(&mut app, "neovim  qt ");
+
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(app.query, "neovim  ");
+        wait_for_search(&mut app);
+        assert_eq!(app.results.len(), 2);
+
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(app.query, "");
+
+        type_query(&mut app, "htop");
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert!(app.query.is_empty());
+        assert!(app.results.is_empty());
+        assert_eq!(app.source_filter, SourceFilter::Official);
+    }
+
+    #[test]
+    fn test_cursor_editing() {
+        let mut app = test_app(&["neovim", "htop"]);
+        type_query(&mut app, "nvim");
+
+        // Insert and delete in the middle, across a multi-byte char
+        press(&mut app, KeyCode::Left);
+        press(&mut app, KeyCode::Left);
+        press(&mut app, KeyCode::Left);
+        type_query(&mut app, "éo");
+        assert_eq!(app.query, "néovim");
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Backspace);
+        type_query(&mut app, "eo");
+        assert_eq!(app.query, "neovim");
+        assert_eq!(app.cursor(), 3);
+
+        press(&mut app, KeyCode::Home);
+        type_query(&mut app, "x ");
+        press(&mut app, KeyCode::End);
+        press(&mut app, KeyCode::Right);
+        assert_eq!(app.cursor(), app.query.len());
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(app.query, "x ");
+    }
+
+    #[test]
+    fn test_remove_only_offered_when_installed() {
+        let mut app = test_app(&["neofetch", "htop"]);
+        app.results = vec![0, 1];
+        app.installed.insert("htop".to_string());
+
+        press(&mut app, KeyCode::Delete);
+        assert!(app.pending_action.is_none());
+        assert_eq!(app.status, "neofetch is not installed");
+
+        app.selected = 1;
+        press(&mut app, KeyCode::Delete);
+        assert_eq!(
+            app.pending_action,
+            Some(PendingAction::Remove {
+                name: "htop".to_string(),
+                source: PackageSource::Official,
+            })
+        );
+    }
+
+    #[test]
+    fn test_mark_all_respects_filter_and_limits() {
+        let mut db = PackageDatabase::from_names(&["ttf-a", "ttf-b", "ttf-c"], PackageSource::Official);
+        db.append_names(&["ttf-aur"], PackageSource::Aur);
+        let names: Vec<String> = (0..Config::default().max_results + 100).map(|i| format!("lib{}", i)).collect();
+        db.append_names(&names, PackageSource::Official);
+
+        let config = Config {
+            max_batch: 2,
+            ..Config::default()
+        };
+        let mut app = test_app_with(&[], config);
+        app.set_database(db);
+
+        // Only the AUR result is displayed, so only it gets marked
+        app.source_filter = SourceFilter::Aur;
+        type_query(&mut app, "ttf");
+        wait_for_search(&mut app);
+        app.mark_all();
+        assert_eq!(app.selected_set, HashSet::from([3]));
+
+        // The batch cap stops marking early
+        app.source_filter = SourceFilter::All;
+        app.search();
+        wait_for_search(&mut app);
+        app.mark_all();
+        assert_eq!(app.selected_set.len(), 2);
+
+        // Pressing again on a fully marked list unmarks it
+        app.selected_set.clear();
+        app.config.max_batch = usize::MAX;
+        app.clear_search();
+        type_query(&mut app, "lib");
+        wait_for_search(&mut app);
+        app.mark_all();
+        assert_eq!(app.selected_set.len(), app.config.max_results);
+        app.mark_all();
+        assert!(app.selected_set.is_empty());
+    }
+
+    #[test]
+    fn test_details_fetch_is_debounced_and_cached() {
+        let mut app = test_app(&["neofetch", "htop"]);
+        app.results = vec![0, 1];
+
+        // The first poll only notes the selection
+        app.poll_details();
+        assert!(app.details_loading());
+        assert!(app.detail_in_flight.is_none());
+
+        // Moving on before the debounce restarts the wait
+        app.select_next();
+        app.poll_details();
+        assert!(app.detail_in_flight.is_none());
+        assert_eq!(app.detail_request.as_ref().map(|(name, _)| name.as_str()), Some("htop"));
+
+        // Cached details are shown without another fetch
+        let info = PackageInfo {
+            name: "htop".to_string(),
+            ..PackageInfo::default()
+        };
+        app.details.insert("htop".to_string(), Some(info));
+        app.poll_details();
+        assert!(!app.details_loading());
+        assert_eq!(app.selected_info().map(|info| info.name.as_str()), Some("htop"));
+    }
+
+    #[test]
+    fn test_info_popup_loads_in_background() {
+        let mut app = test_app(&["htop"]);
+        app.results = vec![0];
+        // The preview's lookup is already running
+        app.detail_in_flight = Some("htop".to_string());
+
+        press(&mut app, KeyCode::F(3));
+        assert!(matches!(app.overlay, Some(Overlay::Info { ref text, .. }) if text == "Loading details…"));
+
+        let info = PackageInfo {
+            name: "htop".to_string(),
+            version: "3.3.0-3".to_string(),
+            ..PackageInfo::default()
+        };
+        app.detail_tx.send(("htop".to_string(), Some(info))).unwrap();
+        app.poll_details();
+        assert!(matches!(app.overlay, Some(Overlay::Info { ref text, .. }) if text.contains("3.3.0-3")));
+    }
+
+    #[test]
+    fn test_info_popup_scrolls_and_closes() {
+        let mut app = test_app(&["htop"]);
+        app.results = vec![0];
+        let info = PackageInfo {
+            name: "htop".to_string(),
+            depends: (0..30).map(|i| format!("lib{}", i)).collect(),
+            ..PackageInfo::default()
+        };
+        app.details.insert("htop".to_string(), Some(info));
+
+        press(&mut app, KeyCode::F(3));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Up);
+        assert!(matches!(app.overlay, Some(Overlay::Info { ref title, scroll: 1, .. }) if title == "htop"));
+
+        // Scrolling stops at the last line
+        for _ in 0..10 {
+            press(&mut app, KeyCode::PageDown);
+        }
+        let Some(Overlay::Info { ref text, scroll, .. }) = app.overlay else {
+            panic!("expected the info popup");
+        };
+        assert_eq!(scroll as usize, text.lines().count() - 1);
+
+        press(&mut app, KeyCode::Char('i'));
+        assert!(app.overlay.is_none());
+    }
+
+    #[test]
+    fn test_batch_sizes_still_loading_are_unknown() {
+        let mut app = test_app(&["htop", "neovim"]);
+        app.selected_set.extend([0, 1]);
+        app.details.insert(
+            "htop".to_string(),
+            Some(PackageInfo {
+                name: "htop".to_string(),
+                download_size: 1024,
+                ..PackageInfo::default()
+            }),
+        );
+        // neovim's lookup is still in flight
+        app.size_requests.insert("neovim".to_string());
+
+        app.config.batch_confirm_threshold = 1;
+        app.install_marked();
+        match &app.overlay {
+            Some(Overlay::ConfirmBatch {
+                download_size,
+                unchecked,
+                ..
+            }) => assert_eq!((*download_size, *unchecked), (1024, 1)),
+            other => panic!("expected batch confirmation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_conflicting_batch_asks_first() {
+        let mut app = test_app(&["pipewire-pulse", "pulseaudio"]);
+        let info = |name: &str, conflict: &str| PackageInfo {
+            name: name.to_string(),
+            conflicts: vec![conflict.to_string()],
+            ..PackageInfo::default()
+        };
+        app.selected_set.extend([0, 1]);
+        for name in ["pipewire-pulse", "pulseaudio"] {
+            app.size_requests.insert(name.to_string());
+        }
+
+        // Nothing fetched yet: below the threshold, but it still asks
+        app.config.batch_confirm_threshold = 10;
+        app.install_marked();
+        assert!(app.pending_action.is_none());
+        match &app.overlay {
+            Some(Overlay::ConfirmBatch { unchecked, conflicts, .. }) => {
+                assert_eq!((*unchecked, conflicts.len()), (2, 0));
+            }
+            other => panic!("expected batch confirmation, got {:?}", other),
+        }
+
+        // The popup fills in as the details arrive
+        app.detail_tx.send(("pipewire-pulse".to_string(), Some(info("pipewire-pulse", "pulseaudio")))).unwrap();
+        app.detail_tx.send(("pulseaudio".to_string(), Some(info("pulseaudio", "pipewire-pulse")))).unwrap();
+        app.poll_details();
+        match &app.overlay {
+            Some(Overlay::ConfirmBatch { unchecked, conflicts, .. }) => {
+                assert_eq!((*unchecked, conflicts.len()), (0, 1));
+            }
+            other => panic!("expected batch confirmation, got {:?}", other),
+        }
+
+        // Fully checked and still conflicting, it asks again next time
+        app.overlay = None;
+        app.install_marked();
+        assert!(matches!(app.overlay, Some(Overlay::ConfirmBatch { ref conflicts, .. }) if conflicts.len() == 1));
+    }
+
+    #[test]
+    fn test_quit_confirms_while_rebuilding() {
+        let mut app = test_app(&["neofetch"]);
+        let (release, gate) = mpsc::channel::<()>();
+        let (saw_cancel, cancelled) = mpsc::channel();
+        app.start_rebuild(move |cancel| {
+            let _ = gate.recv();
+            let _ = saw_cancel.send(cancel.load(Ordering::SeqCst));
+            PackageDatabase::from_names(&["neofetch"], PackageSource::Official)
+        });
+
+        press(&mut app, KeyCode::Esc);
+        assert!(!app.should_quit);
+        assert!(matches!(app.overlay, Some(Overlay::ConfirmQuit { ref running }) if running == "database rebuild"));
+        press(&mut app, KeyCode::Char('n'));
+        assert!(app.overlay.is_none());
+
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('y'));
+        assert!(app.should_quit);
+
+        // Shutdown tells the rebuild to skip the cache and waits for it
+        let flag = Arc::clone(&app.rebuild_cancel);
+        thread::spawn(move || {
+            while !flag.load(Ordering::SeqCst) {
+                thread::yield_now();
+            }
+            release.send(()).unwrap();
+        });
+        app.shutdown();
+        assert!(app.workers.is_empty());
+        assert!(cancelled.recv().unwrap());
+
+        // With nothing running, quitting is immediate
+        let mut app = test_app(&["neofetch"]);
+        press(&mut app, KeyCode::Esc);
+        assert!(app.should_quit);
+        assert!(app.overlay.is_none());
+    }
+
+    #[test]
+    fn test_background_rebuild() {
+        let mut app = test_app(&["neofetch", "htop"]);
+        let (release, gate) = mpsc::channel::<()>();
+        app.start_rebuild(move |_| {
+            let _ = gate.recv();
+            PackageDatabase::from_names(&["neofetch", "neovim", "htop"], PackageSource::Official)
+        });
+        assert!(app.is_rebuilding());
+
+        // A second refresh while one is running is ignored
+        app.start_rebuild(|_| panic!("overlapping rebuild started"));
+        assert_eq!(app.status, "Rebuild already in progress");
+
+        // Searching still works against the old database
+        type_query(&mut app, "neo");
+        wait_for_search(&mut app);
+        assert_eq!(app.results.len(), 1);
+
+        release.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while app.is_rebuilding() {
+            assert!(Instant::now() < deadline, "rebuild timed out");
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_rebuild();
+        }
+
+        // The query is re-run against the new database
+        wait_for_search(&mut app);
+        assert_eq!(app.results.len(), 2);
+    }
+
+    #[test]
+    fn test_spinner_runs_only_while_busy() {
+        let mut app = App::with_config(Config::default());
+        let first = app.spinner();
+        assert!(first.is_some());
+
+        for _ in 0..TICKS_PER_SPINNER_FRAME {
+            app.tick();
+        }
+        assert_ne!(app.spinner(), first);
+
+        app.finish_loading(PackageDatabase::from_names(&["htop"], PackageSource::Official), Duration::ZERO);
+        assert!(app.spinner().is_none());
+        assert!(app.status.starts_with("Loaded "));
+    }
+
+    #[test]
+    fn test_enter_installs_selected_flatpak() {
+        let mut app = test_app(&[]);
+        let flatpak = |id: &str, name: &str| FlatpakApp {
+            id: id.to_string(),
+            name: name.to_string(),
+            ..FlatpakApp::default()
+        };
+        app.flatpak = FlatpakDatabase::from_apps(vec![
+            flatpak("org.gimp.GIMP", "GIMP"),
+            flatpak("org.kde.krita", "Krita"),
+            flatpak("com.example.Kritique", "Kritique"),
+        ]);
+        app.mode = AppMode::Universal;
+
+        type_query(&mut app, "krit");
+        assert_eq!(app.flatpak_results, vec![1, 2]);
+
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.selected_flatpak().map(|f| f.name.as_str()), Some("Kritique"));
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.selected, 1); // Navigation stops at the last result
+
+        press(&mut app, KeyCode::Enter);
+        assert!(matches!(app.overlay, Some(Overlay::ConfirmInstall { .. })));
+        press(&mut app, KeyCode::Char('y'));
+        assert_eq!(
+            app.pending_action,
+            Some(PendingAction::InstallFlatpak {
+                app_id: "com.example.Kritique".to_string(),
+                name: "Kritique".to_string(),
+                // No remote listed it, so the configured fallback is used
+                remote: "flathub".to_string(),
+            })
+        );
+        press(&mut app, KeyCode::Delete);
+        assert_eq!(app.status, "Kritique is not installed");
+        app.flatpak.mark_installed("com.example.Kritique", InstallScope::User);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.status, "Kritique is already installed (Del to uninstall)");
+        press(&mut app, KeyCode::Delete);
+        assert_eq!(
+            app.pending_action,
+            Some(PendingAction::RemoveFlatpak {
+                app_id: "com.example.Kritique".to_string(),
+                name: "Kritique".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_set_database_drops_stale_selection() {
+        let mut app = test_app(&["alpha", "beta", "gamma", "delta", "epsilon"]);
+        app.results = vec![0, 1, 2, 3, 4];
+        app.selected = 4;
+        assert_eq!(app.selected_package().map(|(name, _)| name), Some("epsilon"));
+
+        app.set_database(PackageDatabase::from_names(&["alpha", "beta"], PackageSource::Official));
+        assert!(app.results.is_empty());
+        assert_eq!(app.selected, 0);
+        assert!(app.selected_package().is_none());
+
+        // Even a stale index that slipped through must not resolve
+        app.results = vec![4];
+        assert!(app.selected_package().is_none());
+    }
+
+    #[test]
+    fn test_restore_session_reruns_search() {
+        let mut app = test_app(&["neofetch", "neovim", "htop"]);
+        app.restore_session(Session {
+            query: "neo".to_string(),
+            source_filter: Some(SourceFilter::Official),
+            mode: AppMode::Search,
+        });
+        wait_for_search(&mut app);
+
+        assert_eq!(app.results.len(), 2);
+        assert_eq!(app.session().query, "neo");
+        assert_eq!(app.session().source_filter, Some(SourceFilter::Official));
+    }
+
+    #[test]
+    fn test_favorites_sort_first_and_list_in_their_mode() {
+        let mut app = test_app(&["neofetch", "neomutt", "neovim", "htop"]);
+        let names = |app: &App, list: &[usize]| -> Vec<String> {
+            list.iter().filter_map(|&idx| app.database.get_name(idx)).map(String::from).collect()
+        };
+        type_query(&mut app, "neo");
+        wait_for_search(&mut app);
+        assert_eq!(names(&app, &app.results), vec!["neofetch", "neomutt", "neovim"]);
+
+        // Star neovim; the next search puts it first
+        app.selected = 2;
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('f'), KeyModifiers::CONTROL));
+        assert!(app.favorites.contains("neovim"));
+        assert_eq!(app.query, "neo");
+        type_query(&mut app, "v");
+        press(&mut app, KeyCode::Backspace);
+        wait_for_search(&mut app);
+        assert_eq!(names(&app, &app.results), vec!["neovim", "neofetch", "neomutt"]);
+
+        app.set_mode(AppMode::Favorites);
+        assert_eq!(app.selected_package().map(|(name, _)| name), Some("neovim"));
+
+        // Plain f unstars outside the text modes
+        press(&mut app, KeyCode::Char('f'));
+        assert!(app.favorites.is_empty());
+        assert_eq!(app.list_len(), 0);
+    }
+
+    #[test]
+    fn test_favorite_in_several_repos_counts_once() {
+        let mut app = test_app(&[]);
+        let mut db = PackageDatabase::from_names(&["neovim"], PackageSource::Official);
+        db.append_names(&["neovim"], PackageSource::Aur);
+        app.set_database(db);
+        app.favorites.toggle("neovim");
+        app.favorites.toggle("vim-gone");
+
+        app.set_mode(AppMode::Favorites);
+        assert_eq!(app.list_len(), 2);
+        assert_eq!(app.status, "2 favorites (1 not in the database)");
+    }
+
+    #[test]
+    fn test_digits_switch_modes_outside_text_modes() {
+        let mut app = test_app(&["gtk4"]);
+        type_query(&mut app, "gtk4");
+        assert_eq!(app.mode, AppMode::Search);
+        assert_eq!(app.query, "gtk4");
+
+        app.set_mode(AppMode::Favorites);
+        press(&mut app, KeyCode::Char('2'));
+        assert_eq!(app.mode, AppMode::History);
+        press(&mut app, KeyCode::Char('1'));
+        assert_eq!(app.mode, AppMode::History);
+        assert_eq!(app.history_filter, "1");
+    }
+
+    #[test]
+    fn test_esc_goes_back_to_search() {
+        let mut app = test_app(&["neofetch"]);
+        app.set_mode(AppMode::History);
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.mode, AppMode::Search);
+        assert!(!app.should_quit);
+
+        press(&mut app, KeyCode::Esc);
+        assert!(app.should_quit);
+
+        // Ctrl+C quits from any mode
+        let mut app = test_app(&["neofetch"]);
+        app.set_mode(AppMode::History);
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn test_remapped_quit_key() {
+        let mut app = test_app(&["neofetch"]);
+        app.keymap = Keymap::parse("quit = \"F10\"").0;
+
+        // Esc is no longer bound to anything
+        press(&mut app, KeyCode::Esc);
+        assert!(!app.should_quit);
+
+        press(&mut app, KeyCode::F(10));
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn test_character_bindings_type_in_text_modes() {
+        let mut app = test_app(&["neofetch"]);
+        app.keymap = Keymap::parse("next_mode = \"m\"\nrefresh = \"r\"").0;
+
+        type_query(&mut app, "mr");
+        assert_eq!(app.query, "mr");
+        assert_eq!(app.mode, AppMode::Search);
+        assert!(!app.is_rebuilding());
+
+        // Outside the text modes the bindings apply
+        app.set_mode(AppMode::Favorites);
+        press(&mut app, KeyCode::Char('m'));
+        assert_eq!(app.mode, AppMode::Favorites.next());
+    }
+
+    #[test]
+    fn test_cycle_theme() {
+        let mut app = test_app(&["neofetch"]);
+        let ctrl_t = KeyEvent::new(KeyCode::Char('t'), KeyModifiers::CONTROL);
+
+        handle_key(&mut app, ctrl_t);
+        assert_eq!(app.theme_name, Some("dark"));
+        handle_key(&mut app, ctrl_t);
+        assert_eq!(app.theme_name, Some("light"));
+        assert_eq!(app.status, "Theme: light");
+
+        // In text modes a plain t is part of the query or filter
+        press(&mut app, KeyCode::Char('t'));
+        app.set_mode(AppMode::History);
+        press(&mut app, KeyCode::Char('t'));
+        assert_eq!(app.theme_name, Some("light"));
+        assert_eq!((app.query.as_str(), app.history_filter.as_str()), ("t", "t"));
+        handle_key(&mut app, ctrl_t);
+        assert_eq!(app.theme_name, Some("gruvbox"));
+    }
+
+    #[test]
+    #[cfg(feature = "terraflow")]
+    fn test_vim_keys_only_navigate_without_a_query() {
+        let mut app = test_app(&["neofetch", "neovim"]);
+
+        // Search mode types the letters
+        type_query(&mut app, "jk");
+        assert_eq!(app.query, "jk");
+
+        app.set_mode(AppMode::Audit);
+        let missing = ["a", "b", "c"].map(|name| crate::terraflow::PackageEntry {
+            name: name.to_string(),
+            source: PackageSource::Official,
+            file: "base.txt".to_string(),
+        });
+        app.audit_result = Some(AuditResult {
+            missing: missing.to_vec(),
+            extra: Vec::new(),
+            config_count: 3,
+            installed_count: 0,
+        });
+        press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.selected, 1);
+        press(&mut app, KeyCode::Char('G'));
+        assert_eq!(app.selected, 2);
+        press(&mut app, KeyCode::Char('k'));
+        assert_eq!(app.selected, 1);
+        press(&mut app, KeyCode::Char('g'));
+        assert_eq!(app.selected, 0);
+        assert_eq!(app.query, "jk");
+    }
+
+    #[test]
+    fn test_reinstall_from_history() {
+        let mut app = test_app(&["neofetch"]);
+        app.config.confirm_install = false;
+        app.history.add(InstallRecord::failure("htop", PackageSource::Official, "exit 1").with_action(Action::Remove));
+        app.history.add(InstallRecord::success("system", PackageSource::Official).with_action(Action::Upgrade));
+        app.set_mode(AppMode::History);
+
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.pending_action, None);
+        assert_eq!(app.status, "Press F9 to upgrade the system");
+
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(
+            app.pending_action,
+            Some(PendingAction::Install { name: "htop".to_string(), source: PackageSource::Official })
+        );
+    }
+
+    #[test]
+    fn test_notifications_newest_first_and_capped() {
+        let mut app = test_app(&["neofetch"]);
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('n'), KeyModifiers::CONTROL));
+        match app.overlay.take() {
+            Some(Overlay::Info { text, .. }) => assert_eq!(text, "No notifications yet"),
+            other => panic!("expected the notifications pane, got {:?}", other),
+        }
+
+        for i in 0..NOTIFICATION_LIMIT + 5 {
+            app.notify(format!("✓ Installed pkg{}", i));
+        }
+        assert_eq!(app.status, "✓ Installed pkg54");
+
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('n'), KeyModifiers::CONTROL));
+        match app.overlay {
+            Some(Overlay::Info { ref title, ref text, .. }) => {
+                assert_eq!(title, "Notifications (50)");
+                let lines: Vec<&str> = text.lines().collect();
+                assert_eq!(lines.len(), NOTIFICATION_LIMIT);
+                assert_eq!(lines[0], "    just now  ✓ Installed pkg54");
+                assert!(lines[49].ends_with("pkg5"));
+            }
+            ref other => panic!("expected the notifications pane, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_selected_name_per_mode() {
+        let mut app = test_app(&["neofetch", "htop"]);
+        assert_eq!(app.selected_name(), None);
+        type_query(&mut app, "htop");
+        wait_for_search(&mut app);
+        assert_eq!(app.selected_name().as_deref(), Some("htop"));
+
+        app.history.add(InstallRecord::success("org.gimp.GIMP", PackageSource::Flatpak));
+        app.set_mode(AppMode::History);
+        assert_eq!(app.selected_name().as_deref(), Some("org.gimp.GIMP"));
+    }
+
+    #[test]
+    fn test_history_save_failure_is_reported() {
+        let dir = std::env::temp_dir().join(format!("terra-history-fail-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // A file where the data directory should be makes every save fail
+        std::fs::write(dir.join("data"), "").unwrap();
+        let mut app = test_app(&["neofetch"]);
+        app.history = History::load_from(dir.join("data").join("history.json"));
+
+        app.flush_history();
+        assert!(app.notifications.is_empty(), "nothing to save yet");
+
+        app.history.record_success("htop", PackageSource::Official, Action::Install, None);
+        app.notify("✓ Installed htop");
+        app.flush_history();
+        assert!(app.status.starts_with("⚠ History not saved: "), "{}", app.status);
+        assert_eq!(app.notifications.len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wrap_navigation() {
+        let mut app = test_app(&["neofetch", "neovim", "htop"]);
+        type_query(&mut app, "ne");
+        wait_for_search(&mut app);
+
+        // Clamped by default
+        press(&mut app, KeyCode::Up);
+        assert_eq!(app.selected, 0);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.selected, 1);
+
+        app.config.wrap_navigation = true;
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.selected, 0);
+        press(&mut app, KeyCode::Up);
+        assert_eq!(app.selected, 1);
+    }
+
+    #[test]
+    fn test_list_window_boundaries() {
+        // Centred in the middle, pinned at either end, never past the list
+        assert_eq!(list_window(0, 100, 10), 0..10);
+        assert_eq!(list_window(50, 100, 10), 45..55);
+        assert_eq!(list_window(99, 100, 10), 90..100);
+        assert_eq!(list_window(96, 100, 10), 90..100);
+        assert_eq!(list_window(3, 4, 10), 0..4);
+        assert_eq!(list_window(0, 0, 10), 0..0);
+        assert_eq!(list_window(5, 10, 0), 5..5);
+    }
+
+    #[test]
+    fn test_selection_visible_at_list_end() {
+        use ratatui::backend::TestBackend;
+
+        let names: Vec<String> = (0..40).map(|i| format!("pkg{:02}", i)).collect();
+        let mut app = test_app(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        type_query(&mut app, "pkg");
+        wait_for_search(&mut app);
+        app.select_last();
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+
+        // The last entry is on the list's last row, behind the marker
+        let buffer = terminal.backend().buffer();
+        let row: String = (1..20).map(|x| buffer[(x, 19)].symbol().to_string()).collect();
+        assert!(row.starts_with("➜   [OFF] pkg39"), "{row:?}");
+        assert_eq!(app.entry_at(5, 19), Some(39));
+    }
+
+    #[test]
+    fn test_matches_highlighted_in_results() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = test_app(&["neovim", "htop"]);
+        type_query(&mut app, "vim");
+        wait_for_search(&mut app);
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+
+        // "neovim" starts at x=11, behind the marker, mark and source tag
+        let buffer = terminal.backend().buffer();
+        assert_eq!(buffer[(11, 4)].symbol(), "n");
+        assert_eq!(buffer[(11, 4)].fg, app.theme.fg);
+        assert_eq!(buffer[(14, 4)].fg, app.theme.accent);
+
+        app.config.highlight_matches = false;
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        assert_eq!(terminal.backend().buffer()[(14, 4)].fg, app.theme.fg);
+    }
+
+    #[test]
+    fn test_preview_shows_cached_aur_info() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_config(Config::default());
+        app.set_database(PackageDatabase::from_names(&["paru", "yay"], PackageSource::Aur));
+        app.is_loading = false;
+        app.details.insert("paru".to_string(), None);
+        app.details.insert("yay".to_string(), None);
+        app.aur_info.insert(
+            "paru".to_string(),
+            Some(AurInfo {
+                name: "paru".to_string(),
+                num_votes: 812,
+                popularity: 21.5,
+                maintainer: None,
+                last_modified: current_timestamp() - 3 * 86400,
+                out_of_date: Some(1_709_942_400),
+            }),
+        );
+        app.aur_info.insert("yay".to_string(), None);
+        type_query(&mut app, "paru");
+        wait_for_search(&mut app);
 
-    let preview = Paragraph::new(content)
-        .block(Block::default().title(" Flatpak Details ").borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
-        .wrap(Wrap { trim: true });
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        let screen = |terminal: &Terminal<TestBackend>| -> String {
+            let buffer = terminal.backend().buffer();
+            buffer.content().iter().map(|cell| cell.symbol()).collect()
+        };
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        let text = screen(&terminal);
+        assert!(text.contains("[AUR] paru ▲812 ⚠ out of date"));
+        assert!(text.contains("⚠ Flagged out of date on 2024-03-09"));
+        assert!(text.contains("Votes: 812 (popularity 21.50)"));
+        assert!(text.contains("Maintainer: orphaned"));
+        assert!(text.contains("Updated: 3 days ago"));
 
-    frame.render_widget(preview, area);
-}
+        // Failed lookups stay cached rather than being retried
+        app.clear_search();
+        type_query(&mut app, "yay");
+        wait_for_search(&mut app);
+        app.poll_aur();
+        assert!(app.aur_in_flight.is_empty());
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        assert!(screen(&terminal).contains("AUR info unavailable"));
+    }
 
-fn draw_history_list(frame: &mut Frame, area: Rect, app: &mut App) {
-    let theme = &app.theme;
-    let visible_height = area.height.saturating_sub(2) as usize;
-    let records = app.history.recent(visible_height);
+    #[test]
+    fn test_aur_answers_cached_per_name() {
+        let mut app = test_app(&["paru"]);
+        let info = |name: &str| AurInfo {
+            name: name.to_string(),
+            num_votes: 1,
+            popularity: 0.0,
+            maintainer: None,
+            last_modified: 0,
+            out_of_date: None,
+        };
 
-    let items: Vec<ListItem> = records
-        .iter()
-        .enumerate()
-        .map(|(i, record)| {
-            let status_icon = if record.success {
-                Span::styled("✓", Style::default().fg(theme.success))
-            } else {
-                Span::styled("✗", Style::default().fg(theme.error))
-            };
+        // Names the AUR doesn't return are cached as unavailable
+        app.aur_tx
+            .send((vec!["paru".to_string(), "gone".to_string()], Some(vec![info("paru")])))
+            .unwrap();
+        app.poll_aur();
+        assert_eq!(app.aur_info.get("paru"), Some(&Some(info("paru"))));
+        assert_eq!(app.aur_info.get("gone"), Some(&None));
+        assert!(!app.aur_unreachable);
 
-            let style = if i == app.selected {
-                Style::default().bg(theme.highlight_bg).fg(theme.fg)
-            } else {
-                Style::default().fg(theme.fg)
-            };
+        // A failed request stops further lookups
+        app.aur_tx.send((vec!["yay".to_string()], None)).unwrap();
+        app.poll_aur();
+        assert_eq!(app.aur_info.get("yay"), Some(&None));
+        assert!(app.aur_unreachable);
+    }
 
-            ListItem::new(Line::from(vec![
-                status_icon,
-                Span::raw(" "),
-                Span::styled(&record.name, style),
-                Span::styled(format!(" ({})", record.formatted_time()), Style::default().fg(theme.muted)),
-            ]))
-        })
-        .collect();
+    #[test]
+    fn test_pkgbuild_preview() {
+        let mut app = test_app(&["htop"]);
+        type_query(&mut app, "htop");
+        wait_for_search(&mut app);
+        press(&mut app, KeyCode::F(6));
+        assert!(app.pkgbuild_loader.is_none());
+        assert!(app.status.contains("isn't from the AUR"));
 
-    let list = List::new(items)
-        .block(Block::default().title(format!(" History ({}) ", app.history.records.len())).borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
-        .highlight_symbol("➜ ");
+        let (tx, rx) = mpsc::channel();
+        app.pkgbuild_loader = Some(rx);
+        tx.send(("paru".to_string(), Ok("pkgname=paru\npkgver=2.0.3\n".to_string()))).unwrap();
+        app.poll_pkgbuild();
+        assert!(matches!(&app.overlay, Some(Overlay::Info { title, text, .. })
+            if title == "PKGBUILD of paru" && text.starts_with("pkgname=paru")));
 
-    frame.render_stateful_widget(list, area, &mut app.list_state);
-}
+        // A failed fetch is reported instead of opening anything
+        app.overlay = None;
+        let (tx, rx) = mpsc::channel();
+        app.pkgbuild_loader = Some(rx);
+        tx.send(("yay".to_string(), Err("offline".to_string()))).unwrap();
+        app.poll_pkgbuild();
+        assert!(app.overlay.is_none());
+        assert_eq!(app.status, "✗ Couldn't fetch the PKGBUILD of yay: offline");
+    }
 
-fn draw_history_detail(frame: &mut Frame, area: Rect, app: &App) {
-    let theme = &app.theme;
+    #[test]
+    fn test_mouse_selects_drawn_rows() {
+        use ratatui::backend::TestBackend;
 
-    let content = if let Some(record) = app.history.records.get(app.selected) {
-        let mut lines = vec![
-            Line::from(vec![
-                Span::styled("📦 ", Style::default()),
-                Span::styled(&record.name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
-            ]),
-            Line::from(""),
-            Line::from(vec![
-                Span::styled("Source: ", Style::default().fg(theme.muted)),
-                Span::styled(format!("{}", record.source), Style::default().fg(theme.fg)),
-            ]),
-            Line::from(vec![
-                Span::styled("Time: ", Style::default().fg(theme.muted)),
-                Span::styled(record.formatted_time(), Style::default().fg(theme.fg)),
-            ]),
-            Line::from(vec![
-                Span::styled("Status: ", Style::default().fg(theme.muted)),
-                if record.success {
-                    Span::styled("Success", Style::default().fg(theme.success))
-                } else {
-                    Span::styled("Failed", Style::default().fg(theme.error))
-                },
-            ]),
-        ];
+        let mut app = test_app(&["neofetch", "neovim", "htop"]);
+        app.config.confirm_install = false;
+        type_query(&mut app, "ne");
+        wait_for_search(&mut app);
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
 
-        if let Some(ref error) = record.error {
-            lines.push(Line::from(""));
-            lines.push(Line::from(Span::styled("Error:", Style::default().fg(theme.error))));
-            lines.push(Line::from(Span::styled(error, Style::default().fg(theme.muted))));
-        }
+        let click = |app: &mut App, column, row| {
+            handle_mouse(app, MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            });
+        };
+        // The header takes three rows and the list's border one more
+        click(&mut app, 5, 5);
+        assert_eq!(app.selected, 1);
+        click(&mut app, 5, 9);
+        assert_eq!(app.selected, 1, "clicks below the last entry are ignored");
+        assert_eq!(app.pending_action, None);
 
-        lines
-    } else {
-        vec![Line::from(Span::styled("No history selected", Style::default().fg(theme.muted)))]
-    };
+        handle_mouse(&mut app, MouseEvent {
+            kind: MouseEventKind::ScrollUp,
+            column: 60,
+            row: 10,
+            modifiers: KeyModifiers::NONE,
+        });
+        assert_eq!(app.selected, 0);
 
-    let preview = Paragraph::new(content)
-        .block(Block::default().title(" Details ").borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
-        .wrap(Wrap { trim: true });
+        click(&mut app, 5, 4);
+        click(&mut app, 5, 4);
+        assert!(matches!(app.pending_action, Some(PendingAction::Install { .. })));
+    }
 
-    frame.render_widget(preview, area);
-}
+    #[test]
+    fn test_mouse_skips_history_day_rows() {
+        use ratatui::backend::TestBackend;
 
-#[cfg(feature = "terraflow")]
-fn draw_audit_list(frame: &mut Frame, area: Rect, app: &mut App) {
-    let theme = &app.theme;
+        let mut app = test_app(&[]);
+        let at = |name, timestamp| InstallRecord {
+            timestamp,
+            ..InstallRecord::success(name, PackageSource::Official)
+        };
+        app.history.add(at("old", 1_709_200_000));
+        app.history.add(at("new", 1_709_300_000));
+        app.set_mode(AppMode::History);
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
 
-    let items: Vec<ListItem> = if let Some(ref result) = app.audit_result {
-        result.missing.iter().enumerate().map(|(i, pkg)| {
-            let source_tag = match pkg.source {
-                PackageSource::Official => Span::styled("[OFF]", Style::default().fg(theme.accent)),
-                PackageSource::Aur => Span::styled("[AUR]", Style::default().fg(theme.secondary)),
-            };
+        // Rows: day, new, day, old
+        assert_eq!(app.entry_at(5, 4), None);
+        assert_eq!(app.entry_at(5, 7), Some(1));
+        app.click(5, 7);
+        assert_eq!(app.selected, 1);
+    }
 
-            let style = if i == app.selected {
-                Style::default().bg(theme.highlight_bg).fg(theme.fg)
-            } else {
-                Style::default().fg(theme.fg)
-            };
+    #[test]
+    fn test_history_navigation_at_end_of_long_history() {
+        use ratatui::backend::TestBackend;
 
-            ListItem::new(Line::from(vec![
-                source_tag,
-                Span::raw(" "),
-                Span::styled(&pkg.name, style),
-            ]))
-        }).collect()
-    } else {
-        vec![ListItem::new(Line::from(Span::styled("No audit data", Style::default().fg(theme.muted))))]
-    };
+        let mut app = test_app(&[]);
+        for i in 0..60 {
+            app.history.add(InstallRecord {
+                timestamp: 1_709_200_000 + i,
+                ..InstallRecord::success(format!("pkg{:02}", i), PackageSource::Official)
+            });
+        }
+        app.set_mode(AppMode::History);
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
 
-    let title = if let Some(ref result) = app.audit_result {
-        format!(" Missing ({}) ", result.missing.len())
-    } else {
-        " Audit ".to_string()
-    };
+        handle_key(&mut app, KeyEvent::new(KeyCode::End, KeyModifiers::CONTROL));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::PageDown);
+        assert_eq!(app.selected, 59);
+        assert_eq!(app.selected_history_record().unwrap().name, "pkg00");
 
-    let list = List::new(items)
-        .block(Block::default().title(title).borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
-        .highlight_symbol("➜ ");
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        // The oldest record is drawn on the list's last row, selected
+        let buffer = terminal.backend().buffer();
+        let row: String = (1..20).map(|x| buffer[(x, 19)].symbol().to_string()).collect();
+        assert!(row.starts_with("➜ ✓ pkg00"), "{row:?}");
 
-    frame.render_stateful_widget(list, area, &mut app.list_state);
-}
+        press(&mut app, KeyCode::PageUp);
+        assert_eq!(app.selected_history_record().unwrap().name, "pkg10");
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        assert_eq!(app.list_state.selected(), Some(49));
+    }
 
-#[cfg(feature = "terraflow")]
-fn draw_audit_detail(frame: &mut Frame, area: Rect, app: &App) {
-    let theme = &app.theme;
+    #[test]
+    fn test_history_rows_group_by_day() {
+        let at = |timestamp| InstallRecord {
+            timestamp,
+            ..InstallRecord::success("htop", PackageSource::Official)
+        };
+        let records = [at(1_709_300_000), at(1_709_217_000), at(1_709_200_000)];
 
-    let content = if let Some(ref result) = app.audit_result {
-        if let Some(pkg) = result.missing.get(app.selected) {
-            vec![
-                Line::from(vec![
-                    Span::styled("📦 ", Style::default()),
-                    Span::styled(&pkg.name, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
-                ]),
-                Line::from(""),
-                Line::from(vec![
-                    Span::styled("Source: ", Style::default().fg(theme.muted)),
-                    Span::styled(format!("{}", pkg.source), Style::default().fg(theme.fg)),
-                ]),
-                Line::from(vec![
-                    Span::styled("Config: ", Style::default().fg(theme.muted)),
-                    Span::styled(&pkg.file, Style::default().fg(theme.fg)),
-                ]),
-                Line::from(""),
-                Line::from(Span::styled("This package is in your config but not installed.", Style::default().fg(theme.error))),
-            ]
-        } else {
+        assert_eq!(
+            history_rows(&records),
             vec![
-                Line::from(Span::styled("Audit Summary", Style::default().fg(theme.fg).add_modifier(Modifier::BOLD))),
-                Line::from(""),
-                Line::from(vec![
-                    Span::styled("Config packages: ", Style::default().fg(theme.muted)),
-                    Span::styled(format!("{}", result.config_count), Style::default().fg(theme.fg)),
-                ]),
-                Line::from(vec![
-                    Span::styled("Installed: ", Style::default().fg(theme.muted)),
-                    Span::styled(format!("{}", result.installed_count), Style::default().fg(theme.fg)),
-                ]),
-                Line::from(vec![
-                    Span::styled("Missing: ", Style::default().fg(theme.muted)),
-                    Span::styled(format!("{}", result.missing.len()), Style::default().fg(theme.error)),
-                ]),
+                HistoryRow::Day("2024-03-01".to_string()),
+                HistoryRow::Record(0),
+                HistoryRow::Day("2024-02-29".to_string()),
+                HistoryRow::Record(1),
+                HistoryRow::Record(2),
             ]
-        }
-    } else {
-        vec![
-            Line::from(Span::styled("TerraFlow not configured", Style::default().fg(theme.muted))),
-            Line::from(""),
-            Line::from(Span::styled("Place package lists in:", Style::default().fg(theme.fg))),
-            Line::from(Span::styled("  ~/TerraFlow-Dotfiles/packages/", Style::default().fg(theme.accent))),
-        ]
-    };
+        );
+    }
 
-    let preview = Paragraph::new(content)
-        .block(Block::default().title(" Details ").borders(Borders::ALL).border_style(Style::default().fg(theme.border)))
-        .wrap(Wrap { trim: true });
+    #[test]
+    #[cfg(feature = "terraflow")]
+    fn test_audit_extra_view() {
+        let mut app = test_app(&["neofetch"]);
+        app.set_mode(AppMode::Audit);
+        app.audit_result = Some(AuditResult {
+            missing: vec![crate::terraflow::PackageEntry {
+                name: "neovim".to_string(),
+                source: PackageSource::Official,
+                file: "base.txt".to_string(),
+            }],
+            extra: vec!["htop".to_string(), "steam".to_string()],
+            config_count: 1,
+            installed_count: 2,
+        });
 
-    frame.render_widget(preview, area);
-}
+        press(&mut app, KeyCode::End);
+        assert_eq!(app.selected, 0);
 
-fn draw_footer(frame: &mut Frame, area: Rect, app: &App) {
-    let theme = &app.theme;
+        // Tab switches lists, each with its own length
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.audit_view, AuditView::Extra);
+        press(&mut app, KeyCode::End);
+        assert_eq!(app.selected, 1);
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.audit_view, AuditView::Missing);
+        assert_eq!(app.selected, 0);
+    }
 
-    let keybindings = match app.mode {
-        AppMode::Search => vec![
-            Span::styled(" ↑↓", Style::default().fg(theme.accent)),
-            Span::styled(" Nav ", Style::default().fg(theme.muted)),
-            Span::styled("Enter", Style::default().fg(theme.accent)),
-            Span::styled(" Install ", Style::default().fg(theme.muted)),
-            Span::styled("Tab", Style::default().fg(theme.accent)),
-            Span::styled(" Source ", Style::default().fg(theme.muted)),
-            Span::styled("1-3", Style::default().fg(theme.accent)),
-            Span::styled(" Mode ", Style::default().fg(theme.muted)),
-            Span::styled("Esc", Style::default().fg(theme.accent)),
-            Span::styled(" Quit", Style::default().fg(theme.muted)),
-        ],
-        AppMode::Universal => vec![
-            Span::styled(" ↑↓", Style::default().fg(theme.accent)),
-            Span::styled(" Nav ", Style::default().fg(theme.muted)),
-            Span::styled("Enter", Style::default().fg(theme.accent)),
-            Span::styled(" Install ", Style::default().fg(theme.muted)),
-            Span::styled("F2", Style::default().fg(theme.accent)),
-            Span::styled(" Reload ", Style::default().fg(theme.muted)),
-            Span::styled("Esc", Style::default().fg(theme.accent)),
-            Span::styled(" Quit", Style::default().fg(theme.muted)),
-        ],
-        AppMode::History => vec![
-            Span::styled(" ↑↓", Style::default().fg(theme.accent)),
-            Span::styled(" Nav ", Style::default().fg(theme.muted)),
-            Span::styled("1-3", Style::default().fg(theme.accent)),
-            Span::styled(" Mode ", Style::default().fg(theme.muted)),
-            Span::styled("Esc", Style::default().fg(theme.accent)),
-            Span::styled(" Quit", Style::default().fg(theme.muted)),
-        ],
-        #[cfg(feature = "terraflow")]
-        AppMode::Audit => vec![
-            Span::styled(" ↑↓", Style::default().fg(theme.accent)),
-            Span::styled(" Nav ", Style::default().fg(theme.muted)),
-            Span::styled("1-3", Style::default().fg(theme.accent)),
-            Span::styled(" Mode ", Style::default().fg(theme.muted)),
-            Span::styled("Esc", Style::default().fg(theme.accent)),
-            Span::styled(" Quit", Style::default().fg(theme.muted)),
-        ],
-    };
+    #[test]
+    #[cfg(feature = "terraflow")]
+    fn test_install_missing() {
+        let mut app = test_app(&["neofetch"]);
+        app.set_mode(AppMode::Audit);
+        let ctrl_x = KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL);
 
-    let status_style = if app.status.contains("µs") || app.status.contains("ms") {
-        Style::default().fg(theme.success)
-    } else if app.status.starts_with('✗') {
-        Style::default().fg(theme.error)
-    } else {
-        Style::default().fg(theme.muted)
-    };
+        handle_key(&mut app, ctrl_x);
+        assert!(app.overlay.is_none());
 
-    let footer_block = Block::default().borders(Borders::ALL).border_style(Style::default().fg(theme.border));
-    let footer_layout = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
-        .split(footer_block.inner(area));
+        let entry = |name: &str, source| crate::terraflow::PackageEntry {
+            name: name.to_string(),
+            source,
+            file: "packages.txt".to_string(),
+        };
+        app.audit_result = Some(AuditResult {
+            missing: vec![entry("neovim", PackageSource::Official), entry("paru-bin", PackageSource::Aur)],
+            ..AuditResult::default()
+        });
 
-    frame.render_widget(footer_block, area);
-    frame.render_widget(Paragraph::new(Line::from(keybindings)), footer_layout[0]);
-    frame.render_widget(Paragraph::new(Span::styled(&app.status, status_style)), footer_layout[1]);
-}
+        // Even a single missing package asks first
+        handle_key(&mut app, ctrl_x);
+        press(&mut app, KeyCode::Char('y'));
+        assert_eq!(
+            app.pending_action,
+            Some(PendingAction::InstallBatch {
+                packages: vec![
+                    ("neovim".to_string(), PackageSource::Official),
+                    ("paru-bin".to_string(), PackageSource::Aur),
+                ],
+            })
+        );
+    }
 
-/// Handle keyboard input
-pub fn handle_input(app: &mut App) -> io::Result<bool> {
-    if event::poll(Duration::from_millis(16))? {
-        if let Event::Key(key) = event::read()? {
-            if key.kind != KeyEventKind::Press {
-                return Ok(false);
-            }
+    #[test]
+    #[cfg(feature = "terraflow")]
+    fn test_sync_plan_removals_are_opt_in() {
+        let mut app = test_app(&["neofetch"]);
+        let plan = SyncPlan {
+            install: vec![crate::terraflow::PackageEntry {
+                name: "neovim".to_string(),
+                source: PackageSource::Official,
+                file: "base.txt".to_string(),
+            }],
+            remove: vec![crate::terraflow::Removal {
+                name: "htop".to_string(),
+                source: PackageSource::Official,
+                required_by: Vec::new(),
+            }],
+        };
+        let open = |app: &mut App| {
+            app.overlay = Some(Overlay::SyncPlan {
+                plan: plan.clone(),
+                remove: false,
+                scroll: 0,
+            })
+        };
+        let install = vec![("neovim".to_string(), PackageSource::Official)];
 
-            match key.code {
-                KeyCode::Esc => {
-                    app.should_quit = true;
-                    return Ok(true);
-                }
-                KeyCode::Char('1') => app.set_mode(AppMode::Search),
-                KeyCode::F(2) => app.set_mode(AppMode::Universal),
-                KeyCode::Char('2') => app.set_mode(AppMode::History),
-                #[cfg(feature = "terraflow")]
-                KeyCode::Char('3') => app.set_mode(AppMode::Audit),
-                KeyCode::Up => app.select_previous(),
-                KeyCode::Down => app.select_next(),
-                KeyCode::PageUp => app.page_up(),
-                KeyCode::PageDown => app.page_down(),
-                KeyCode::Tab if app.mode == AppMode::Search => app.toggle_source(),
-                KeyCode::F(5) if app.mode == AppMode::Search => app.refresh_database(),
-                KeyCode::Enter if app.mode == AppMode::Search => {
-                    if app.selected_package().is_some() {
-                        return Ok(true);
-                    }
-                }
-                KeyCode::Backspace if app.mode == AppMode::Search => {
-                    app.query.pop();
-                    app.search();
-                }
-                KeyCode::Backspace if app.mode == AppMode::Universal => {
-                    app.query.pop();
-                    app.search_flatpak();
-                }
-                KeyCode::Char(c) if app.mode == AppMode::Search => {
-                    app.query.push(c);
-                    app.search();
-                }
-                KeyCode::Char(c) if app.mode == AppMode::Universal => {
-                    app.query.push(c);
-                    app.search_flatpak();
-                }
-                _ => {}
+        open(&mut app);
+        press(&mut app, KeyCode::Char('y'));
+        assert_eq!(
+            app.pending_action,
+            Some(PendingAction::Sync { install: install.clone(), remove: Vec::new() })
+        );
+
+        open(&mut app);
+        press(&mut app, KeyCode::Char('r'));
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(
+            app.pending_action,
+            Some(PendingAction::Sync { install, remove: vec![("htop".to_string(), PackageSource::Official)] })
+        );
+    }
+
+    #[test]
+    fn test_history_filter() {
+        let mut app = test_app(&["neofetch"]);
+        app.history.add(crate::history::InstallRecord::success("neovim", PackageSource::Official));
+        app.history.add(crate::history::InstallRecord::failure("htop", PackageSource::Official, "exit 1"));
+        app.history.add(crate::history::InstallRecord::success("neofetch", PackageSource::Official));
+        app.history.add(crate::history::InstallRecord::failure("neo-matrix", PackageSource::Aur, "exit 1"));
+
+        app.set_mode(AppMode::History);
+        type_query(&mut app, "neo");
+        assert_eq!(app.history_filter, "neo");
+        assert!(app.query.is_empty());
+        assert_eq!(app.history_view(), vec![0, 1, 3]);
+
+        // Navigation stays within the filtered records
+        for _ in 0..3 {
+            press(&mut app, KeyCode::Down);
+        }
+        assert_eq!(app.selected, 2);
+        handle_key(&mut app, KeyEvent::new(KeyCode::Home, KeyModifiers::CONTROL));
+        handle_key(&mut app, KeyEvent::new(KeyCode::End, KeyModifiers::CONTROL));
+        assert_eq!(app.selected, 2);
+
+        // Tab cycles success-only, failure-only and back to all
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.history_status, StatusFilter::Success);
+        assert_eq!(app.selected, 0);
+        assert_eq!(app.history_view(), vec![1, 3]);
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.history_view(), vec![0]);
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.history_status, StatusFilter::All);
+
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert_eq!(app.history_view().len(), 4);
+        assert_eq!(history_title(1, 4), " History (1/4) ");
+    }
+
+    #[test]
+    fn test_install_asks_first_unless_disabled() {
+        let mut app = test_app(&["neofetch", "neovim"]);
+        app.results = vec![0, 1];
+
+        press(&mut app, KeyCode::Enter);
+        match &app.overlay {
+            Some(Overlay::ConfirmInstall { name, source, .. }) => {
+                assert_eq!(name, "neofetch");
+                assert_eq!(*source, PackageSource::Official);
             }
+            other => panic!("expected install confirmation, got {:?}", other),
+        }
+        press(&mut app, KeyCode::Char('n'));
+        assert!(app.overlay.is_none());
+        assert!(app.pending_action.is_none());
+
+        app.config.confirm_install = false;
+        press(&mut app, KeyCode::Enter);
+        assert!(app.overlay.is_none());
+        assert!(matches!(app.pending_action, Some(PendingAction::Install { .. })));
+    }
+
+    #[test]
+    fn test_toggle_mark_queues_selection() {
+        let mut app = test_app(&["neofetch", "neovim", "htop"]);
+        app.results = vec![0, 1, 2];
+
+        // Ctrl+Space marks and advances
+        let ctrl_space = KeyEvent::new(KeyCode::Char(' '), KeyModifiers::CONTROL);
+        handle_key(&mut app, ctrl_space);
+        handle_key(&mut app, ctrl_space);
+        assert_eq!(app.selected_set, HashSet::from([0, 1]));
+        assert_eq!(app.selected, 2);
+
+        press(&mut app, KeyCode::Up);
+        press(&mut app, KeyCode::Insert);
+        assert_eq!(app.selected_set, HashSet::from([0]));
+        assert!(app.query.is_empty());
+    }
+
+    #[test]
+    fn test_marked_size_totals() {
+        let mut app = test_app(&["neofetch", "neovim", "htop"]);
+        app.selected_set = HashSet::from([0, 1, 2]);
+        let sized = |name: &str, download_size, installed_size| PackageInfo {
+            name: name.to_string(),
+            download_size,
+            installed_size,
+            ..PackageInfo::default()
+        };
+        app.details.insert("neofetch".to_string(), Some(sized("neofetch", 100, 400)));
+        app.details.insert("neovim".to_string(), Some(sized("neovim", 2_000, 9_000)));
+        app.details.insert("htop".to_string(), None);
+
+        // htop's size couldn't be fetched, so the totals are a lower bound
+        assert_eq!(app.marked_sizes(), (2_100, 9_400, 1));
+
+        // Cached packages are never fetched again
+        app.poll_details();
+        assert!(app.size_requests.is_empty());
+    }
+
+    #[test]
+    fn test_upgrade_from_any_mode() {
+        let mut app = test_app(&["neofetch"]);
+        app.set_mode(AppMode::History);
+
+        press(&mut app, KeyCode::F(9));
+        assert_eq!(app.pending_action, Some(PendingAction::Upgrade));
+    }
+
+    #[test]
+    fn test_only_native_actions_need_root() {
+        assert!(PendingAction::Upgrade.needs_root());
+        assert!(PendingAction::Remove { name: "htop".to_string(), source: PackageSource::Aur }.needs_root());
+        assert!(!PendingAction::InstallFlatpak {
+            app_id: "org.gimp.GIMP".to_string(),
+            name: "GIMP".to_string(),
+            remote: "flathub".to_string(),
         }
+        .needs_root());
+        assert!(!PendingAction::InstallBatch { packages: vec![("org.gimp.GIMP".to_string(), PackageSource::Flatpak)] }.needs_root());
     }
 
-    Ok(false)
+    #[test]
+    fn test_show_updates() {
+        let mut app = test_app(&["neofetch"]);
+        press(&mut app, KeyCode::F(8));
+        assert!(app.overlay.is_none());
+
+        app.updates = Some(vec![PackageUpdate {
+            name: "linux".to_string(),
+            old_version: "6.7.1-1".to_string(),
+            new_version: "6.7.2-1".to_string(),
+            source: PackageSource::Official,
+        }]);
+        press(&mut app, KeyCode::F(8));
+        match &app.overlay {
+            Some(Overlay::Info { title, text, .. }) => {
+                assert_eq!(title, "Updates (1)");
+                assert_eq!(text, "[Official] linux 6.7.1-1 -> 6.7.2-1");
+            }
+            other => panic!("expected update list, got {:?}", other),
+        }
+    }
 }